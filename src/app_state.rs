@@ -3,6 +3,11 @@
 use deadpool_postgres::Pool;
 use std::sync::Arc;
 use uuid::Uuid;
+use crate::config::AppConfig;
+use crate::services::jwt_service::KeyRing;
+use crate::services::mailer::Mailer;
+use crate::services::session_cache::SessionCache;
+use crate::utils::password_validator::PasswordValidator;
 use crate::websocket::connection_manager::ConnectionManager;
 
 /// Application state containing shared resources
@@ -12,8 +17,20 @@ pub struct AppState {
     pub connections: ConnectionManager,
     /// Database connection pool wrapped in Arc for thread-safe sharing
     pub db: Pool,
+    /// Mailer used for transactional emails (password resets, etc.)
+    pub mailer: Arc<dyn Mailer>,
+    /// Application settings loaded at startup
+    pub config: Arc<AppConfig>,
+    /// Password policy built from the configuration
+    pub password_validator: Arc<PasswordValidator>,
+    /// Keys used to sign and verify JWTs
+    pub keys: Arc<KeyRing>,
+    /// Sessions recently confirmed active, shared by the authentication middleware
+    pub sessions: Arc<SessionCache>,
     /// Optional ID of the currently authenticated user
     pub current_user_id: Option<Uuid>,
+    /// Optional ID of the session the current user authenticated with
+    pub current_session_id: Option<Uuid>,
 }
 
 impl AppState {
@@ -22,14 +39,33 @@ impl AppState {
     /// # Arguments
     /// * `db` - Arc-wrapped database connection pool
     /// * `connections` - WebSocket connection manager
+    /// * `mailer` - Email delivery backend
+    /// * `config` - Application settings
+    /// * `password_validator` - Configured password policy
+    /// * `keys` - JWT signing and verification keys
+    /// * `sessions` - Cache of active sessions
     /// 
     /// # Returns
     /// * `Self` - New AppState instance
-    pub fn new(db: Pool, connections: ConnectionManager) -> Self {
+    pub fn new(
+        db: Pool,
+        connections: ConnectionManager,
+        mailer: Arc<dyn Mailer>,
+        config: Arc<AppConfig>,
+        password_validator: Arc<PasswordValidator>,
+        keys: Arc<KeyRing>,
+        sessions: Arc<SessionCache>,
+    ) -> Self {
         Self {
             connections,
             db,
+            mailer,
+            config,
+            password_validator,
+            keys,
+            sessions,
             current_user_id: None,
+            current_session_id: None,
         }
     }
 }
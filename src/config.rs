@@ -0,0 +1,334 @@
+// config.rs
+
+use dotenv::dotenv;
+use std::{env, str::FromStr};
+use thiserror::Error;
+
+/// Configuration-related error types
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    /// Error when an environment variable holds a value of the wrong type
+    #[error("Invalid value for {0}: {1}")]
+    InvalidValue(String, String),
+}
+
+/// Application settings loaded once at startup
+#[derive(Debug, Clone)]
+pub struct AppConfig {
+    /// Port the HTTP server listens on
+    pub port: u16,
+    /// Email verification policy
+    pub email_verification: EmailVerificationConfig,
+    /// Two-factor authentication settings
+    pub two_factor: TwoFactorConfig,
+    /// Brute-force protection for the login endpoint
+    pub login_protection: LoginProtectionConfig,
+    /// Limits on endpoints that send emails on request
+    pub email_throttle: EmailThrottleConfig,
+    /// Password strength policy
+    pub password_policy: PasswordPolicyConfig,
+    /// JWT signing and verification keys
+    pub jwt: JwtConfig,
+    /// Account deletion policy
+    pub account_deletion: AccountDeletionConfig,
+    pub presence: PresenceConfig,
+    pub websocket: WebSocketConfig,
+    /// Draining of connections when the server stops
+    pub shutdown: ShutdownConfig,
+    /// Sharing of WebSocket events between instances
+    pub pubsub: PubSubConfig,
+    /// Delivery of outgoing emails and the links they contain
+    pub mailer: MailerConfig,
+}
+
+/// Controls how email verification is enforced
+#[derive(Debug, Clone)]
+pub struct EmailVerificationConfig {
+    /// Whether users must verify their email before logging in
+    pub required_for_login: bool,
+    /// Whether users must verify their email before creating chats
+    pub required_for_chat_creation: bool,
+    /// How long a verification link stays valid, in hours
+    pub token_ttl_hours: i64,
+    /// Minimum delay between two verification emails for the same user, in seconds
+    pub resend_interval_seconds: i64,
+}
+
+/// Controls TOTP enrolment and the two-step login
+#[derive(Debug, Clone)]
+pub struct TwoFactorConfig {
+    /// Issuer name shown by authenticator apps
+    pub issuer: String,
+    /// How long a login challenge stays valid, in seconds
+    pub challenge_ttl_seconds: i64,
+    /// Number of codes that may be tried against one login challenge
+    pub max_challenge_attempts: i32,
+    /// Whether users must enable 2FA before they can use chats, WebSockets and event streams
+    pub required_for_chats: bool,
+}
+
+/// Controls how failed logins are throttled
+#[derive(Debug, Clone)]
+pub struct LoginProtectionConfig {
+    /// Failed attempts allowed for one username before it is locked
+    pub max_failures_per_username: i32,
+    /// Failed attempts allowed from one IP address before it is locked
+    pub max_failures_per_ip: i32,
+    /// Length of the first lockout, in seconds; it doubles with every further failure
+    pub base_lockout_seconds: i64,
+    /// Upper bound for a single lockout, in seconds
+    pub max_lockout_seconds: i64,
+    /// Quiet period after which the failure counter starts over, in seconds
+    pub failure_window_seconds: i64,
+    /// Whether to take the client IP from the X-Forwarded-For header (only behind a trusted proxy)
+    pub trust_forwarded_for: bool,
+    /// Number of trusted proxies in front of the server, each appending to X-Forwarded-For
+    pub trusted_proxies: usize,
+}
+
+/// Limits how often anonymous endpoints may send emails, per target email and per client IP
+#[derive(Debug, Clone)]
+pub struct EmailThrottleConfig {
+    /// Requests allowed for one email address per window
+    pub max_requests_per_email: i32,
+    /// Requests allowed from one IP address per window
+    pub max_requests_per_ip: i32,
+    /// Length of the counting window, in seconds
+    pub window_seconds: i64,
+}
+
+/// Rules a password must satisfy
+#[derive(Debug, Clone)]
+pub struct PasswordPolicyConfig {
+    /// Minimum number of characters
+    pub min_length: usize,
+    /// Whether an uppercase letter is required
+    pub require_uppercase: bool,
+    /// Whether a lowercase letter is required
+    pub require_lowercase: bool,
+    /// Whether a digit is required
+    pub require_number: bool,
+    /// Whether a special character is required
+    pub require_special_char: bool,
+    /// Optional file of common passwords to reject, one per line
+    pub denylist_file: Option<String>,
+}
+
+/// Keys used to sign and verify JWTs
+#[derive(Debug, Clone)]
+pub struct JwtConfig {
+    /// Signing algorithm: HS256, RS256 or EdDSA
+    pub algorithm: String,
+    /// Key ID stamped into the `kid` header of issued tokens
+    pub key_id: String,
+    /// Shared secret for HS256
+    pub secret: Option<String>,
+    /// PEM private key for RS256 or EdDSA
+    pub private_key_file: Option<String>,
+    /// PEM public key matching `private_key_file`
+    pub public_key_file: Option<String>,
+    /// Retired keys still accepted for verification, as comma-separated `kid:ALG:path` entries
+    pub verification_keys: Option<String>,
+    /// Value of the `iss` claim, checked on every token
+    pub issuer: String,
+    /// Value of the `aud` claim, checked on every token
+    pub audience: String,
+    /// Lifetime of an issued token, in seconds
+    pub token_ttl_seconds: i64,
+    /// How long a session found active is trusted without checking the database again, in seconds
+    pub session_cache_ttl_seconds: u64,
+}
+
+/// Controls how account deletion requests are carried out
+#[derive(Debug, Clone)]
+pub struct AccountDeletionConfig {
+    /// Delay between a deletion request and the actual deletion, in hours (0 deletes immediately)
+    pub grace_period_hours: i64,
+    /// How often accounts whose grace period has ended are purged, in seconds
+    pub purge_interval_seconds: u64,
+}
+
+/// Controls how user presence is derived from WebSocket activity
+#[derive(Debug, Clone)]
+pub struct PresenceConfig {
+    /// Inactivity after which an online user is shown as idle, in seconds
+    pub idle_after_seconds: i64,
+    /// How often connections are checked for inactivity, in seconds
+    pub sweep_interval_seconds: u64,
+    /// Time after which the presence recorded by a node that stopped refreshing it is dropped,
+    /// in seconds; must exceed `sweep_interval_seconds`, at which nodes refresh it
+    pub node_timeout_seconds: i64,
+}
+
+/// Controls WebSocket heartbeats and the reaping of dead connections
+#[derive(Debug, Clone)]
+pub struct WebSocketConfig {
+    /// Delay between two pings sent to each client, in seconds
+    pub ping_interval_seconds: u64,
+    /// Number of consecutive unanswered pings after which a connection is closed
+    pub max_missed_pongs: u32,
+    /// Silence after which the sweeper evicts a connection from its chat, in seconds
+    pub stale_after_seconds: i64,
+    /// How often the sweeper looks for stale connections, in seconds
+    pub sweep_interval_seconds: u64,
+    /// Number of messages buffered per chat and per user before slow clients start missing some
+    pub channel_capacity: usize,
+    /// Number of lag events within `lag_window_seconds` after which a client is disconnected
+    pub max_lag_events: usize,
+    /// Window over which lag events are counted, in seconds
+    pub lag_window_seconds: u64,
+    /// How long a new connection may take to send its hello before it is treated as a version 1 client, in seconds
+    pub hello_timeout_seconds: u64,
+    /// Most messages replayed to a resuming client; older missed messages are reported as a gap
+    pub max_replay_messages: i64,
+}
+
+/// Selects how WebSocket events reach the other instances
+#[derive(Debug, Clone)]
+pub struct PubSubConfig {
+    /// `memory` for a single instance, `postgres` for several
+    pub backend: String,
+    /// Database the `postgres` backend listens on for the events of the other instances
+    pub database_url: Option<String>,
+}
+
+/// Selects how outgoing emails are delivered and where their links point
+#[derive(Debug, Clone)]
+pub struct MailerConfig {
+    /// `smtp`, `file` or `log`
+    pub backend: String,
+    /// Sender address
+    pub from: String,
+    /// SMTP server used by the `smtp` backend
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    /// Optional SMTP credentials
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    /// Directory the `file` backend writes emails to
+    pub outbox_dir: String,
+    /// Public base URL of the server, used for links to its own endpoints
+    pub app_base_url: String,
+    /// Client page that lets users enter a new password; the reset token is appended as `?token=`
+    pub password_reset_url: Option<String>,
+}
+
+/// Controls how connections are drained when the server stops
+#[derive(Debug, Clone)]
+pub struct ShutdownConfig {
+    /// Longest wait for open requests and connections to finish before exiting, in seconds
+    pub drain_timeout_seconds: u64,
+    /// Clients are told to reconnect after a random delay up to this long, in seconds, so they
+    /// do not all come back at once
+    pub reconnect_window_seconds: u64,
+}
+
+impl AppConfig {
+    /// Loads the configuration from environment variables, falling back to defaults
+    ///
+    /// # Returns
+    /// * `Result<AppConfig, ConfigError>` - The loaded configuration or an error
+    pub fn from_env() -> Result<Self, ConfigError> {
+        dotenv().ok();
+
+        Ok(Self {
+            port: env_or("PORT", 3000)?,
+            email_verification: EmailVerificationConfig {
+                required_for_login: env_or("EMAIL_VERIFICATION_REQUIRED_FOR_LOGIN", false)?,
+                required_for_chat_creation: env_or("EMAIL_VERIFICATION_REQUIRED_FOR_CHATS", false)?,
+                token_ttl_hours: env_or("EMAIL_VERIFICATION_TTL_HOURS", 24)?,
+                resend_interval_seconds: env_or("EMAIL_VERIFICATION_RESEND_INTERVAL_SECS", 60)?,
+            },
+            two_factor: TwoFactorConfig {
+                issuer: env_or("TOTP_ISSUER", "rustle_chat".to_string())?,
+                challenge_ttl_seconds: env_or("TWO_FACTOR_CHALLENGE_TTL_SECS", 300)?,
+                max_challenge_attempts: env_or("TWO_FACTOR_MAX_ATTEMPTS", 5)?,
+                required_for_chats: env_or("REQUIRE_TWO_FACTOR", false)?,
+            },
+            login_protection: LoginProtectionConfig {
+                max_failures_per_username: env_or("LOGIN_MAX_FAILURES_PER_USERNAME", 5)?,
+                max_failures_per_ip: env_or("LOGIN_MAX_FAILURES_PER_IP", 20)?,
+                base_lockout_seconds: env_or("LOGIN_BASE_LOCKOUT_SECS", 30)?,
+                max_lockout_seconds: env_or("LOGIN_MAX_LOCKOUT_SECS", 900)?,
+                failure_window_seconds: env_or("LOGIN_FAILURE_WINDOW_SECS", 900)?,
+                trust_forwarded_for: env_or("TRUST_X_FORWARDED_FOR", false)?,
+                trusted_proxies: env_or("TRUSTED_PROXIES", 1)?,
+            },
+            email_throttle: EmailThrottleConfig {
+                max_requests_per_email: env_or("EMAIL_THROTTLE_MAX_PER_EMAIL", 3)?,
+                max_requests_per_ip: env_or("EMAIL_THROTTLE_MAX_PER_IP", 10)?,
+                window_seconds: env_or("EMAIL_THROTTLE_WINDOW_SECS", 3600)?,
+            },
+            password_policy: PasswordPolicyConfig {
+                min_length: env_or("PASSWORD_MIN_LENGTH", 8)?,
+                require_uppercase: env_or("PASSWORD_REQUIRE_UPPERCASE", true)?,
+                require_lowercase: env_or("PASSWORD_REQUIRE_LOWERCASE", true)?,
+                require_number: env_or("PASSWORD_REQUIRE_NUMBER", true)?,
+                require_special_char: env_or("PASSWORD_REQUIRE_SPECIAL_CHAR", true)?,
+                denylist_file: env::var("PASSWORD_DENYLIST_FILE").ok(),
+            },
+            jwt: JwtConfig {
+                algorithm: env_or("JWT_ALGORITHM", "HS256".to_string())?,
+                key_id: env_or("JWT_KEY_ID", "default".to_string())?,
+                secret: env::var("JWT_SECRET_KEY").ok(),
+                private_key_file: env::var("JWT_PRIVATE_KEY_FILE").ok(),
+                public_key_file: env::var("JWT_PUBLIC_KEY_FILE").ok(),
+                verification_keys: env::var("JWT_VERIFICATION_KEYS").ok(),
+                issuer: env_or("JWT_ISSUER", "rustle_chat".to_string())?,
+                audience: env_or("JWT_AUDIENCE", "rustle_chat".to_string())?,
+                token_ttl_seconds: env_or("JWT_TTL_SECS", 86400)?,
+                session_cache_ttl_seconds: env_or("SESSION_CACHE_TTL_SECS", 30)?,
+            },
+            account_deletion: AccountDeletionConfig {
+                grace_period_hours: env_or("ACCOUNT_DELETION_GRACE_PERIOD_HOURS", 168)?,
+                purge_interval_seconds: env_or("ACCOUNT_PURGE_INTERVAL_SECS", 3600)?,
+            },
+            presence: PresenceConfig {
+                idle_after_seconds: env_or("PRESENCE_IDLE_AFTER_SECS", 300)?,
+                sweep_interval_seconds: env_or("PRESENCE_SWEEP_INTERVAL_SECS", 30)?,
+                node_timeout_seconds: env_or("PRESENCE_NODE_TIMEOUT_SECS", 120)?,
+            },
+            websocket: WebSocketConfig {
+                ping_interval_seconds: env_or("WS_PING_INTERVAL_SECS", 30)?,
+                max_missed_pongs: env_or("WS_MAX_MISSED_PONGS", 2)?,
+                stale_after_seconds: env_or("WS_STALE_AFTER_SECS", 120)?,
+                sweep_interval_seconds: env_or("WS_SWEEP_INTERVAL_SECS", 60)?,
+                channel_capacity: env_or("WS_CHANNEL_CAPACITY", 100)?,
+                max_lag_events: env_or("WS_MAX_LAG_EVENTS", 3)?,
+                lag_window_seconds: env_or("WS_LAG_WINDOW_SECS", 60)?,
+                hello_timeout_seconds: env_or("WS_HELLO_TIMEOUT_SECS", 2)?,
+                max_replay_messages: env_or("WS_MAX_REPLAY_MESSAGES", 500)?,
+            },
+            shutdown: ShutdownConfig {
+                drain_timeout_seconds: env_or("SHUTDOWN_DRAIN_TIMEOUT_SECS", 20)?,
+                reconnect_window_seconds: env_or("SHUTDOWN_RECONNECT_WINDOW_SECS", 5)?,
+            },
+            pubsub: PubSubConfig {
+                backend: env_or("PUBSUB_BACKEND", "memory".to_string())?,
+                database_url: env::var("DATABASE_URL").ok(),
+            },
+            mailer: MailerConfig {
+                backend: env_or("MAILER_BACKEND", "log".to_string())?,
+                from: env_or("MAIL_FROM", "rustle_chat <no-reply@localhost>".to_string())?,
+                smtp_host: env_or("SMTP_HOST", "localhost".to_string())?,
+                smtp_port: env_or("SMTP_PORT", 1025)?,
+                smtp_username: env::var("SMTP_USERNAME").ok(),
+                smtp_password: env::var("SMTP_PASSWORD").ok(),
+                outbox_dir: env_or("MAIL_OUTBOX_DIR", "outbox".to_string())?,
+                app_base_url: env_or("APP_BASE_URL", "http://localhost:3000".to_string())?,
+                password_reset_url: env::var("PASSWORD_RESET_URL").ok(),
+            },
+        })
+    }
+}
+
+/// Reads and parses an environment variable, returning `default` when it is not set
+fn env_or<T: FromStr>(name: &str, default: T) -> Result<T, ConfigError> {
+    match env::var(name) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map_err(|_| ConfigError::InvalidValue(name.to_string(), value)),
+        Err(_) => Ok(default),
+    }
+}
@@ -1,14 +1,28 @@
 use tokio_postgres::Client;
 
+// Key of the Postgres advisory lock held while migrations run
+const MIGRATION_LOCK_ID: i64 = 0x7275_7374_6c65; // "rustle"
+
 // This function applies database migrations, such as creating the database and tables.
 pub async fn apply_migrations(client: &Client) -> Result<(), String> {
     // Create the database if it does not exist
     create_database_if_not_exists(client).await;
 
+    // Several nodes may start at once; only one of them migrates at a time
+    client
+        .execute("SELECT pg_advisory_lock($1)", &[&MIGRATION_LOCK_ID])
+        .await
+        .map_err(|e| format!("Error acquiring the migration lock: {}", e))?;
+
     // Create the necessary tables
-    create_tables(client).await?;
+    let result = create_tables(client).await;
 
-    Ok(())
+    client
+        .execute("SELECT pg_advisory_unlock($1)", &[&MIGRATION_LOCK_ID])
+        .await
+        .map_err(|e| format!("Error releasing the migration lock: {}", e))?;
+
+    result
 }
 
 // This function checks if the database exists and creates it if necessary.
@@ -51,6 +65,80 @@ async fn create_tables(client: &Client) -> Result<(), String> {
         .await
         .map_err(|e| format!("Error creating users table: {}", e))?;
 
+    // Add the 'email_verified' flag to 'users'. Accounts that existed before the column
+    // was introduced are considered verified; new accounts start unverified.
+    let add_email_verified_column_query = "
+        ALTER TABLE users ADD COLUMN IF NOT EXISTS email_verified BOOLEAN NOT NULL DEFAULT TRUE;
+        ALTER TABLE users ALTER COLUMN email_verified SET DEFAULT FALSE;
+    ";
+    client
+        .batch_execute(add_email_verified_column_query)
+        .await
+        .map_err(|e| format!("Error adding email_verified column: {}", e))?;
+
+    // Add the TOTP two-factor authentication columns to 'users'
+    let add_totp_columns_query = "
+        ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_secret VARCHAR(64);
+        ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_enabled BOOLEAN NOT NULL DEFAULT FALSE;
+        ALTER TABLE users ADD COLUMN IF NOT EXISTS totp_last_step BIGINT;
+    ";
+    client
+        .batch_execute(add_totp_columns_query)
+        .await
+        .map_err(|e| format!("Error adding TOTP columns: {}", e))?;
+
+    // Add the global 'roles' list to 'users' (e.g. '{admin}')
+    let add_roles_column_query = "
+        ALTER TABLE users ADD COLUMN IF NOT EXISTS roles TEXT[] NOT NULL DEFAULT '{}'
+    ";
+    client
+        .execute(add_roles_column_query, &[])
+        .await
+        .map_err(|e| format!("Error adding roles column: {}", e))?;
+
+    // Add the public profile columns to 'users'
+    let add_profile_columns_query = "
+        ALTER TABLE users ADD COLUMN IF NOT EXISTS display_name VARCHAR(50);
+        ALTER TABLE users ADD COLUMN IF NOT EXISTS bio VARCHAR(500);
+        ALTER TABLE users ADD COLUMN IF NOT EXISTS avatar_ref VARCHAR(255);
+        ALTER TABLE users ADD COLUMN IF NOT EXISTS status_text VARCHAR(100);
+        ALTER TABLE users ADD COLUMN IF NOT EXISTS status_emoji VARCHAR(16);
+    ";
+    client
+        .batch_execute(add_profile_columns_query)
+        .await
+        .map_err(|e| format!("Error adding profile columns: {}", e))?;
+
+    // Enable trigram matching and index usernames and display names for the user directory
+    let create_user_search_indexes_query = "
+        CREATE EXTENSION IF NOT EXISTS pg_trgm;
+        ALTER TABLE users ADD COLUMN IF NOT EXISTS discoverable BOOLEAN NOT NULL DEFAULT TRUE;
+        CREATE INDEX IF NOT EXISTS users_username_trgm_idx ON users USING GIN (lower(username) gin_trgm_ops);
+        CREATE INDEX IF NOT EXISTS users_display_name_trgm_idx ON users USING GIN (lower(display_name) gin_trgm_ops);
+    ";
+    client
+        .batch_execute(create_user_search_indexes_query)
+        .await
+        .map_err(|e| format!("Error creating user search indexes: {}", e))?;
+
+    // Add the pending account deletion date to 'users'
+    let add_deletion_column_query = "
+        ALTER TABLE users ADD COLUMN IF NOT EXISTS deletion_scheduled_at TIMESTAMP
+    ";
+    client
+        .execute(add_deletion_column_query, &[])
+        .await
+        .map_err(|e| format!("Error adding deletion_scheduled_at column: {}", e))?;
+
+    // Add the time a user was last seen online to 'users'
+    let add_last_seen_column_query = "
+        ALTER TABLE users ADD COLUMN IF NOT EXISTS last_seen_at TIMESTAMP
+    ";
+    client
+        .execute(add_last_seen_column_query, &[])
+        .await
+        .map_err(|e| format!("Error adding last_seen_at column: {}", e))?;
+
     // Create the 'chats' table
     let create_chats_table_query = "
         CREATE TABLE IF NOT EXISTS chats (
@@ -83,7 +171,7 @@ async fn create_tables(client: &Client) -> Result<(), String> {
         CREATE TABLE IF NOT EXISTS messages (
             id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
             chat_id UUID NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
-            sender_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            sender_id UUID REFERENCES users(id) ON DELETE SET NULL, -- NULL once the author deleted their account
             message_text TEXT NOT NULL,
             timestamp TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
         )
@@ -93,6 +181,96 @@ async fn create_tables(client: &Client) -> Result<(), String> {
         .await
         .map_err(|e| format!("Error creating messages table: {}", e))?;
 
+    // Keep messages of deleted accounts: older databases cascaded the deletion of a user
+    // to their messages, which removed history from other people's chats
+    let anonymise_deleted_senders_query = "
+        ALTER TABLE messages ALTER COLUMN sender_id DROP NOT NULL;
+        DO $$
+        BEGIN
+            IF EXISTS (
+                SELECT 1 FROM pg_constraint
+                WHERE conname = 'messages_sender_id_fkey' AND confdeltype = 'c'
+            ) THEN
+                ALTER TABLE messages DROP CONSTRAINT messages_sender_id_fkey;
+                ALTER TABLE messages ADD CONSTRAINT messages_sender_id_fkey
+                    FOREIGN KEY (sender_id) REFERENCES users(id) ON DELETE SET NULL;
+            END IF;
+        END $$;
+    ";
+    client
+        .batch_execute(anonymise_deleted_senders_query)
+        .await
+        .map_err(|e| format!("Error updating messages sender constraint: {}", e))?;
+
+    // Number the messages of each chat; chats.last_seq holds the last number handed out.
+    // Messages from before sequence numbers existed are numbered in the order they were sent.
+    let add_message_sequence_query = "
+        ALTER TABLE chats ADD COLUMN IF NOT EXISTS last_seq BIGINT NOT NULL DEFAULT 0;
+        ALTER TABLE messages ADD COLUMN IF NOT EXISTS seq BIGINT;
+        WITH numbered AS (
+            SELECT m.id, c.last_seq + ROW_NUMBER() OVER (PARTITION BY m.chat_id ORDER BY m.timestamp, m.id) AS seq
+            FROM messages m
+            JOIN chats c ON c.id = m.chat_id
+            WHERE m.seq IS NULL
+        ), updated AS (
+            UPDATE messages m SET seq = numbered.seq
+            FROM numbered
+            WHERE m.id = numbered.id
+            RETURNING m.chat_id, m.seq
+        )
+        UPDATE chats c SET last_seq = u.max_seq
+        FROM (SELECT chat_id, MAX(seq) AS max_seq FROM updated GROUP BY chat_id) u
+        WHERE c.id = u.chat_id;
+        ALTER TABLE messages ALTER COLUMN seq SET NOT NULL;
+        CREATE UNIQUE INDEX IF NOT EXISTS messages_chat_id_seq_idx ON messages (chat_id, seq);
+    ";
+    client
+        .batch_execute(add_message_sequence_query)
+        .await
+        .map_err(|e| format!("Error adding message sequence numbers: {}", e))?;
+
+    // Remember the id clients give their messages, so that resending one does not store it twice
+    let add_message_client_id_query = "
+        ALTER TABLE messages ADD COLUMN IF NOT EXISTS client_id UUID;
+        CREATE UNIQUE INDEX IF NOT EXISTS messages_client_id_idx
+            ON messages (chat_id, sender_id, client_id) WHERE client_id IS NOT NULL;
+    ";
+    client
+        .batch_execute(add_message_client_id_query)
+        .await
+        .map_err(|e| format!("Error adding message client ids: {}", e))?;
+
+    // Create the 'pubsub_payloads' table holding WebSocket events too large for a notification.
+    // Rows only live a few minutes, so the table skips the write-ahead log.
+    let create_pubsub_payloads_table_query = "
+        CREATE UNLOGGED TABLE IF NOT EXISTS pubsub_payloads (
+            id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+            payload TEXT NOT NULL,
+            created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )
+    ";
+    client
+        .execute(create_pubsub_payloads_table_query, &[])
+        .await
+        .map_err(|e| format!("Error creating pub/sub payloads table: {}", e))?;
+
+    // Create the 'user_presence' table recording which nodes a user is connected to, so that
+    // presence is shared by every node. Rows of a node that stopped refreshing them are swept.
+    let create_user_presence_table_query = "
+        CREATE UNLOGGED TABLE IF NOT EXISTS user_presence (
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            node_id UUID NOT NULL,
+            status VARCHAR(10) NOT NULL, -- 'online' or 'idle'
+            updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (user_id, node_id)
+        );
+        CREATE INDEX IF NOT EXISTS user_presence_node_id_idx ON user_presence (node_id);
+    ";
+    client
+        .batch_execute(create_user_presence_table_query)
+        .await
+        .map_err(|e| format!("Error creating user presence table: {}", e))?;
+
     // Create the 'sessions' table to manage user login sessions
     let create_sessions_table_query = "
         CREATE TABLE IF NOT EXISTS sessions (
@@ -126,5 +304,168 @@ async fn create_tables(client: &Client) -> Result<(), String> {
         .await
         .map_err(|e| format!("Error creating invites table: {}", e))?;
 
+    // Create the 'password_resets' table for single-use password reset tokens (stored hashed)
+    let create_password_resets_table_query = "
+        CREATE TABLE IF NOT EXISTS password_resets (
+            id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            token_hash VARCHAR(64) NOT NULL UNIQUE,
+            expires_at TIMESTAMP NOT NULL,
+            used_at TIMESTAMP,
+            created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )
+    ";
+    client
+        .execute(create_password_resets_table_query, &[])
+        .await
+        .map_err(|e| format!("Error creating password resets table: {}", e))?;
+
+    // Create the 'email_verifications' table for email confirmation tokens (stored hashed)
+    let create_email_verifications_table_query = "
+        CREATE TABLE IF NOT EXISTS email_verifications (
+            id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            token_hash VARCHAR(64) NOT NULL UNIQUE,
+            expires_at TIMESTAMP NOT NULL,
+            used_at TIMESTAMP,
+            created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )
+    ";
+    client
+        .execute(create_email_verifications_table_query, &[])
+        .await
+        .map_err(|e| format!("Error creating email verifications table: {}", e))?;
+
+    // Create the 'totp_recovery_codes' table for one-time 2FA recovery codes (stored hashed)
+    let create_totp_recovery_codes_table_query = "
+        CREATE TABLE IF NOT EXISTS totp_recovery_codes (
+            id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            code_hash VARCHAR(64) NOT NULL,
+            used_at TIMESTAMP
+        )
+    ";
+    client
+        .execute(create_totp_recovery_codes_table_query, &[])
+        .await
+        .map_err(|e| format!("Error creating TOTP recovery codes table: {}", e))?;
+
+    // Create the 'login_challenges' table for the second step of a two-factor login
+    let create_login_challenges_table_query = "
+        CREATE TABLE IF NOT EXISTS login_challenges (
+            id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            token_hash VARCHAR(64) NOT NULL UNIQUE,
+            attempts INTEGER NOT NULL DEFAULT 0,
+            expires_at TIMESTAMP NOT NULL
+        )
+    ";
+    client
+        .execute(create_login_challenges_table_query, &[])
+        .await
+        .map_err(|e| format!("Error creating login challenges table: {}", e))?;
+
+    // Create the 'login_attempts' table to track failed logins per username and per IP
+    let create_login_attempts_table_query = "
+        CREATE TABLE IF NOT EXISTS login_attempts (
+            scope VARCHAR(16) NOT NULL, -- 'username' or 'ip'
+            key VARCHAR(255) NOT NULL,
+            failed_count INTEGER NOT NULL DEFAULT 0,
+            last_failed_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            locked_until TIMESTAMP,
+            PRIMARY KEY (scope, key)
+        )
+    ";
+    client
+        .execute(create_login_attempts_table_query, &[])
+        .await
+        .map_err(|e| format!("Error creating login attempts table: {}", e))?;
+
+    // Create the 'request_throttles' table counting email-sending requests per email and per IP
+    let create_request_throttles_table_query = "
+        CREATE TABLE IF NOT EXISTS request_throttles (
+            scope VARCHAR(32) NOT NULL, -- e.g. 'resend_verification:ip'
+            key VARCHAR(255) NOT NULL,
+            request_count INTEGER NOT NULL DEFAULT 0,
+            window_started_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (scope, key)
+        )
+    ";
+    client
+        .execute(create_request_throttles_table_query, &[])
+        .await
+        .map_err(|e| format!("Error creating request throttles table: {}", e))?;
+
+    // Create the 'email_changes' table for pending email address changes (tokens stored hashed)
+    let create_email_changes_table_query = "
+        CREATE TABLE IF NOT EXISTS email_changes (
+            id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            new_email VARCHAR(255) NOT NULL,
+            token_hash VARCHAR(64) NOT NULL UNIQUE,
+            expires_at TIMESTAMP NOT NULL,
+            used_at TIMESTAMP,
+            created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )
+    ";
+    client
+        .execute(create_email_changes_table_query, &[])
+        .await
+        .map_err(|e| format!("Error creating email changes table: {}", e))?;
+
+    // Create the 'audit_log' table recording security-relevant account changes
+    let create_audit_log_table_query = "
+        CREATE TABLE IF NOT EXISTS audit_log (
+            id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            event VARCHAR(64) NOT NULL,
+            details JSONB NOT NULL DEFAULT '{}',
+            ip_address VARCHAR(45),
+            created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE INDEX IF NOT EXISTS audit_log_user_id_idx ON audit_log (user_id, created_at);
+    ";
+    client
+        .batch_execute(create_audit_log_table_query)
+        .await
+        .map_err(|e| format!("Error creating audit log table: {}", e))?;
+
+    // Create the 'user_blocks' table listing the users each user has blocked
+    let create_user_blocks_table_query = "
+        CREATE TABLE IF NOT EXISTS user_blocks (
+            blocker_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            blocked_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (blocker_id, blocked_id),
+            CHECK (blocker_id <> blocked_id)
+        );
+        CREATE INDEX IF NOT EXISTS user_blocks_blocked_id_idx ON user_blocks (blocked_id);
+    ";
+    client
+        .batch_execute(create_user_blocks_table_query)
+        .await
+        .map_err(|e| format!("Error creating user blocks table: {}", e))?;
+
+    // Create the 'friend_requests' table; accepted requests form the contact list
+    let create_friend_requests_table_query = "
+        CREATE TABLE IF NOT EXISTS friend_requests (
+            id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+            requester_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            addressee_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            status VARCHAR(20) NOT NULL DEFAULT 'pending', -- 'pending', 'accepted', 'rejected'
+            created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            CHECK (requester_id <> addressee_id)
+        );
+        CREATE UNIQUE INDEX IF NOT EXISTS friend_requests_open_pair_idx
+            ON friend_requests (LEAST(requester_id, addressee_id), GREATEST(requester_id, addressee_id))
+            WHERE status IN ('pending', 'accepted');
+        CREATE INDEX IF NOT EXISTS friend_requests_addressee_id_idx ON friend_requests (addressee_id);
+    ";
+    client
+        .batch_execute(create_friend_requests_table_query)
+        .await
+        .map_err(|e| format!("Error creating friend requests table: {}", e))?;
+
     Ok(())
 }
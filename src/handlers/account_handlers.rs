@@ -0,0 +1,99 @@
+use axum::{
+    debug_handler,
+    extract::{ConnectInfo, Extension, Path},
+    http::HeaderMap,
+    response::IntoResponse,
+    Json,
+};
+use std::net::SocketAddr;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    models::{
+        account::{ChangeEmailRequest, ChangePasswordRequest, DeleteAccountRequest},
+        user::Claims,
+    },
+    services::account_service::AccountService,
+    utils::client_ip::client_ip,
+};
+
+/// Handler for downloading an archive of the user's personal data
+#[debug_handler]
+pub async fn export_data(
+    Extension(state): Extension<AppState>,
+    Extension(user_id): Extension<String>,
+) -> impl IntoResponse {
+    let user_id = Uuid::parse_str(&user_id).expect("User should be authenticated");
+    let account_service = AccountService::new(&state);
+    account_service.export(user_id).await
+}
+
+/// Handler for requesting the deletion of the user's account
+#[debug_handler]
+pub async fn delete_account(
+    Extension(state): Extension<AppState>,
+    Extension(user_id): Extension<String>,
+    Json(payload): Json<DeleteAccountRequest>,
+) -> impl IntoResponse {
+    let user_id = Uuid::parse_str(&user_id).expect("User should be authenticated");
+    let account_service = AccountService::new(&state);
+    account_service.delete_account(user_id, payload).await
+}
+
+/// Handler for cancelling a pending account deletion
+#[debug_handler]
+pub async fn cancel_deletion(
+    Extension(state): Extension<AppState>,
+    Extension(user_id): Extension<String>,
+) -> impl IntoResponse {
+    let user_id = Uuid::parse_str(&user_id).expect("User should be authenticated");
+    let account_service = AccountService::new(&state);
+    account_service.cancel_deletion(user_id).await
+}
+
+/// Handler for changing the password of the logged-in user
+#[debug_handler]
+pub async fn change_password(
+    Extension(state): Extension<AppState>,
+    Extension(claims): Extension<Claims>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<ChangePasswordRequest>,
+) -> impl IntoResponse {
+    let user_id = Uuid::parse_str(&claims.sub).expect("User should be authenticated");
+    let session_id = Uuid::parse_str(&claims.sid).expect("Session should be authenticated");
+    let ip = client_ip(&headers, peer, &state.config.login_protection);
+    let account_service = AccountService::new(&state);
+    account_service
+        .change_password(user_id, session_id, payload, Some(ip))
+        .await
+}
+
+/// Handler for requesting a change of the logged-in user's email address
+#[debug_handler]
+pub async fn change_email(
+    Extension(state): Extension<AppState>,
+    Extension(user_id): Extension<String>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<ChangeEmailRequest>,
+) -> impl IntoResponse {
+    let user_id = Uuid::parse_str(&user_id).expect("User should be authenticated");
+    let ip = client_ip(&headers, peer, &state.config.login_protection);
+    let account_service = AccountService::new(&state);
+    account_service.change_email(user_id, payload, Some(ip)).await
+}
+
+/// Handler for confirming an email change from the link sent to the new address
+#[debug_handler]
+pub async fn confirm_email_change(
+    Extension(state): Extension<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(token): Path<String>,
+) -> impl IntoResponse {
+    let ip = client_ip(&headers, peer, &state.config.login_protection);
+    let account_service = AccountService::new(&state);
+    account_service.confirm_email_change(&token, Some(ip)).await
+}
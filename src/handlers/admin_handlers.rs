@@ -0,0 +1,36 @@
+use axum::{extract::Path, response::IntoResponse, Extension, Json};
+use hyper::StatusCode;
+use serde_json::json;
+
+use crate::{app_state::AppState, services::login_protection_service::LoginProtectionService};
+
+/// Handler for lifting a login lockout on an account (admin only)
+pub async fn unlock_account(
+    Extension(state): Extension<AppState>,
+    Path(username): Path<String>,
+) -> impl IntoResponse {
+    let client = match state.db.get().await {
+        Ok(client) => client,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Database connection error" })),
+            );
+        }
+    };
+
+    match LoginProtectionService::unlock_username(&client, &username).await {
+        Ok(true) => (
+            StatusCode::OK,
+            Json(json!({ "message": format!("Account {} unlocked", username) })),
+        ),
+        Ok(false) => (
+            StatusCode::OK,
+            Json(json!({ "message": format!("Account {} was not locked", username) })),
+        ),
+        Err(_) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": "Failed to unlock account" })),
+        ),
+    }
+}
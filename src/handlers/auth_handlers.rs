@@ -1,13 +1,16 @@
 use axum::{
     debug_handler,
-    extract::Extension,
+    extract::{ConnectInfo, Extension, Path},
+    http::HeaderMap,
     response::IntoResponse,
     Json,
 };
+use std::net::SocketAddr;
 use crate::{
     app_state::AppState,
-    models::user::{LoginData, RegisterData},
+    models::user::{LoginData, RegisterData, ResendVerificationRequest},
     services::auth_service::AuthService,
+    utils::client_ip::client_ip,
 };
 
 /// Handler for user registration
@@ -16,7 +19,7 @@ pub async fn register(
     Extension(state): Extension<AppState>,
     Json(payload): Json<RegisterData>,
 ) -> impl IntoResponse {
-    let auth_service = AuthService::new(state.db.clone());
+    let auth_service = AuthService::new(&state);
     auth_service.register_user(payload).await
 }
 
@@ -24,10 +27,40 @@ pub async fn register(
 #[debug_handler]
 pub async fn login(
     Extension(state): Extension<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(payload): Json<LoginData>,
 ) -> impl IntoResponse {
-    let auth_service = AuthService::new(state.db.clone());
-    auth_service.login_user(payload).await
+    let ip = client_ip(&headers, peer, &state.config.login_protection);
+    let auth_service = AuthService::new(&state);
+    auth_service.login_user(payload, Some(ip)).await
 }
 
+/// Handler for confirming an email address from the link sent by email
+#[debug_handler]
+pub async fn verify_email(
+    Extension(state): Extension<AppState>,
+    Path(token): Path<String>,
+) -> impl IntoResponse {
+    let auth_service = AuthService::new(&state);
+    auth_service.verify_email(&token).await
+}
 
+/// Handler for resending the verification email
+#[debug_handler]
+pub async fn resend_verification(
+    Extension(state): Extension<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<ResendVerificationRequest>,
+) -> impl IntoResponse {
+    let ip = client_ip(&headers, peer, &state.config.login_protection);
+    let auth_service = AuthService::new(&state);
+    auth_service.resend_verification(payload, Some(ip)).await
+}
+
+/// Handler publishing the public JWT verification keys as a JWKS document
+#[debug_handler]
+pub async fn jwks(Extension(state): Extension<AppState>) -> impl IntoResponse {
+    Json(state.keys.jwks())
+}
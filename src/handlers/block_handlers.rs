@@ -0,0 +1,43 @@
+use axum::{
+    debug_handler,
+    extract::{Extension, Path},
+    response::IntoResponse,
+};
+use uuid::Uuid;
+
+use crate::{app_state::AppState, services::block_service::BlockService};
+
+/// Handler for listing the users blocked by the authenticated user
+#[debug_handler]
+pub async fn list_blocks(
+    Extension(state): Extension<AppState>,
+    Extension(user_id): Extension<String>,
+) -> impl IntoResponse {
+    let user_id = Uuid::parse_str(&user_id).expect("User should be authenticated");
+    let block_service = BlockService::new(&state);
+    block_service.list(user_id).await
+}
+
+/// Handler for blocking a user
+#[debug_handler]
+pub async fn block_user(
+    Extension(state): Extension<AppState>,
+    Extension(user_id): Extension<String>,
+    Path(username): Path<String>,
+) -> impl IntoResponse {
+    let user_id = Uuid::parse_str(&user_id).expect("User should be authenticated");
+    let block_service = BlockService::new(&state);
+    block_service.block(user_id, &username).await
+}
+
+/// Handler for unblocking a user
+#[debug_handler]
+pub async fn unblock_user(
+    Extension(state): Extension<AppState>,
+    Extension(user_id): Extension<String>,
+    Path(username): Path<String>,
+) -> impl IntoResponse {
+    let user_id = Uuid::parse_str(&user_id).expect("User should be authenticated");
+    let block_service = BlockService::new(&state);
+    block_service.unblock(user_id, &username).await
+}
@@ -3,11 +3,17 @@ use crate::{
     handlers::invitation_handlers::send_invitation_helper,
     models::{
         chat::{Chat, CreateChatRequest},
-        message::SendMessageRequest,
+        message::{MessageRangeQuery, SendMessageRequest},
     },
-    services::chat_service::{self, ChatService},
+    repositories::auth_repository::AuthRepository,
+    services::chat_service::{self, ChatService, SendMessageError},
+};
+use axum::{
+    debug_handler,
+    extract::{Path, Query},
+    response::IntoResponse,
+    Extension, Json,
 };
-use axum::{debug_handler, extract::Path, response::IntoResponse, Extension, Json};
 use hyper::StatusCode;
 use uuid::Uuid;
 
@@ -18,6 +24,24 @@ pub async fn create_chat(
 ) -> Result<Json<Chat>, (StatusCode, String)> {
     let user_id = Uuid::parse_str(&user_id).expect("User should be authenticated");
 
+    // Optionally require a verified email address before creating chats
+    if state.config.email_verification.required_for_chat_creation {
+        let client = state
+            .db
+            .get()
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        let verified = AuthRepository::is_email_verified(&client, user_id)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        if !verified {
+            return Err((
+                StatusCode::FORBIDDEN,
+                "Verify your email address before creating chats".to_string(),
+            ));
+        }
+    }
+
     // Pass the Arc<Pool> to the service layer
     let chat = match ChatService::create_chat(state.db.clone(), user_id, payload.name.clone()).await {
         Ok(chat) => chat,
@@ -48,10 +72,14 @@ pub async fn create_chat(
 #[debug_handler]
 pub async fn get_chat_messages(
     Extension(state): Extension<AppState>,
+    Extension(user_id): Extension<String>,
     Path(chat_id): axum::extract::Path<Uuid>, // Extracts `chat_id` from the URL
+    Query(range): Query<MessageRangeQuery>, // Optional `after` / `before` bounds
 ) -> impl IntoResponse {
+    let user_id = Uuid::parse_str(&user_id).expect("User should be authenticated");
+
     // Pass the Arc<Pool> to the service layer
-    let messages = ChatService::get_chat_messages(state.db.clone(), chat_id).await;
+    let messages = ChatService::get_chat_messages(state.db.clone(), chat_id, user_id, range).await;
 
     match messages {
         Ok(messages) => Ok(Json(messages)),
@@ -62,16 +90,27 @@ pub async fn get_chat_messages(
 #[debug_handler]
 pub async fn send_message_handler(
     Extension(state): Extension<AppState>,
+    Extension(user_id): Extension<String>,
     Json(payload): Json<SendMessageRequest>,
 ) -> impl IntoResponse {
-    let user_id = state.current_user_id.expect("User should be authenticated");
+    let user_id = Uuid::parse_str(&user_id).expect("User should be authenticated");
 
-    // Pass the Arc<Pool> to the service layer
-    match ChatService::send_message(state.db.clone(), payload.chat_id, user_id, payload.message)
+    // Store the message and push it to the members connected to the chat
+    match ChatService::post_message(state.db.clone(), &state.connections, payload.chat_id, user_id, payload.message, payload.client_id)
         .await
     {
         Ok(message) => Ok(Json(message)),
-        Err(e) => Err((StatusCode::FORBIDDEN, e)), // Alterado para FORBIDDEN se o usuário não for membro
+        Err(e) => {
+            let status = match e {
+                SendMessageError::NotMember => StatusCode::FORBIDDEN,
+                SendMessageError::Empty => StatusCode::BAD_REQUEST,
+                SendMessageError::Internal(ref details) => {
+                    eprintln!("Failed to send message: {}", details);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+            };
+            Err((status, e.to_string()))
+        }
     }
 }
 
@@ -0,0 +1,71 @@
+use axum::{
+    debug_handler,
+    extract::{Extension, Path},
+    response::IntoResponse,
+    Json,
+};
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    models::friend::{FriendRequestResponse, SendFriendRequest},
+    services::friend_service::FriendService,
+};
+
+/// Handler for sending a friend request
+#[debug_handler]
+pub async fn send_request(
+    Extension(state): Extension<AppState>,
+    Extension(user_id): Extension<String>,
+    Json(payload): Json<SendFriendRequest>,
+) -> impl IntoResponse {
+    let user_id = Uuid::parse_str(&user_id).expect("User should be authenticated");
+    let friend_service = FriendService::new(&state);
+    friend_service.send_request(user_id, payload).await
+}
+
+/// Handler for listing pending friend requests
+#[debug_handler]
+pub async fn list_requests(
+    Extension(state): Extension<AppState>,
+    Extension(user_id): Extension<String>,
+) -> impl IntoResponse {
+    let user_id = Uuid::parse_str(&user_id).expect("User should be authenticated");
+    let friend_service = FriendService::new(&state);
+    friend_service.list_requests(user_id).await
+}
+
+/// Handler for accepting or declining a friend request
+#[debug_handler]
+pub async fn respond_to_request(
+    Extension(state): Extension<AppState>,
+    Extension(user_id): Extension<String>,
+    Json(payload): Json<FriendRequestResponse>,
+) -> impl IntoResponse {
+    let user_id = Uuid::parse_str(&user_id).expect("User should be authenticated");
+    let friend_service = FriendService::new(&state);
+    friend_service.respond(user_id, payload).await
+}
+
+/// Handler for listing contacts with their online presence
+#[debug_handler]
+pub async fn list_contacts(
+    Extension(state): Extension<AppState>,
+    Extension(user_id): Extension<String>,
+) -> impl IntoResponse {
+    let user_id = Uuid::parse_str(&user_id).expect("User should be authenticated");
+    let friend_service = FriendService::new(&state);
+    friend_service.list_contacts(user_id).await
+}
+
+/// Handler for removing a contact
+#[debug_handler]
+pub async fn remove_contact(
+    Extension(state): Extension<AppState>,
+    Extension(user_id): Extension<String>,
+    Path(username): Path<String>,
+) -> impl IntoResponse {
+    let user_id = Uuid::parse_str(&user_id).expect("User should be authenticated");
+    let friend_service = FriendService::new(&state);
+    friend_service.remove_contact(user_id, &username).await
+}
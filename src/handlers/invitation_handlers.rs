@@ -10,7 +10,7 @@ use crate::{
     app_state::AppState,
     models::invitation::{InvitationNotification, InvitationResponse},
     repositories::invitation_repository::InvitationRepository,
-    services::{auth_service::AuthService, invitation_service::InvitationService},
+    services::invitation_service::InvitationService,
     websocket::{
         connection_manager::ConnectionManager,
         types::{StatusMessage, UserStatus, WebSocketMessage},
@@ -45,25 +45,16 @@ pub async fn respond_to_invitation(
                     return Err((StatusCode::INTERNAL_SERVER_ERROR, e));
                 }
 
-                // Add the user to the WebSocket connection
-                if let Ok(_) = state
-                    .connections
-                    .add_user_to_chat(invitation.chat_id, user_id)
-                    .await
-                {
-                    // Create a WebSocket notification for the user joining the chat
-                    let notification = WebSocketMessage::Status(StatusMessage {
-                        chat_id: invitation.chat_id,
-                        user_id,
-                        status: UserStatus::Joined,
-                        timestamp: Utc::now().naive_utc(),
-                    });
-                    // Broadcast the notification to the chat
-                    let _ = state
-                        .connections
-                        .broadcast_to_chat(invitation.chat_id, user_id, notification)
-                        .await;
-                }
+                // Create a WebSocket notification for the user joining the chat; the user enters
+                // the chat room in memory once one of their connections opens it
+                let notification = WebSocketMessage::Status(StatusMessage {
+                    chat_id: invitation.chat_id,
+                    user_id,
+                    status: UserStatus::Joined,
+                    timestamp: Utc::now().naive_utc(),
+                });
+                // Broadcast the notification to the chat
+                state.connections.broadcast_to_chat(invitation.chat_id, notification);
             }
             // Return the updated invitation as a JSON response
             Ok(Json(invitation))
@@ -89,30 +80,26 @@ pub async fn send_invitation_helper(
         .send_invitation(chat_id, inviter_id, &invitee_username)
         .await
     {
-        Ok(invitation_id) => {
-            // Create an instance of AuthService to fetch the inviter's username
-            let auth_service = AuthService::new(pool.clone());
-
-            // Check if the invitee is online
-            if let Some(user) = connections.get_online_user(&invitee_username).await {
-                // Create a WebSocket notification for the invitation
-                let notification = WebSocketMessage::Invitation(InvitationNotification {
-                    invitation_id,
-                    chat_id,
-                    inviter_username: auth_service
-                        .get_username(inviter_id)
-                        .await
-                        .unwrap_or_default(), // Fetch the inviter's username
-                    timestamp: Utc::now().naive_utc(),
-                });
+        // The invitee has blocked the inviter; report success without notifying anyone
+        Ok(None) => Ok(()),
+        Ok(Some((invitation_id, invitee_id))) => {
+            // Create a WebSocket notification for the invitation
+            let notification = WebSocketMessage::Invitation(InvitationNotification {
+                invitation_id,
+                chat_id,
+                inviter_username: connections
+                    .get_user_from_db(inviter_id)
+                    .await
+                    .unwrap_or_default(), // Fetch the inviter's username
+                timestamp: Utc::now().naive_utc(),
+            });
 
-                // Send the notification directly to the invitee
-                if let Err(e) = connections.send_direct_message(user.id, notification).await {
-                    return Err((
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        format!("Error sending WebSocket notification: {}", e),
-                    ));
-                }
+            // Send the notification directly to the invitee, on whichever node they are connected
+            if let Err(e) = connections.send_direct_message(invitee_id, notification).await {
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Error sending WebSocket notification: {}", e),
+                ));
             }
 
             // Return success if everything works
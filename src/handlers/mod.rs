@@ -1,3 +1,11 @@
+pub mod account_handlers;
+pub mod admin_handlers;
 pub mod auth_handlers;
+pub mod block_handlers;
 pub mod chat_handlers;
-pub mod invitation_handlers;
\ No newline at end of file
+pub mod friend_handlers;
+pub mod invitation_handlers;
+pub mod password_handlers;
+pub mod presence_handlers;
+pub mod profile_handlers;
+pub mod two_factor_handlers;
\ No newline at end of file
@@ -0,0 +1,45 @@
+use axum::{
+    debug_handler,
+    extract::{ConnectInfo, Extension},
+    http::HeaderMap,
+    response::IntoResponse,
+    Json,
+};
+use std::net::SocketAddr;
+
+use crate::{
+    app_state::AppState,
+    models::password::{ForgotPasswordRequest, ResetPasswordRequest},
+    services::password_service::PasswordService,
+    utils::client_ip::client_ip,
+};
+
+/// Handler for describing the active password policy
+#[debug_handler]
+pub async fn password_policy(Extension(state): Extension<AppState>) -> impl IntoResponse {
+    let password_service = PasswordService::new(&state);
+    password_service.policy()
+}
+
+/// Handler for requesting a password reset email
+#[debug_handler]
+pub async fn forgot_password(
+    Extension(state): Extension<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<ForgotPasswordRequest>,
+) -> impl IntoResponse {
+    let ip = client_ip(&headers, peer, &state.config.login_protection);
+    let password_service = PasswordService::new(&state);
+    password_service.forgot_password(payload, Some(ip)).await
+}
+
+/// Handler for resetting a password with a token received by email
+#[debug_handler]
+pub async fn reset_password(
+    Extension(state): Extension<AppState>,
+    Json(payload): Json<ResetPasswordRequest>,
+) -> impl IntoResponse {
+    let password_service = PasswordService::new(&state);
+    password_service.reset_password(payload).await
+}
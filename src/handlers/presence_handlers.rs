@@ -0,0 +1,23 @@
+use axum::{
+    debug_handler,
+    extract::{Extension, Query},
+    response::IntoResponse,
+};
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState, models::presence::PresenceQuery,
+    services::presence_service::PresenceService,
+};
+
+/// Handler for looking up the presence and last-seen time of users
+#[debug_handler]
+pub async fn get_presence(
+    Extension(state): Extension<AppState>,
+    Extension(user_id): Extension<String>,
+    Query(query): Query<PresenceQuery>,
+) -> impl IntoResponse {
+    let user_id = Uuid::parse_str(&user_id).expect("User should be authenticated");
+    let presence_service = PresenceService::new(&state);
+    presence_service.get_presence(user_id, query).await
+}
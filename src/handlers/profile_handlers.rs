@@ -0,0 +1,58 @@
+use axum::{
+    debug_handler,
+    extract::{Extension, Path, Query},
+    response::IntoResponse,
+    Json,
+};
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    models::profile::{UpdateProfileRequest, UserSearchQuery},
+    services::profile_service::ProfileService,
+};
+
+/// Handler returning the profile of the authenticated user
+#[debug_handler]
+pub async fn get_me(
+    Extension(state): Extension<AppState>,
+    Extension(user_id): Extension<String>,
+) -> impl IntoResponse {
+    let user_id = Uuid::parse_str(&user_id).expect("User should be authenticated");
+    let profile_service = ProfileService::new(&state);
+    profile_service.get_own_profile(user_id).await
+}
+
+/// Handler for updating the profile of the authenticated user
+#[debug_handler]
+pub async fn update_me(
+    Extension(state): Extension<AppState>,
+    Extension(user_id): Extension<String>,
+    Json(payload): Json<UpdateProfileRequest>,
+) -> impl IntoResponse {
+    let user_id = Uuid::parse_str(&user_id).expect("User should be authenticated");
+    let profile_service = ProfileService::new(&state);
+    profile_service.update_profile(user_id, payload).await
+}
+
+/// Handler returning the public profile of a user
+#[debug_handler]
+pub async fn get_user(
+    Extension(state): Extension<AppState>,
+    Path(username): Path<String>,
+) -> impl IntoResponse {
+    let profile_service = ProfileService::new(&state);
+    profile_service.get_profile(&username).await
+}
+
+/// Handler for searching the user directory (e.g. invite autocompletion)
+#[debug_handler]
+pub async fn search_users(
+    Extension(state): Extension<AppState>,
+    Extension(user_id): Extension<String>,
+    Query(query): Query<UserSearchQuery>,
+) -> impl IntoResponse {
+    let user_id = Uuid::parse_str(&user_id).expect("User should be authenticated");
+    let profile_service = ProfileService::new(&state);
+    profile_service.search(user_id, query).await
+}
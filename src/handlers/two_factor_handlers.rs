@@ -0,0 +1,64 @@
+use axum::{
+    debug_handler,
+    extract::{ConnectInfo, Extension},
+    http::HeaderMap,
+    response::IntoResponse,
+    Json,
+};
+use std::net::SocketAddr;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    models::two_factor::{TotpCodeRequest, TwoFactorLoginRequest},
+    services::{auth_service::AuthService, two_factor_service::TwoFactorService},
+    utils::client_ip::client_ip,
+};
+
+/// Handler for starting TOTP enrolment
+#[debug_handler]
+pub async fn enroll(
+    Extension(state): Extension<AppState>,
+    Extension(user_id): Extension<String>,
+) -> impl IntoResponse {
+    let user_id = Uuid::parse_str(&user_id).expect("User should be authenticated");
+    let two_factor_service = TwoFactorService::new(&state);
+    two_factor_service.enroll(user_id).await
+}
+
+/// Handler for confirming TOTP enrolment with a code from the authenticator app
+#[debug_handler]
+pub async fn confirm(
+    Extension(state): Extension<AppState>,
+    Extension(user_id): Extension<String>,
+    Json(payload): Json<TotpCodeRequest>,
+) -> impl IntoResponse {
+    let user_id = Uuid::parse_str(&user_id).expect("User should be authenticated");
+    let two_factor_service = TwoFactorService::new(&state);
+    two_factor_service.confirm(user_id, &payload.code).await
+}
+
+/// Handler for disabling two-factor authentication
+#[debug_handler]
+pub async fn disable(
+    Extension(state): Extension<AppState>,
+    Extension(user_id): Extension<String>,
+    Json(payload): Json<TotpCodeRequest>,
+) -> impl IntoResponse {
+    let user_id = Uuid::parse_str(&user_id).expect("User should be authenticated");
+    let two_factor_service = TwoFactorService::new(&state);
+    two_factor_service.disable(user_id, &payload.code).await
+}
+
+/// Handler for the second login step
+#[debug_handler]
+pub async fn login_two_factor(
+    Extension(state): Extension<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<TwoFactorLoginRequest>,
+) -> impl IntoResponse {
+    let ip = client_ip(&headers, peer, &state.config.login_protection);
+    let auth_service = AuthService::new(&state);
+    auth_service.complete_two_factor_login(payload, Some(ip)).await
+}
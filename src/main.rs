@@ -9,11 +9,24 @@ mod websocket;
 mod app_state;
 mod repositories;
 mod utils;
+mod config;
 
 // Import required functions and types
+use app_state::AppState;
+use config::AppConfig;
 use database::init::init_db;
 use routes::app_routes::create_router;
+use services::account_service::AccountService;
+use services::presence_service::PresenceService;
+use services::jwt_service::KeyRing;
+use services::mailer::mailer_from_config;
+use services::session_cache::SessionCache;
 use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use utils::password_validator::PasswordValidator;
+use websocket::connection_manager::ConnectionManager;
+use websocket::pubsub::pubsub_from_config;
 use tokio::signal;
 
 // The main entry point for the application using the tokio runtime.
@@ -34,19 +47,136 @@ async fn main() {
         }
     };
 
-    // Create the router using the function from the router module
-    let app = create_router(db);
+    // Load the application settings from the environment
+    let config = match AppConfig::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error loading configuration: {}", e);  // Log the error if a setting is invalid
+            return;
+        }
+    };
 
-    // Set the server address to listen on all IP addresses (0.0.0.0) and port 3000
-    let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
+    // Initialize the mailer backend selected in the configuration
+    let mailer = match mailer_from_config(&config.mailer) {
+        Ok(mailer) => mailer,
+        Err(e) => {
+            eprintln!("Error initializing the mailer: {}", e);  // Log the error if the configuration is invalid
+            return;
+        }
+    };
+
+    // Build the password policy, loading the denylist of common passwords if configured
+    let password_validator = match PasswordValidator::from_config(&config.password_policy) {
+        Ok(validator) => validator,
+        Err(e) => {
+            eprintln!("Error loading the password denylist: {}", e);  // Log the error if the file cannot be read
+            return;
+        }
+    };
+
+    // Load the JWT signing key and the keys still accepted for verification
+    let keys = match KeyRing::from_config(&config.jwt) {
+        Ok(keys) => keys,
+        Err(e) => {
+            eprintln!("Error loading the JWT keys: {}", e);  // Log the error if a key is missing or invalid
+            return;
+        }
+    };
+
+    // Initialize the backend that shares WebSocket events with the other nodes
+    let pubsub = match pubsub_from_config(&config.pubsub, db.clone()) {
+        Ok(pubsub) => pubsub,
+        Err(e) => {
+            eprintln!("Error initializing the pub/sub backend: {}", e);  // Log the error if the configuration is invalid
+            return;
+        }
+    };
+
+    // Create the shared application state
+    let connections = ConnectionManager::new(db.clone(), config.websocket.channel_capacity, pubsub.clone());
+
+    // Purge accounts whose deletion grace period has ended
+    tokio::spawn(AccountService::run_purge_task(
+        db.clone(),
+        connections.clone(),
+        std::time::Duration::from_secs(config.account_deletion.purge_interval_seconds.max(1)),
+    ));
+
+    // Deliver the events published by the other nodes to local connections
+    let listener_connections = connections.clone();
+    tokio::spawn(async move { pubsub.run(listener_connections).await });
+
+    // Mark users without recent WebSocket activity as idle
+    tokio::spawn(PresenceService::run_idle_task(
+        db.clone(),
+        connections.clone(),
+        chrono::Duration::seconds(config.presence.idle_after_seconds),
+        std::time::Duration::from_secs(config.presence.sweep_interval_seconds.max(1)),
+    ));
+
+    // Evict WebSocket connections that stopped answering, and the presence of stopped nodes
+    tokio::spawn(PresenceService::run_reaper_task(
+        db.clone(),
+        connections.clone(),
+        chrono::Duration::seconds(config.websocket.stale_after_seconds),
+        std::time::Duration::from_secs(config.websocket.sweep_interval_seconds.max(1)),
+        config.presence.node_timeout_seconds,
+    ));
+    let sessions = SessionCache::new(Duration::from_secs(config.jwt.session_cache_ttl_seconds));
+    let state = AppState::new(
+        db,
+        connections,
+        mailer,
+        Arc::new(config),
+        Arc::new(password_validator),
+        Arc::new(keys),
+        Arc::new(sessions),
+    );
+
+    // Set the server address to listen on all IP addresses (0.0.0.0) and the configured port
+    let addr = SocketAddr::from(([0, 0, 0, 0], state.config.port));
+
+    // Keep what the shutdown needs before the state moves into the router
+    let connections = state.connections.clone();
+    let shutdown_config = state.config.shutdown.clone();
+
+    // Create the router using the function from the router module
+    let app = create_router(state);
     println!("Server running on http://{}", addr);  // Log server address
 
     // Start the server, binding to the specified address and enabling graceful shutdown
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())  // Convert the app into a service
-        .with_graceful_shutdown(shutdown_signal())  // Enable graceful shutdown using the shutdown signal handler
-        .await
-        .unwrap();  // Panic if server fails to start
+    let (stop_accepting, stop_requested) = tokio::sync::oneshot::channel::<()>();
+    let mut server = tokio::spawn(
+        axum::Server::bind(&addr)
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())  // Convert the app into a service that knows the peer address
+            .with_graceful_shutdown(async {
+                let _ = stop_requested.await;  // Stop accepting connections once shutdown starts
+            }),
+    );
+
+    tokio::select! {
+        result = &mut server => {
+            result.unwrap().unwrap();  // Panic if server fails
+            return;
+        }
+        _ = shutdown_signal() => {}
+    }
+
+    // Tell WebSocket and event stream clients to go away, then give open requests and
+    // connections until the deadline to finish
+    connections.shut_down(Duration::from_secs(shutdown_config.reconnect_window_seconds));
+    let _ = stop_accepting.send(());
+    let drain = async {
+        let _ = server.await;  // In-flight HTTP requests, including event streams
+        connections.drained().await;  // Upgraded WebSocket connections
+    };
+    match tokio::time::timeout(Duration::from_secs(shutdown_config.drain_timeout_seconds), drain).await {
+        Ok(()) => println!("All connections drained"),
+        Err(_) => println!(
+            "Drain deadline reached, exiting with {} connections still open",
+            connections.open_connections.load(std::sync::atomic::Ordering::SeqCst)
+        ),
+    }
 }
 
 // A function to handle graceful shutdown by listening for termination signals.
@@ -0,0 +1,24 @@
+use axum::{
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::models::user::Claims;
+
+/// Allows the request through only if the token grants the "admin" role.
+///
+/// Must run after `auth_middleware`, which puts the validated claims into the request extensions.
+/// Roles are read from the token, so a role change takes effect at the user's next login.
+pub async fn admin_middleware<B>(req: Request<B>, next: Next<B>) -> Result<Response, StatusCode> {
+    let claims = req
+        .extensions()
+        .get::<Claims>()
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    if claims.has_role("admin") {
+        Ok(next.run(req).await)
+    } else {
+        Err(StatusCode::FORBIDDEN)
+    }
+}
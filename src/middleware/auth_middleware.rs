@@ -1,16 +1,19 @@
-use crate::{models::user::Claims};
+use crate::{app_state::AppState, repositories::auth_repository::AuthRepository};
 use axum::{
-    body::Body,
     http::{Request, StatusCode},
     middleware::Next,
     response::Response,
+    Extension,
 };
-use jsonwebtoken::{decode, DecodingKey, Validation};
-use std::env;
 
 use tracing::debug;
+use uuid::Uuid;
 
-pub async fn auth_middleware<B>(mut req: Request<B>, next: Next<B>) -> Result<Response, StatusCode> {
+pub async fn auth_middleware<B>(
+    Extension(state): Extension<AppState>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Result<Response, StatusCode> {
     // Extract the token from the request's Authorization header
     if let Some(auth_header) = req.headers().get("Authorization") {
         if let Ok(auth_str) = auth_header.to_str() {
@@ -18,24 +21,25 @@ pub async fn auth_middleware<B>(mut req: Request<B>, next: Next<B>) -> Result<Re
                 let token = token.trim(); // Remove extra spaces
                 debug!("Received JWT token: {}", token);
 
-                // Retrieve the secret key from environment variables
-                let secret_key = env::var("JWT_SECRET_KEY").map_err(|_| {
-                    eprintln!("Error: JWT_SECRET_KEY not found in .env file.");
-                    StatusCode::INTERNAL_SERVER_ERROR
-                })?;
+                // Validate the token against the key named in its header
+                match state.keys.decode(token) {
+                    Ok(claims) => {
+                        debug!("Valid JWT token. User ID: {}", claims.sub);
 
-                // Validate the token
-                match decode::<Claims>(
-                    token,
-                    &DecodingKey::from_secret(secret_key.as_bytes()),
-                    &Validation::default(),
-                ) {
-                    Ok(token_data) => {
-                        let user_id = token_data.claims.sub;
-                        debug!("Valid JWT token. User ID: {}", user_id);
-                        
-                        // Insert the user_id into the request extensions for later use
-                        req.extensions_mut().insert(user_id);
+                        // Reject tokens whose session has been revoked (logout, password reset, ...).
+                        // Sessions confirmed in the last few seconds are not checked again.
+                        let (user_id, session_id) =
+                            match (Uuid::parse_str(&claims.sub), Uuid::parse_str(&claims.sid)) {
+                                (Ok(user_id), Ok(session_id)) => (user_id, session_id),
+                                _ => return Err(StatusCode::UNAUTHORIZED),
+                            };
+                        if !state.sessions.is_active(session_id, user_id) {
+                            check_session(&state, session_id, user_id).await?;
+                        }
+
+                        // Insert the user_id and the claims into the request extensions for later use
+                        req.extensions_mut().insert(claims.sub.clone());
+                        req.extensions_mut().insert(claims);
                         
                         return Ok(next.run(req).await);
                     }
@@ -53,5 +57,24 @@ pub async fn auth_middleware<B>(mut req: Request<B>, next: Next<B>) -> Result<Re
     Err(StatusCode::UNAUTHORIZED)
 }
 
-
-
+// Looks the session up in the database and caches it if it is still active
+async fn check_session(state: &AppState, session_id: Uuid, user_id: Uuid) -> Result<(), StatusCode> {
+    let client = state.db.get().await.map_err(|e| {
+        eprintln!("Database connection error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    match AuthRepository::is_session_active(&client, session_id, user_id).await {
+        Ok(true) => {
+            state.sessions.remember(session_id, user_id);
+            Ok(())
+        }
+        Ok(false) => {
+            eprintln!("Error: session {} is no longer active", session_id);
+            Err(StatusCode::UNAUTHORIZED)
+        }
+        Err(e) => {
+            eprintln!("Error checking session: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
@@ -1,3 +1,5 @@
+pub mod admin_middleware;
 pub mod auth_middleware;
 pub mod rate_limit_layer;
+pub mod two_factor_middleware;
 pub mod ws_auth_middleware;
\ No newline at end of file
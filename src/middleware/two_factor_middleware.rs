@@ -0,0 +1,48 @@
+use axum::{
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::Response,
+    Extension,
+};
+use uuid::Uuid;
+
+use crate::{app_state::AppState, repositories::two_factor_repository::TwoFactorRepository};
+
+/// Allows the request through only if the user has enabled two-factor authentication,
+/// when `REQUIRE_TWO_FACTOR` is set.
+///
+/// Must run after `auth_middleware`, `ws_auth_middleware` or `event_stream_auth_middleware`,
+/// which identify the user.
+pub async fn two_factor_middleware<B>(
+    Extension(state): Extension<AppState>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Result<Response, (StatusCode, String)> {
+    if !state.config.two_factor.required_for_chats {
+        return Ok(next.run(req).await);
+    }
+
+    // The WebSocket and event stream middlewares pass the user in the state they insert
+    let user_id = req
+        .extensions()
+        .get::<String>()
+        .and_then(|user_id| Uuid::parse_str(user_id).ok())
+        .or(state.current_user_id)
+        .ok_or((StatusCode::UNAUTHORIZED, "Not authenticated".to_string()))?;
+
+    let client = state.db.get().await.map_err(|e| {
+        eprintln!("Database connection error: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Database error".to_string())
+    })?;
+    match TwoFactorRepository::get_totp_state(&client, user_id).await {
+        Ok(Some(totp)) if totp.enabled => Ok(next.run(req).await),
+        Ok(_) => Err((
+            StatusCode::FORBIDDEN,
+            "Two-factor authentication must be enabled to use chats".to_string(),
+        )),
+        Err(e) => {
+            eprintln!("Error checking two-factor authentication: {}", e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Database error".to_string()))
+        }
+    }
+}
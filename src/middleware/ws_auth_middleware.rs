@@ -6,12 +6,19 @@ use serde::Deserialize;
 use tracing::{info, error};
 use uuid::Uuid;
 
-use crate::{app_state::AppState, repositories::auth_repository::AuthRepository};
+use crate::{app_state::AppState, repositories::auth_repository::AuthRepository, websocket::codec::WireFormat};
 
 #[derive(Deserialize, Debug)]
 pub struct WebSocketParams {
     pub token: Option<String>,  // Optional token from query parameters
     pub chat_id: Uuid,          // The chat ID associated with the request
+    pub last_seq: Option<i64>,  // Last message sequence number the client has, to resume
+    pub format: Option<WireFormat>, // Frame encoding, when no subprotocol was negotiated
+}
+
+#[derive(Deserialize, Debug)]
+pub struct EventStreamParams {
+    pub token: Option<String>,  // Optional token from query parameters, since EventSource cannot set headers
 }
 
 pub async fn ws_auth_middleware<B>(
@@ -24,7 +31,7 @@ pub async fn ws_auth_middleware<B>(
     info!("WebSocket connection attempt - Chat ID: {}", params.chat_id);
 
     // Extract token from query params or Authorization header
-    let token = extract_token(&params, &headers)
+    let token = extract_token(params.token.as_ref(), &headers)
         .map_err(|e| {
             error!("Token extraction failed: {}", e);
             (StatusCode::UNAUTHORIZED, e.to_string())
@@ -40,7 +47,7 @@ pub async fn ws_auth_middleware<B>(
     })?;
 
     // Verify session and get user ID
-    let user_id = AuthRepository::verify_session_token(&client, &token)
+    let (user_id, session_id) = AuthRepository::verify_session_token(&client, &token)
         .await
         .map_err(|e| {
             error!("Session verification failed: {}", e);
@@ -53,9 +60,10 @@ pub async fn ws_auth_middleware<B>(
 
     info!("Session verified for user: {}", user_id);
 
-    // Create new state with user ID
+    // Create new state with user ID and session ID
     let mut new_state = state.clone();
     new_state.current_user_id = Some(user_id);
+    new_state.current_session_id = Some(session_id);
 
     // Add chat access verification here if needed
     verify_chat_access(&client, user_id, params.chat_id).await
@@ -72,9 +80,54 @@ pub async fn ws_auth_middleware<B>(
     Ok(next.run(request).await)
 }
 
+// Authenticates `GET /events` the same way as the WebSocket, without a chat to check
+pub async fn event_stream_auth_middleware<B>(
+    Query(params): Query<EventStreamParams>,
+    headers: HeaderMap,
+    Extension(state): Extension<AppState>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Result<Response, (StatusCode, String)> {
+    let token = extract_token(params.token.as_ref(), &headers)
+        .map_err(|e| {
+            error!("Token extraction failed: {}", e);
+            (StatusCode::UNAUTHORIZED, e.to_string())
+        })?;
+
+    let client = state.db.get().await.map_err(|e| {
+        error!("Database connection failed: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to establish database connection".to_string(),
+        )
+    })?;
+
+    let (user_id, session_id) = AuthRepository::verify_session_token(&client, &token)
+        .await
+        .map_err(|e| {
+            error!("Session verification failed: {}", e);
+            (StatusCode::UNAUTHORIZED, "Invalid or expired session".to_string())
+        })?
+        .ok_or_else(|| {
+            error!("No session found for token");
+            (StatusCode::UNAUTHORIZED, "Session not found".to_string())
+        })?;
+
+    info!("Event stream session verified for user: {}", user_id);
+
+    let mut new_state = state.clone();
+    new_state.current_user_id = Some(user_id);
+    new_state.current_session_id = Some(session_id);
+
+    let mut request = request;
+    request.extensions_mut().insert(new_state);
+
+    Ok(next.run(request).await)
+}
+
 // Helper function to extract token from request
-fn extract_token(params: &WebSocketParams, headers: &HeaderMap) -> Result<String, &'static str> {
-    match (params.token.as_ref(), headers.get("Authorization")) {
+fn extract_token(query_token: Option<&String>, headers: &HeaderMap) -> Result<String, &'static str> {
+    match (query_token, headers.get("Authorization")) {
         (Some(token), _) => {
             info!("Using token from query params");
             Ok(token.clone())
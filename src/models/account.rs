@@ -0,0 +1,86 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::{block::BlockedUser, profile::OwnProfile};
+
+#[derive(Deserialize)]
+pub struct DeleteAccountRequest {
+    /// Current password, required to confirm the deletion
+    pub password: String,
+}
+
+#[derive(Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct ChangeEmailRequest {
+    #[validate(email(message = "Invalid email address"))]
+    pub new_email: String,
+    /// Current password, required to confirm the change
+    pub password: String,
+}
+
+/// Archive of the personal data held about a user
+#[derive(Serialize)]
+pub struct DataExport {
+    pub exported_at: NaiveDateTime,
+    pub profile: OwnProfile,
+    pub roles: Vec<String>,
+    pub sessions: Vec<ExportedSession>,
+    pub memberships: Vec<ExportedMembership>,
+    pub invitations: Vec<ExportedInvitation>,
+    pub messages: Vec<ExportedMessage>,
+    pub blocked_users: Vec<BlockedUser>,
+    pub audit_events: Vec<AuditEvent>,
+}
+
+/// A login session; the token itself is never exported
+#[derive(Serialize)]
+pub struct ExportedSession {
+    pub id: Uuid,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Serialize)]
+pub struct ExportedMembership {
+    pub chat_id: Uuid,
+    pub chat_name: Option<String>,
+    pub status: String,
+    pub is_creator: bool,
+}
+
+#[derive(Serialize)]
+pub struct ExportedInvitation {
+    pub id: Uuid,
+    pub chat_id: Uuid,
+    /// "sent" or "received"
+    pub direction: String,
+    /// Username of the other party, if their account still exists
+    pub other_username: Option<String>,
+    pub status: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Serialize)]
+pub struct ExportedMessage {
+    pub id: Uuid,
+    pub chat_id: Uuid,
+    pub message_text: String,
+    pub timestamp: NaiveDateTime,
+}
+
+/// An entry of the account audit trail
+#[derive(Serialize)]
+pub struct AuditEvent {
+    pub event: String,
+    pub details: Value,
+    pub ip_address: Option<String>,
+    pub created_at: NaiveDateTime,
+}
@@ -0,0 +1,19 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A user on the block list of the authenticated user
+#[derive(Serialize)]
+pub struct BlockedUser {
+    pub id: Uuid,
+    pub username: String,
+    pub blocked_at: NaiveDateTime,
+}
+
+/// Pushed to the blocker's own connections when their block list changes, so that they flag
+/// the messages of that user from then on
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BlockListUpdate {
+    pub user_id: Uuid,
+    pub blocked: bool,
+}
@@ -0,0 +1,60 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FriendRequest {
+    pub id: Uuid,
+    pub requester_id: Uuid,
+    pub addressee_id: Uuid,
+    pub status: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SendFriendRequest {
+    pub username: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FriendRequestResponse {
+    pub request_id: Uuid,
+    pub accept: bool,
+}
+
+/// A pending friend request, as listed to either side
+#[derive(Debug, Serialize)]
+pub struct PendingFriendRequest {
+    pub id: Uuid,
+    /// The other user: the requester for incoming requests, the addressee for outgoing ones
+    pub user_id: Uuid,
+    pub username: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FriendRequestList {
+    pub incoming: Vec<PendingFriendRequest>,
+    pub outgoing: Vec<PendingFriendRequest>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Contact {
+    pub id: Uuid,
+    pub username: String,
+    pub display_name: Option<String>,
+    pub online: bool,
+    /// When the friend request was accepted
+    pub since: NaiveDateTime,
+}
+
+/// Pushed to the addressee of a new request, and to the requester once it is accepted
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FriendRequestNotification {
+    pub request_id: Uuid,
+    pub user_id: Uuid,
+    pub username: String,
+    pub status: String,
+    pub timestamp: NaiveDateTime,
+}
@@ -1,17 +1,41 @@
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Message {
     pub id: Uuid,
     pub chat_id: Uuid,  
-    pub sender_id: Uuid, 
+    /// `None` once the author has deleted their account
+    pub sender_id: Option<Uuid>,
     pub message_text: String,
-    pub timestamp: String, 
+    pub timestamp: NaiveDateTime,
+    /// Position of the message in its chat, starting at 1 and without holes
+    pub seq: i64,
+    /// Id the sending client gave the message, if any
+    pub client_id: Option<Uuid>,
+    /// Whether the viewer has blocked the sender, so clients can hide the message
+    pub sender_blocked: bool,
 }
 
 #[derive(Deserialize)]
 pub struct SendMessageRequest {
     pub chat_id: Uuid,
     pub message: String,
+    /// Client-generated id; sending the same id again returns the stored message instead of a copy
+    pub client_id: Option<Uuid>,
+}
+
+/// Optional bounds on the messages returned, e.g. to backfill a gap reported over the WebSocket
+#[derive(Deserialize, Default)]
+pub struct MessageRangeQuery {
+    /// Only messages sent strictly after this time
+    pub after: Option<NaiveDateTime>,
+    /// Only messages sent strictly before this time
+    pub before: Option<NaiveDateTime>,
+    /// Only messages with a sequence number strictly greater than this one
+    pub after_seq: Option<i64>,
+    /// Only the latest this many messages of the range, as set by the server for replays
+    #[serde(skip)]
+    pub latest: Option<i64>,
 }
@@ -1,4 +1,11 @@
 pub mod user;
+pub mod account;
+pub mod block;
+pub mod friend;
 pub mod message;
 pub mod chat;
-pub mod invitation;
\ No newline at end of file
+pub mod invitation;
+pub mod password;
+pub mod presence;
+pub mod profile;
+pub mod two_factor;
\ No newline at end of file
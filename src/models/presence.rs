@@ -0,0 +1,19 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::websocket::types::UserStatus;
+
+#[derive(Deserialize)]
+pub struct PresenceQuery {
+    /// Comma-separated user IDs
+    pub users: String,
+}
+
+#[derive(Serialize)]
+pub struct UserPresence {
+    pub user_id: Uuid,
+    pub username: String,
+    pub status: UserStatus,
+    pub last_seen_at: Option<NaiveDateTime>,
+}
@@ -0,0 +1,66 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+/// Public profile of a user, as shown to other users
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UserProfile {
+    pub id: Uuid,
+    pub username: String,
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    /// Reference to the avatar attachment (opaque to the server)
+    pub avatar_ref: Option<String>,
+    pub status_text: Option<String>,
+    pub status_emoji: Option<String>,
+}
+
+/// Profile of the authenticated user, including private account details
+#[derive(Serialize)]
+pub struct OwnProfile {
+    #[serde(flatten)]
+    pub profile: UserProfile,
+    pub email: String,
+    pub email_verified: bool,
+    pub two_factor_enabled: bool,
+    /// Whether the user appears in directory search results
+    pub discoverable: bool,
+    /// When the account will be deleted, if deletion was requested
+    pub deletion_scheduled_at: Option<NaiveDateTime>,
+}
+
+/// Partial profile update: omitted fields are left unchanged, empty strings clear a field
+#[derive(Deserialize, Validate)]
+pub struct UpdateProfileRequest {
+    #[validate(length(max = 50, message = "The display name must be at most 50 characters long"))]
+    pub display_name: Option<String>,
+    #[validate(length(max = 500, message = "The bio must be at most 500 characters long"))]
+    pub bio: Option<String>,
+    #[validate(length(max = 255, message = "The avatar reference must be at most 255 characters long"))]
+    pub avatar_ref: Option<String>,
+    #[validate(length(max = 100, message = "The status text must be at most 100 characters long"))]
+    pub status_text: Option<String>,
+    #[validate(length(max = 16, message = "The status emoji must be at most 16 characters long"))]
+    pub status_emoji: Option<String>,
+    /// Whether the user appears in directory search results
+    pub discoverable: Option<bool>,
+}
+
+/// Query string of the user directory search
+#[derive(Deserialize)]
+pub struct UserSearchQuery {
+    pub q: String,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// One page of user directory search results, best matches first
+#[derive(Serialize)]
+pub struct UserSearchResults {
+    pub results: Vec<UserProfile>,
+    pub limit: i64,
+    pub offset: i64,
+    /// Whether another page is available at `offset + limit`
+    pub has_more: bool,
+}
@@ -9,7 +9,7 @@ pub struct RegisterData {
     pub username: String,
     #[validate(email(message = "Invalid email address"))]
     pub email: String,
-    #[validate(length(min = 6, message = "Password must be at least 6 characters long"))]
+    /// Checked against the configured password policy rather than a fixed rule
     pub password: String,
 }
 
@@ -19,8 +19,46 @@ pub struct LoginData {
     pub password: String,
 }
 
-#[derive(Serialize, Deserialize)]
+/// Stored credentials and account flags needed to authenticate a user
+pub struct UserCredentials {
+    pub id: Uuid,
+    pub password: String,
+    pub email_verified: bool,
+    pub totp_enabled: bool,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct ResendVerificationRequest {
+    #[validate(email(message = "Invalid email address"))]
+    pub email: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Claims {
-    pub sub: String,  
-    pub exp: usize, 
+    /// User ID
+    pub sub: String,
+    /// Expiration time (seconds since the epoch)
+    pub exp: usize,
+    /// Issued at
+    pub iat: usize,
+    /// Not valid before
+    pub nbf: usize,
+    /// Issuer
+    pub iss: String,
+    /// Audience
+    pub aud: String,
+    /// Unique token ID
+    pub jti: String,
+    /// ID of the `sessions` row the token belongs to
+    pub sid: String,
+    /// Global roles of the user when the token was issued
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+impl Claims {
+    /// Checks whether the token grants a global role (e.g. "admin")
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,186 @@
+// repositories/account_repository.rs
+
+use chrono::NaiveDateTime;
+use deadpool_postgres::{Client, Transaction};
+use tokio_postgres::Error;
+use uuid::Uuid;
+
+use crate::models::account::{
+    ExportedInvitation, ExportedMembership, ExportedMessage, ExportedSession,
+};
+
+pub struct AccountRepository;
+
+impl AccountRepository {
+    /// Lists the sessions of a user, without their tokens
+    pub async fn get_sessions(client: &Client, user_id: Uuid) -> Result<Vec<ExportedSession>, Error> {
+        let query = "SELECT id, expires_at FROM sessions WHERE user_id = $1 ORDER BY expires_at";
+        let rows = client.query(query, &[&user_id]).await?;
+        Ok(rows
+            .iter()
+            .map(|row| ExportedSession {
+                id: row.get(0),
+                expires_at: row.get(1),
+            })
+            .collect())
+    }
+
+    /// Lists the chat memberships of a user
+    pub async fn get_memberships(client: &Client, user_id: Uuid) -> Result<Vec<ExportedMembership>, Error> {
+        let query = "
+            SELECT cm.chat_id, c.name, cm.status, cm.is_creator
+            FROM chat_members cm
+            JOIN chats c ON c.id = cm.chat_id
+            WHERE cm.user_id = $1
+        ";
+        let rows = client.query(query, &[&user_id]).await?;
+        Ok(rows
+            .iter()
+            .map(|row| ExportedMembership {
+                chat_id: row.get(0),
+                chat_name: row.get(1),
+                status: row.get(2),
+                is_creator: row.get(3),
+            })
+            .collect())
+    }
+
+    /// Lists the invitations a user sent or received
+    pub async fn get_invitations(client: &Client, user_id: Uuid) -> Result<Vec<ExportedInvitation>, Error> {
+        let query = "
+            SELECT i.id, i.chat_id,
+                   CASE WHEN i.inviter_id = $1 THEN 'sent' ELSE 'received' END,
+                   other.username, i.status, i.created_at, i.updated_at
+            FROM invites i
+            LEFT JOIN users other
+                ON other.id = CASE WHEN i.inviter_id = $1 THEN i.invitee_id ELSE i.inviter_id END
+            WHERE i.inviter_id = $1 OR i.invitee_id = $1
+            ORDER BY i.created_at
+        ";
+        let rows = client.query(query, &[&user_id]).await?;
+        Ok(rows
+            .iter()
+            .map(|row| ExportedInvitation {
+                id: row.get(0),
+                chat_id: row.get(1),
+                direction: row.get(2),
+                other_username: row.get(3),
+                status: row.get(4),
+                created_at: row.get(5),
+                updated_at: row.get(6),
+            })
+            .collect())
+    }
+
+    /// Lists the messages a user wrote
+    pub async fn get_authored_messages(client: &Client, user_id: Uuid) -> Result<Vec<ExportedMessage>, Error> {
+        let query = "
+            SELECT id, chat_id, message_text, timestamp
+            FROM messages
+            WHERE sender_id = $1
+            ORDER BY timestamp
+        ";
+        let rows = client.query(query, &[&user_id]).await?;
+        Ok(rows
+            .iter()
+            .map(|row| ExportedMessage {
+                id: row.get(0),
+                chat_id: row.get(1),
+                message_text: row.get(2),
+                timestamp: row.get(3),
+            })
+            .collect())
+    }
+
+    /// Stores a pending email change, invalidating any change still outstanding for the user
+    pub async fn create_email_change(
+        transaction: &Transaction<'_>,
+        user_id: Uuid,
+        new_email: &str,
+        token_hash: &str,
+        expires_at: NaiveDateTime,
+    ) -> Result<(), Error> {
+        let invalidate_query = "
+            UPDATE email_changes SET used_at = NOW()
+            WHERE user_id = $1 AND used_at IS NULL
+        ";
+        transaction.execute(invalidate_query, &[&user_id]).await?;
+
+        let query = "
+            INSERT INTO email_changes (user_id, new_email, token_hash, expires_at)
+            VALUES ($1, $2, $3, $4)
+        ";
+        transaction
+            .execute(query, &[&user_id, &new_email, &token_hash, &expires_at])
+            .await?;
+        Ok(())
+    }
+
+    /// Redeems a valid email change token and returns the user and the new address
+    pub async fn consume_email_change(
+        transaction: &Transaction<'_>,
+        token_hash: &str,
+    ) -> Result<Option<(Uuid, String)>, Error> {
+        let query = "
+            UPDATE email_changes SET used_at = NOW()
+            WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+            RETURNING user_id, new_email
+        ";
+        let row = transaction.query_opt(query, &[&token_hash]).await?;
+        Ok(row.map(|row| (row.get(0), row.get(1))))
+    }
+
+    /// Replaces the email of a user with a confirmed address and returns the previous one
+    pub async fn update_email(
+        transaction: &Transaction<'_>,
+        user_id: Uuid,
+        new_email: &str,
+    ) -> Result<String, Error> {
+        let query = "
+            WITH previous AS (SELECT email FROM users WHERE id = $1 FOR UPDATE)
+            UPDATE users SET email = $2, email_verified = TRUE
+            FROM previous
+            WHERE users.id = $1
+            RETURNING previous.email
+        ";
+        let row = transaction.query_one(query, &[&user_id, &new_email]).await?;
+        Ok(row.get(0))
+    }
+
+    /// Marks an account for deletion at the given time
+    pub async fn schedule_deletion(
+        client: &Client,
+        user_id: Uuid,
+        delete_at: NaiveDateTime,
+    ) -> Result<(), Error> {
+        let query = "UPDATE users SET deletion_scheduled_at = $2 WHERE id = $1";
+        client.execute(query, &[&user_id, &delete_at]).await?;
+        Ok(())
+    }
+
+    /// Cancels a pending deletion; returns whether one was pending
+    pub async fn cancel_deletion(client: &Client, user_id: Uuid) -> Result<bool, Error> {
+        let query = "
+            UPDATE users SET deletion_scheduled_at = NULL
+            WHERE id = $1 AND deletion_scheduled_at IS NOT NULL
+        ";
+        let updated = client.execute(query, &[&user_id]).await?;
+        Ok(updated > 0)
+    }
+
+    /// Deletes an account right away
+    ///
+    /// Sessions, memberships, invitations and tokens cascade; authored messages
+    /// stay in their chats with an empty sender.
+    pub async fn delete_user(client: &Client, user_id: Uuid) -> Result<(), Error> {
+        client.execute("DELETE FROM users WHERE id = $1", &[&user_id]).await?;
+        Ok(())
+    }
+
+    /// Deletes every account whose grace period has ended and returns their IDs
+    pub async fn purge_due_accounts(client: &Client) -> Result<Vec<Uuid>, Error> {
+        let query = "DELETE FROM users WHERE deletion_scheduled_at <= NOW() RETURNING id";
+        let rows = client.query(query, &[]).await?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+}
@@ -0,0 +1,56 @@
+// repositories/audit_repository.rs
+
+use deadpool_postgres::{Client, Transaction};
+use serde_json::Value;
+use std::net::IpAddr;
+use tokio_postgres::Error;
+use uuid::Uuid;
+
+use crate::models::account::AuditEvent;
+
+pub struct AuditRepository;
+
+impl AuditRepository {
+    /// Appends an event to the audit trail of a user
+    ///
+    /// Takes a transaction so the event is only recorded if the change it describes is committed.
+    pub async fn record(
+        transaction: &Transaction<'_>,
+        user_id: Uuid,
+        event: &str,
+        details: &Value,
+        ip: Option<IpAddr>,
+    ) -> Result<(), Error> {
+        let query = "
+            INSERT INTO audit_log (user_id, event, details, ip_address)
+            VALUES ($1, $2, $3::TEXT::JSONB, $4)
+        ";
+        transaction
+            .execute(
+                query,
+                &[&user_id, &event, &details.to_string(), &ip.map(|ip| ip.to_string())],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Lists the audit trail of a user, oldest first
+    pub async fn get_events(client: &Client, user_id: Uuid) -> Result<Vec<AuditEvent>, Error> {
+        let query = "
+            SELECT event, details::TEXT, ip_address, created_at
+            FROM audit_log
+            WHERE user_id = $1
+            ORDER BY created_at
+        ";
+        let rows = client.query(query, &[&user_id]).await?;
+        Ok(rows
+            .iter()
+            .map(|row| AuditEvent {
+                event: row.get(0),
+                details: serde_json::from_str(row.get(1)).unwrap_or(Value::Null),
+                ip_address: row.get(2),
+                created_at: row.get(3),
+            })
+            .collect())
+    }
+}
@@ -3,7 +3,7 @@
 use chrono::Utc;
 use deadpool_postgres::Client;
 use uuid::Uuid;
-use crate::models::user::RegisterData;
+use crate::models::user::{RegisterData, UserCredentials};
 
 pub struct AuthRepository;
 
@@ -18,56 +18,112 @@ impl AuthRepository {
         Ok(count > 0)
     }
 
-    /// Creates a new user in the database
+    /// Creates a new user in the database and returns its ID
     pub async fn create_user(
         client: &Client, 
         user_data: &RegisterData, 
         hashed_password: &str
-    ) -> Result<(), tokio_postgres::Error> {
-        let query = "INSERT INTO users (username, email, password) VALUES ($1, $2, $3)";
-        client
-            .execute(query, &[&user_data.username, &user_data.email, &hashed_password])
+    ) -> Result<Uuid, tokio_postgres::Error> {
+        let query = "INSERT INTO users (username, email, password) VALUES ($1, $2, $3) RETURNING id";
+        let row = client
+            .query_one(query, &[&user_data.username, &user_data.email, &hashed_password])
             .await?;
-        Ok(())
+        Ok(row.get(0))
     }
 
     /// Gets user credentials for authentication
     pub async fn get_user_credentials(
         client: &Client,
         username: &str,
-    ) -> Result<Option<(Uuid, String)>, tokio_postgres::Error> {
-        let query = "SELECT id, password FROM users WHERE username = $1";
+    ) -> Result<Option<UserCredentials>, tokio_postgres::Error> {
+        let query = "SELECT id, password, email_verified, totp_enabled FROM users WHERE username = $1";
         let row = client.query_opt(query, &[&username]).await?;
         
-        Ok(row.map(|row| {
-            let user_id: Uuid = row.get(0);
-            let password: String = row.get(1);
-            (user_id, password)
+        Ok(row.map(|row| UserCredentials {
+            id: row.get(0),
+            password: row.get(1),
+            email_verified: row.get(2),
+            totp_enabled: row.get(3),
         }))
     }
 
+    /// Checks whether a user has verified their email address
+    pub async fn is_email_verified(client: &Client, user_id: Uuid) -> Result<bool, tokio_postgres::Error> {
+        let query = "SELECT email_verified FROM users WHERE id = $1";
+        let row = client.query_opt(query, &[&user_id]).await?;
+        Ok(row.map(|row| row.get(0)).unwrap_or(false))
+    }
+
+    /// Finds a user by email, returning their ID, username and verification flag
+    pub async fn find_verification_status_by_email(
+        client: &Client,
+        email: &str,
+    ) -> Result<Option<(Uuid, String, bool)>, tokio_postgres::Error> {
+        let query = "SELECT id, username, email_verified FROM users WHERE email = $1";
+        let row = client.query_opt(query, &[&email]).await?;
+        Ok(row.map(|row| (row.get(0), row.get(1), row.get(2))))
+    }
+
+    /// Finds a user by email, returning their ID and username
+    pub async fn find_user_by_email(
+        client: &Client,
+        email: &str,
+    ) -> Result<Option<(Uuid, String)>, tokio_postgres::Error> {
+        let query = "SELECT id, username FROM users WHERE email = $1";
+        let row = client.query_opt(query, &[&email]).await?;
+        Ok(row.map(|row| (row.get(0), row.get(1))))
+    }
+
+    /// Returns the password hash of a user, for re-authentication of sensitive actions
+    pub async fn get_password_hash(client: &Client, user_id: Uuid) -> Result<Option<String>, tokio_postgres::Error> {
+        let query = "SELECT password FROM users WHERE id = $1";
+        let row = client.query_opt(query, &[&user_id]).await?;
+        Ok(row.map(|row| row.get(0)))
+    }
+
+    /// Returns the global roles of a user (e.g. "admin")
+    pub async fn get_roles(client: &Client, user_id: Uuid) -> Result<Vec<String>, tokio_postgres::Error> {
+        let query = "SELECT roles FROM users WHERE id = $1";
+        let row = client.query_opt(query, &[&user_id]).await?;
+        Ok(row.map(|row| row.get(0)).unwrap_or_default())
+    }
+
     /// Creates a new session for authenticated user
+    ///
+    /// The session ID is chosen by the caller so it can be embedded in the token as `sid`.
     pub async fn create_session(
         client: &Client,
+        session_id: Uuid,
         user_id: Uuid,
         token: &str,
     ) -> Result<(), tokio_postgres::Error> {
         let expires_at = (Utc::now() + chrono::Duration::days(30)).naive_utc();
-        let query = "INSERT INTO sessions (user_id, token, expires_at) VALUES ($1, $2, $3)";
+        let query = "INSERT INTO sessions (id, user_id, token, expires_at) VALUES ($1, $2, $3, $4)";
         
         client
-            .execute(query, &[&user_id, &token, &expires_at])
+            .execute(query, &[&session_id, &user_id, &token, &expires_at])
             .await?;
         Ok(())
     }
 
-    /// Verifies if a session token is valid
+    /// Checks that a session still exists (i.e. has not been revoked) and belongs to the user
+    pub async fn is_session_active(
+        client: &Client,
+        session_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<bool, tokio_postgres::Error> {
+        let query = "SELECT EXISTS(SELECT 1 FROM sessions WHERE id = $1 AND user_id = $2 AND expires_at > NOW())";
+        let row = client.query_one(query, &[&session_id, &user_id]).await?;
+        Ok(row.get(0))
+    }
+
+    /// Verifies if a session token is valid, returning the user ID and the session ID
     pub async fn verify_session_token(
         client: &Client,
         token: &str,
-    ) -> Result<Option<Uuid>, tokio_postgres::Error> {
-        let query = "SELECT user_id FROM sessions WHERE token = $1 AND expires_at > NOW()";
+    ) -> Result<Option<(Uuid, Uuid)>, tokio_postgres::Error> {
+        let query = "SELECT user_id, id FROM sessions WHERE token = $1 AND expires_at > NOW()";
         let row = client.query_opt(query, &[&token]).await?;
-        Ok(row.map(|row| row.get(0)))
+        Ok(row.map(|row| (row.get(0), row.get(1))))
     }
 }
\ No newline at end of file
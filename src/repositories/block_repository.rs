@@ -0,0 +1,63 @@
+// repositories/block_repository.rs
+
+use deadpool_postgres::Client;
+use tokio_postgres::Error;
+use uuid::Uuid;
+
+use crate::models::block::BlockedUser;
+
+pub struct BlockRepository;
+
+impl BlockRepository {
+    /// Adds a user to the block list of another; blocking twice is a no-op
+    pub async fn block(client: &Client, blocker_id: Uuid, blocked_id: Uuid) -> Result<(), Error> {
+        let query = "
+            INSERT INTO user_blocks (blocker_id, blocked_id)
+            VALUES ($1, $2)
+            ON CONFLICT (blocker_id, blocked_id) DO NOTHING
+        ";
+        client.execute(query, &[&blocker_id, &blocked_id]).await?;
+        Ok(())
+    }
+
+    /// Removes a user from a block list; returns whether they were blocked
+    pub async fn unblock(client: &Client, blocker_id: Uuid, blocked_id: Uuid) -> Result<bool, Error> {
+        let query = "DELETE FROM user_blocks WHERE blocker_id = $1 AND blocked_id = $2";
+        let deleted = client.execute(query, &[&blocker_id, &blocked_id]).await?;
+        Ok(deleted > 0)
+    }
+
+    /// Checks whether `blocker_id` has blocked `blocked_id`
+    pub async fn has_blocked(client: &Client, blocker_id: Uuid, blocked_id: Uuid) -> Result<bool, Error> {
+        let query = "SELECT 1 FROM user_blocks WHERE blocker_id = $1 AND blocked_id = $2";
+        let row = client.query_opt(query, &[&blocker_id, &blocked_id]).await?;
+        Ok(row.is_some())
+    }
+
+    /// Returns the IDs of the users a user has blocked
+    pub async fn get_blocked_ids(client: &Client, blocker_id: Uuid) -> Result<Vec<Uuid>, Error> {
+        let query = "SELECT blocked_id FROM user_blocks WHERE blocker_id = $1";
+        let rows = client.query(query, &[&blocker_id]).await?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    /// Lists the users a user has blocked, most recent first
+    pub async fn get_blocked_users(client: &Client, blocker_id: Uuid) -> Result<Vec<BlockedUser>, Error> {
+        let query = "
+            SELECT u.id, u.username, b.created_at
+            FROM user_blocks b
+            JOIN users u ON u.id = b.blocked_id
+            WHERE b.blocker_id = $1
+            ORDER BY b.created_at DESC
+        ";
+        let rows = client.query(query, &[&blocker_id]).await?;
+        Ok(rows
+            .iter()
+            .map(|row| BlockedUser {
+                id: row.get(0),
+                username: row.get(1),
+                blocked_at: row.get(2),
+            })
+            .collect())
+    }
+}
@@ -4,7 +4,7 @@ use chrono::Utc;
 use deadpool_postgres::Transaction;
 use tokio_postgres::Error;
 use uuid::Uuid;
-use crate::models::message::Message;
+use crate::models::message::{Message, MessageRangeQuery};
 
 pub struct ChatRepository;
 
@@ -30,15 +30,29 @@ impl ChatRepository {
         Ok(())
     }
 
-    /// Fetches all messages in a specific chat
-    pub async fn get_chat_messages(transaction: &Transaction<'_>, chat_id: Uuid) -> Result<Vec<Message>, Error> {
+    /// Fetches the messages of a chat within an optional time or sequence range, flagging those whose sender the viewer has blocked
+    pub async fn get_chat_messages(transaction: &Transaction<'_>, chat_id: Uuid, viewer_id: Uuid, range: &MessageRangeQuery) -> Result<Vec<Message>, Error> {
+        // A NULL limit returns the whole range
         let query = "
-            SELECT m.id, m.sender_id, m.message_text, m.timestamp 
-            FROM messages m
-            WHERE m.chat_id = $1
-            ORDER BY m.timestamp
+            SELECT * FROM (
+                SELECT m.id, m.chat_id, m.sender_id, m.message_text, m.timestamp, m.seq, m.client_id,
+                       EXISTS (
+                           SELECT 1 FROM user_blocks b
+                           WHERE b.blocker_id = $2 AND b.blocked_id = m.sender_id
+                       )
+                FROM messages m
+                WHERE m.chat_id = $1
+                  AND ($3::TIMESTAMP IS NULL OR m.timestamp > $3)
+                  AND ($4::TIMESTAMP IS NULL OR m.timestamp < $4)
+                  AND ($5::BIGINT IS NULL OR m.seq > $5)
+                ORDER BY m.seq DESC
+                LIMIT $6
+            ) AS latest
+            ORDER BY seq
         ";
-        let rows = transaction.query(query, &[&chat_id]).await?;
+        let rows = transaction
+            .query(query, &[&chat_id, &viewer_id, &range.after, &range.before, &range.after_seq, &range.latest])
+            .await?;
 
         let messages = rows
             .iter()
@@ -48,26 +62,39 @@ impl ChatRepository {
                 sender_id: row.get(2),
                 message_text: row.get(3),
                 timestamp: row.get(4),
+                seq: row.get(5),
+                client_id: row.get(6),
+                sender_blocked: row.get(7),
             })
             .collect();
 
         Ok(messages)
     }
 
-    /// Inserts a new message into the database
-    pub async fn insert_message(transaction: &Transaction<'_>, message_id: Uuid, chat_id: Uuid, sender_id: Uuid, message_text: &str) -> Result<(), Error> {
+    /// Inserts a new message into the database with the next sequence number of its chat
+    ///
+    /// Taking the number locks the chat row until the transaction ends, so messages of a
+    /// chat are committed in sequence order and a rollback gives the number back.
+    ///
+    /// Returns false, without inserting, when the sender already stored a message under
+    /// `client_id` in this chat; the transaction must then be rolled back to give the number back.
+    pub async fn insert_message(transaction: &Transaction<'_>, message_id: Uuid, chat_id: Uuid, sender_id: Uuid, message_text: &str, client_id: Option<Uuid>) -> Result<bool, Error> {
         let query = "
-            INSERT INTO messages (id, chat_id, sender_id, message_text) 
-            VALUES ($1, $2, $3, $4)
+            WITH next AS (
+                UPDATE chats SET last_seq = last_seq + 1 WHERE id = $2 RETURNING last_seq
+            )
+            INSERT INTO messages (id, chat_id, sender_id, message_text, seq, client_id)
+            SELECT $1, $2, $3, $4, last_seq, $5 FROM next
+            ON CONFLICT (chat_id, sender_id, client_id) WHERE client_id IS NOT NULL DO NOTHING
         ";
-        transaction.execute(query, &[&message_id, &chat_id, &sender_id, &message_text]).await?;
-        Ok(())
+        let inserted = transaction.execute(query, &[&message_id, &chat_id, &sender_id, &message_text, &client_id]).await?;
+        Ok(inserted == 1)
     }
 
     /// Retrieves a message by its ID
     pub async fn get_message_by_id(transaction: &Transaction<'_>, message_id: Uuid) -> Result<Message, Error> {
         let query = "
-            SELECT id, chat_id, sender_id, message_text, timestamp
+            SELECT id, chat_id, sender_id, message_text, timestamp, seq, client_id
             FROM messages
             WHERE id = $1
         ";
@@ -79,9 +106,33 @@ impl ChatRepository {
             sender_id: row.get(2),
             message_text: row.get(3),
             timestamp: row.get(4),
+            seq: row.get(5),
+            client_id: row.get(6),
+            sender_blocked: false,
         })
     }
 
+    /// Retrieves the message a sender already stored in a chat under a client id
+    pub async fn get_message_by_client_id(transaction: &Transaction<'_>, chat_id: Uuid, sender_id: Uuid, client_id: Uuid) -> Result<Option<Message>, Error> {
+        let query = "
+            SELECT id, chat_id, sender_id, message_text, timestamp, seq, client_id
+            FROM messages
+            WHERE chat_id = $1 AND sender_id = $2 AND client_id = $3
+        ";
+        let row = transaction.query_opt(query, &[&chat_id, &sender_id, &client_id]).await?;
+
+        Ok(row.map(|row| Message {
+            id: row.get(0),
+            chat_id: row.get(1),
+            sender_id: row.get(2),
+            message_text: row.get(3),
+            timestamp: row.get(4),
+            seq: row.get(5),
+            client_id: row.get(6),
+            sender_blocked: false,
+        }))
+    }
+
     /// Checks if a user is a member of a chat
     pub async fn check_user_membership(transaction: &Transaction<'_>, chat_id: Uuid, user_id: Uuid) -> Result<bool, Error> {
         let query = "
@@ -90,6 +141,18 @@ impl ChatRepository {
         let result = transaction.query_opt(query, &[&chat_id, &user_id]).await?;
         Ok(result.is_some())
     }
+
+    /// Lists the chats a user has joined or created, with the sequence number of their last message
+    pub async fn get_member_chats(transaction: &Transaction<'_>, user_id: Uuid) -> Result<Vec<(Uuid, i64)>, Error> {
+        let query = "
+            SELECT c.id, c.last_seq
+            FROM chats c
+            JOIN chat_members cm ON cm.chat_id = c.id
+            WHERE cm.user_id = $1 AND (cm.status = 'accepted' OR cm.is_creator = true)
+        ";
+        let rows = transaction.query(query, &[&user_id]).await?;
+        Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+    }
 }
 
 
@@ -0,0 +1,60 @@
+// repositories/email_verification_repository.rs
+
+use chrono::NaiveDateTime;
+use deadpool_postgres::{Client, Transaction};
+use tokio_postgres::Error;
+use uuid::Uuid;
+
+pub struct EmailVerificationRepository;
+
+impl EmailVerificationRepository {
+    /// Stores a new verification token hash for a user
+    pub async fn create_verification_token(
+        client: &Client,
+        user_id: Uuid,
+        token_hash: &str,
+        expires_at: NaiveDateTime,
+    ) -> Result<(), Error> {
+        let query = "
+            INSERT INTO email_verifications (user_id, token_hash, expires_at)
+            VALUES ($1, $2, $3)
+        ";
+        client
+            .execute(query, &[&user_id, &token_hash, &expires_at])
+            .await?;
+        Ok(())
+    }
+
+    /// Returns when the most recent verification email was issued for a user
+    pub async fn last_sent_at(
+        client: &Client,
+        user_id: Uuid,
+    ) -> Result<Option<NaiveDateTime>, Error> {
+        let query = "SELECT MAX(created_at) FROM email_verifications WHERE user_id = $1";
+        let row = client.query_one(query, &[&user_id]).await?;
+        Ok(row.get(0))
+    }
+
+    /// Redeems a valid token, marks the user's email as verified and returns the user ID
+    pub async fn consume_verification_token(
+        transaction: &Transaction<'_>,
+        token_hash: &str,
+    ) -> Result<Option<Uuid>, Error> {
+        let query = "
+            UPDATE email_verifications SET used_at = NOW()
+            WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+            RETURNING user_id
+        ";
+        let user_id: Option<Uuid> = transaction
+            .query_opt(query, &[&token_hash])
+            .await?
+            .map(|row| row.get(0));
+
+        if let Some(user_id) = user_id {
+            let verify_query = "UPDATE users SET email_verified = TRUE WHERE id = $1";
+            transaction.execute(verify_query, &[&user_id]).await?;
+        }
+
+        Ok(user_id)
+    }
+}
@@ -0,0 +1,226 @@
+// repositories/friend_repository.rs
+
+use chrono::{NaiveDateTime, Utc};
+use deadpool_postgres::{Client, Pool};
+use tokio_postgres::{error::SqlState, Row};
+use uuid::Uuid;
+use crate::models::friend::{Contact, FriendRequest, FriendRequestList, PendingFriendRequest};
+use crate::repositories::block_repository::BlockRepository;
+
+pub struct FriendRepository {
+    pool: Pool,
+}
+
+impl FriendRepository {
+    pub fn new(pool: Pool) -> Self {
+        FriendRepository { pool }
+    }
+
+    async fn client(&self) -> Result<Client, String> {
+        self.pool
+            .get()
+            .await
+            .map_err(|e| format!("Failed to get client from pool: {}", e))
+    }
+
+    pub async fn get_user_id_by_username(
+        &self,
+        username: &str,
+    ) -> Result<Option<Uuid>, String> {
+        let query = "SELECT id FROM users WHERE username = $1";
+
+        let client = self.client().await?;
+
+        match client.query_opt(query, &[&username]).await {
+            Ok(Some(row)) => Ok(Some(row.get(0))),
+            Ok(None) => Ok(None),
+            Err(e) => Err(format!("Failed to get user ID: {}", e)),
+        }
+    }
+
+    /// Checks whether `blocker_id` has blocked `blocked_id`
+    pub async fn has_blocked(
+        &self,
+        blocker_id: Uuid,
+        blocked_id: Uuid,
+    ) -> Result<bool, String> {
+        let client = self.client().await?;
+
+        BlockRepository::has_blocked(&client, blocker_id, blocked_id)
+            .await
+            .map_err(|e| format!("Failed to check block list: {}", e))
+    }
+
+    /// Creates a pending friend request
+    ///
+    /// Returns `Ok(None)` when the two users already have a pending request
+    /// (in either direction) or are already contacts.
+    pub async fn create_request(
+        &self,
+        requester_id: Uuid,
+        addressee_id: Uuid,
+    ) -> Result<Option<FriendRequest>, String> {
+        let now = Utc::now().naive_utc();
+
+        let query = "
+            INSERT INTO friend_requests (id, requester_id, addressee_id, status, created_at, updated_at)
+            VALUES ($1, $2, $3, 'pending', $4, $4)
+            RETURNING id, requester_id, addressee_id, status, created_at, updated_at
+        ";
+
+        let client = self.client().await?;
+
+        match client
+            .query_one(query, &[&Uuid::new_v4(), &requester_id, &addressee_id, &now])
+            .await
+        {
+            Ok(row) => Ok(Some(row_to_request(&row))),
+            Err(e) if e.code() == Some(&SqlState::UNIQUE_VIOLATION) => Ok(None),
+            Err(e) => Err(format!("Failed to create friend request: {}", e)),
+        }
+    }
+
+    /// Accepts or declines a pending request addressed to the user
+    pub async fn update_request_status(
+        &self,
+        request_id: Uuid,
+        addressee_id: Uuid,
+        accepted: bool,
+    ) -> Result<FriendRequest, String> {
+        let status = if accepted { "accepted" } else { "rejected" };
+        let now = Utc::now().naive_utc();
+
+        let query = "
+            UPDATE friend_requests
+            SET status = $1, updated_at = $2
+            WHERE id = $3 AND addressee_id = $4 AND status = 'pending'
+            RETURNING id, requester_id, addressee_id, status, created_at, updated_at
+        ";
+
+        let client = self.client().await?;
+
+        let row = client
+            .query_opt(query, &[&status, &now, &request_id, &addressee_id])
+            .await
+            .map_err(|e| format!("Failed to update friend request: {}", e))?;
+
+        match row {
+            Some(row) => Ok(row_to_request(&row)),
+            None => Err("Friend request not found".to_string()),
+        }
+    }
+
+    /// Lists the pending requests sent and received by the user
+    ///
+    /// Incoming requests from users the addressee has blocked are left out.
+    pub async fn get_pending_requests(&self, user_id: Uuid) -> Result<FriendRequestList, String> {
+        let incoming_query = "
+            SELECT f.id, u.id, u.username, f.created_at
+            FROM friend_requests f
+            JOIN users u ON u.id = f.requester_id
+            WHERE f.addressee_id = $1 AND f.status = 'pending'
+              AND NOT EXISTS (
+                  SELECT 1 FROM user_blocks b
+                  WHERE b.blocker_id = $1 AND b.blocked_id = f.requester_id
+              )
+            ORDER BY f.created_at DESC
+        ";
+        let outgoing_query = "
+            SELECT f.id, u.id, u.username, f.created_at
+            FROM friend_requests f
+            JOIN users u ON u.id = f.addressee_id
+            WHERE f.requester_id = $1 AND f.status = 'pending'
+            ORDER BY f.created_at DESC
+        ";
+
+        let client = self.client().await?;
+
+        let incoming = client
+            .query(incoming_query, &[&user_id])
+            .await
+            .map_err(|e| format!("Failed to fetch friend requests: {}", e))?;
+        let outgoing = client
+            .query(outgoing_query, &[&user_id])
+            .await
+            .map_err(|e| format!("Failed to fetch friend requests: {}", e))?;
+
+        Ok(FriendRequestList {
+            incoming: incoming.iter().map(row_to_pending).collect(),
+            outgoing: outgoing.iter().map(row_to_pending).collect(),
+        })
+    }
+
+    /// Lists the contacts of the user, with whether each is connected to any node
+    pub async fn get_contacts(&self, user_id: Uuid) -> Result<Vec<Contact>, String> {
+        // Contacts who blocked the user always appear offline to them
+        let query = "
+            SELECT u.id, u.username, u.display_name, f.updated_at,
+                   EXISTS (SELECT 1 FROM user_presence p WHERE p.user_id = u.id)
+                   AND NOT EXISTS (
+                       SELECT 1 FROM user_blocks b WHERE b.blocker_id = u.id AND b.blocked_id = $1
+                   )
+            FROM friend_requests f
+            JOIN users u
+                ON u.id = CASE WHEN f.requester_id = $1 THEN f.addressee_id ELSE f.requester_id END
+            WHERE (f.requester_id = $1 OR f.addressee_id = $1) AND f.status = 'accepted'
+            ORDER BY u.username
+        ";
+
+        let client = self.client().await?;
+
+        let rows = client
+            .query(query, &[&user_id])
+            .await
+            .map_err(|e| format!("Failed to fetch contacts: {}", e))?;
+
+        Ok(rows
+            .iter()
+            .map(|row| Contact {
+                id: row.get(0),
+                username: row.get(1),
+                display_name: row.get(2),
+                online: row.get(4),
+                since: row.get::<_, NaiveDateTime>(3),
+            })
+            .collect())
+    }
+
+    /// Removes a contact; returns whether the two users were contacts
+    pub async fn remove_contact(&self, user_id: Uuid, contact_id: Uuid) -> Result<bool, String> {
+        let query = "
+            DELETE FROM friend_requests
+            WHERE status = 'accepted'
+              AND ((requester_id = $1 AND addressee_id = $2)
+                   OR (requester_id = $2 AND addressee_id = $1))
+        ";
+
+        let client = self.client().await?;
+
+        let deleted = client
+            .execute(query, &[&user_id, &contact_id])
+            .await
+            .map_err(|e| format!("Failed to remove contact: {}", e))?;
+
+        Ok(deleted > 0)
+    }
+}
+
+fn row_to_request(row: &Row) -> FriendRequest {
+    FriendRequest {
+        id: row.get(0),
+        requester_id: row.get(1),
+        addressee_id: row.get(2),
+        status: row.get(3),
+        created_at: row.get::<_, NaiveDateTime>(4),
+        updated_at: row.get::<_, NaiveDateTime>(5),
+    }
+}
+
+fn row_to_pending(row: &Row) -> PendingFriendRequest {
+    PendingFriendRequest {
+        id: row.get(0),
+        user_id: row.get(1),
+        username: row.get(2),
+        created_at: row.get::<_, NaiveDateTime>(3),
+    }
+}
@@ -4,6 +4,7 @@ use chrono::{NaiveDateTime, Utc};
 use deadpool_postgres::{Client, Pool};
 use uuid::Uuid;
 use crate::models::invitation::ChatInvitation;
+use crate::repositories::block_repository::BlockRepository;
 
 pub struct InvitationRepository {
     pool: Pool,
@@ -86,6 +87,22 @@ impl InvitationRepository {
         }
     }
 
+    /// Checks whether `blocker_id` has blocked `blocked_id`
+    pub async fn has_blocked(
+        &self,
+        blocker_id: Uuid,
+        blocked_id: Uuid,
+    ) -> Result<bool, String> {
+        let client: Client = self.pool
+            .get()
+            .await
+            .map_err(|e| format!("Failed to get client from pool: {}", e))?;
+
+        BlockRepository::has_blocked(&client, blocker_id, blocked_id)
+            .await
+            .map_err(|e| format!("Failed to check block list: {}", e))
+    }
+
     pub async fn insert_user_to_chat(
         &self,
         chat_id: Uuid,
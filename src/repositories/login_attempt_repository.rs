@@ -0,0 +1,70 @@
+// repositories/login_attempt_repository.rs
+
+use chrono::NaiveDateTime;
+use deadpool_postgres::Client;
+use tokio_postgres::Error;
+
+pub struct LoginAttemptRepository;
+
+impl LoginAttemptRepository {
+    /// Returns until when a username or IP is locked, if it currently is
+    pub async fn get_locked_until(
+        client: &Client,
+        scope: &str,
+        key: &str,
+    ) -> Result<Option<NaiveDateTime>, Error> {
+        let query = "
+            SELECT locked_until FROM login_attempts
+            WHERE scope = $1 AND key = $2 AND locked_until > NOW()
+        ";
+        let row = client.query_opt(query, &[&scope, &key]).await?;
+        Ok(row.and_then(|row| row.get(0)))
+    }
+
+    /// Counts a failed attempt and returns the number of consecutive failures
+    ///
+    /// The counter starts over when the previous failure is older than `window_seconds`.
+    pub async fn record_failure(
+        client: &Client,
+        scope: &str,
+        key: &str,
+        window_seconds: i64,
+    ) -> Result<i32, Error> {
+        let query = "
+            INSERT INTO login_attempts (scope, key, failed_count, last_failed_at)
+            VALUES ($1, $2, 1, NOW())
+            ON CONFLICT (scope, key) DO UPDATE SET
+                failed_count = CASE
+                    WHEN login_attempts.last_failed_at < NOW() - make_interval(secs => $3::BIGINT)
+                    THEN 1
+                    ELSE login_attempts.failed_count + 1
+                END,
+                last_failed_at = NOW()
+            RETURNING failed_count
+        ";
+        let row = client
+            .query_one(query, &[&scope, &key, &window_seconds])
+            .await?;
+        Ok(row.get(0))
+    }
+
+    /// Locks a username or IP until the given time
+    pub async fn lock_until(
+        client: &Client,
+        scope: &str,
+        key: &str,
+        locked_until: NaiveDateTime,
+    ) -> Result<(), Error> {
+        let query = "UPDATE login_attempts SET locked_until = $3 WHERE scope = $1 AND key = $2";
+        client
+            .execute(query, &[&scope, &key, &locked_until])
+            .await?;
+        Ok(())
+    }
+
+    /// Forgets all failures and any lock for a username or IP
+    pub async fn clear(client: &Client, scope: &str, key: &str) -> Result<u64, Error> {
+        let query = "DELETE FROM login_attempts WHERE scope = $1 AND key = $2";
+        client.execute(query, &[&scope, &key]).await
+    }
+}
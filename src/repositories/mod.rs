@@ -1,3 +1,14 @@
+pub mod account_repository;
+pub mod audit_repository;
 pub mod auth_repository;
+pub mod block_repository;
 pub mod chat_repository;
-pub mod invitation_repository;
\ No newline at end of file
+pub mod email_verification_repository;
+pub mod friend_repository;
+pub mod invitation_repository;
+pub mod login_attempt_repository;
+pub mod password_reset_repository;
+pub mod presence_repository;
+pub mod profile_repository;
+pub mod request_throttle_repository;
+pub mod two_factor_repository;
\ No newline at end of file
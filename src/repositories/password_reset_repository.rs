@@ -0,0 +1,81 @@
+// repositories/password_reset_repository.rs
+
+use chrono::NaiveDateTime;
+use deadpool_postgres::{Client, Transaction};
+use tokio_postgres::Error;
+use uuid::Uuid;
+
+pub struct PasswordResetRepository;
+
+impl PasswordResetRepository {
+    /// Stores a new reset token hash, invalidating any token still outstanding for the user
+    pub async fn create_reset_token(
+        client: &Client,
+        user_id: Uuid,
+        token_hash: &str,
+        expires_at: NaiveDateTime,
+    ) -> Result<(), Error> {
+        let invalidate_query = "
+            UPDATE password_resets SET used_at = NOW()
+            WHERE user_id = $1 AND used_at IS NULL
+        ";
+        client.execute(invalidate_query, &[&user_id]).await?;
+
+        let query = "
+            INSERT INTO password_resets (user_id, token_hash, expires_at)
+            VALUES ($1, $2, $3)
+        ";
+        client
+            .execute(query, &[&user_id, &token_hash, &expires_at])
+            .await?;
+        Ok(())
+    }
+
+    /// Marks a valid token as used and returns its user, so a token can only be redeemed once
+    pub async fn consume_reset_token(
+        transaction: &Transaction<'_>,
+        token_hash: &str,
+    ) -> Result<Option<Uuid>, Error> {
+        let query = "
+            UPDATE password_resets SET used_at = NOW()
+            WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+            RETURNING user_id
+        ";
+        let row = transaction.query_opt(query, &[&token_hash]).await?;
+        Ok(row.map(|row| row.get(0)))
+    }
+
+    /// Replaces the stored password hash of a user
+    pub async fn update_password(
+        transaction: &Transaction<'_>,
+        user_id: Uuid,
+        hashed_password: &str,
+    ) -> Result<(), Error> {
+        let query = "UPDATE users SET password = $1 WHERE id = $2";
+        transaction
+            .execute(query, &[&hashed_password, &user_id])
+            .await?;
+        Ok(())
+    }
+
+    /// Revokes every session of a user
+    pub async fn revoke_user_sessions(
+        transaction: &Transaction<'_>,
+        user_id: Uuid,
+    ) -> Result<u64, Error> {
+        let query = "DELETE FROM sessions WHERE user_id = $1";
+        transaction.execute(query, &[&user_id]).await
+    }
+
+    /// Revokes every session of a user except the one making the request
+    pub async fn revoke_other_sessions(
+        transaction: &Transaction<'_>,
+        user_id: Uuid,
+        current_session_id: Uuid,
+    ) -> Result<u64, Error> {
+        let query = "DELETE FROM sessions WHERE user_id = $1 AND id <> $2";
+        transaction
+            .execute(query, &[&user_id, &current_session_id])
+            .await
+    }
+}
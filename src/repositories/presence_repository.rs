@@ -0,0 +1,161 @@
+// repositories/presence_repository.rs
+
+use chrono::NaiveDateTime;
+use deadpool_postgres::{Client, Transaction};
+use tokio_postgres::Error;
+use uuid::Uuid;
+
+use crate::websocket::types::UserStatus;
+
+/// First key of the advisory locks serializing the presence changes of each user
+const PRESENCE_LOCK_CLASS: i32 = 0x7072_6573; // "pres"
+
+/// Users who share an accepted contact or a chat with user `$1`
+const RELATED_USERS: &str = "
+    SELECT CASE WHEN requester_id = $1 THEN addressee_id ELSE requester_id END
+    FROM friend_requests
+    WHERE (requester_id = $1 OR addressee_id = $1) AND status = 'accepted'
+    UNION
+    SELECT other.user_id
+    FROM chat_members own
+    JOIN chat_members other ON other.chat_id = own.chat_id
+    WHERE own.user_id = $1 AND other.user_id <> $1
+      AND (own.status = 'accepted' OR own.is_creator = true)
+      AND (other.status = 'accepted' OR other.is_creator = true)
+";
+
+/// Number of nodes a user is connected to, and whether any of them sees the user active
+const STATUS_QUERY: &str = "
+    SELECT COUNT(*), COALESCE(BOOL_OR(status = 'online'), FALSE)
+    FROM user_presence WHERE user_id = $1
+";
+
+pub struct PresenceRepository;
+
+impl PresenceRepository {
+    /// Records when a user was last seen
+    pub async fn set_last_seen(client: &Client, user_id: Uuid, seen_at: NaiveDateTime) -> Result<(), Error> {
+        let query = "UPDATE users SET last_seen_at = $2 WHERE id = $1";
+        client.execute(query, &[&user_id, &seen_at]).await?;
+        Ok(())
+    }
+
+    /// Lists the users who follow the presence of a user: their contacts and
+    /// the members of the chats they have joined, except the users they have blocked
+    pub async fn get_audience(client: &Client, user_id: Uuid) -> Result<Vec<Uuid>, Error> {
+        let query = format!(
+            "
+            SELECT related.id FROM ({}) AS related(id)
+            WHERE NOT EXISTS (
+                SELECT 1 FROM user_blocks WHERE blocker_id = $1 AND blocked_id = related.id
+            )
+            ",
+            RELATED_USERS
+        );
+        let rows = client.query(&query, &[&user_id]).await?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    /// Fetches the username, presence across all nodes and last-seen time of the given users,
+    /// keeping only those whose audience includes the viewer (and the viewer themselves)
+    pub async fn get_presence(
+        client: &Client,
+        viewer_id: Uuid,
+        user_ids: &[Uuid],
+    ) -> Result<Vec<(Uuid, String, UserStatus, Option<NaiveDateTime>)>, Error> {
+        let query = format!(
+            "
+            SELECT u.id, u.username, u.last_seen_at,
+                   COUNT(p.node_id), COALESCE(BOOL_OR(p.status = 'online'), FALSE)
+            FROM users u
+            LEFT JOIN user_presence p ON p.user_id = u.id
+            WHERE u.id = ANY($2)
+              AND (u.id = $1 OR (
+                  u.id IN ({})
+                  AND NOT EXISTS (
+                      SELECT 1 FROM user_blocks WHERE blocker_id = u.id AND blocked_id = $1
+                  )
+              ))
+            GROUP BY u.id
+            ORDER BY u.username
+            ",
+            RELATED_USERS
+        );
+        let rows = client.query(&query, &[&viewer_id, &user_ids]).await?;
+        Ok(rows
+            .iter()
+            .map(|row| (row.get(0), row.get(1), cluster_status(row.get(3), row.get(4)), row.get(2)))
+            .collect())
+    }
+
+    /// Sets the status of a user on one node, or removes it once the node has no connection
+    /// of the user left, and returns the user's status over all nodes before and after
+    pub async fn set_node_status(
+        transaction: &Transaction<'_>,
+        user_id: Uuid,
+        node_id: Uuid,
+        status: UserStatus,
+    ) -> Result<(UserStatus, UserStatus), Error> {
+        // Nodes changing the presence of the same user at once take turns, so that exactly
+        // one of them sees the user come online or go offline
+        let lock_query = "SELECT pg_advisory_xact_lock($1, hashtext($2::uuid::text))";
+        transaction.execute(lock_query, &[&PRESENCE_LOCK_CLASS, &user_id]).await?;
+
+        let row = transaction.query_one(STATUS_QUERY, &[&user_id]).await?;
+        let before = cluster_status(row.get(0), row.get(1));
+        match status {
+            UserStatus::Offline => {
+                let query = "DELETE FROM user_presence WHERE user_id = $1 AND node_id = $2";
+                transaction.execute(query, &[&user_id, &node_id]).await?;
+            }
+            _ => {
+                let query = "
+                    INSERT INTO user_presence (user_id, node_id, status) VALUES ($1, $2, $3)
+                    ON CONFLICT (user_id, node_id)
+                    DO UPDATE SET status = EXCLUDED.status, updated_at = CURRENT_TIMESTAMP
+                ";
+                let status = if status == UserStatus::Idle { "idle" } else { "online" };
+                transaction.execute(query, &[&user_id, &node_id, &status]).await?;
+            }
+        }
+        let row = transaction.query_one(STATUS_QUERY, &[&user_id]).await?;
+        let after = cluster_status(row.get(0), row.get(1));
+        Ok((before, after))
+    }
+
+    /// Returns the status of a user over all nodes
+    pub async fn get_status(client: &Client, user_id: Uuid) -> Result<UserStatus, Error> {
+        let row = client.query_one(STATUS_QUERY, &[&user_id]).await?;
+        Ok(cluster_status(row.get(0), row.get(1)))
+    }
+
+    /// Shows that a node is still running, so that its rows are not swept
+    pub async fn refresh_node(client: &Client, node_id: Uuid) -> Result<(), Error> {
+        let query = "UPDATE user_presence SET updated_at = CURRENT_TIMESTAMP WHERE node_id = $1";
+        client.execute(query, &[&node_id]).await?;
+        Ok(())
+    }
+
+    /// Removes the rows of nodes that stopped refreshing them, returning the users they covered
+    pub async fn remove_dead_nodes(client: &Client, timeout_seconds: i64) -> Result<Vec<Uuid>, Error> {
+        let query = "
+            DELETE FROM user_presence
+            WHERE updated_at < CURRENT_TIMESTAMP - $1 * INTERVAL '1 second'
+            RETURNING user_id
+        ";
+        let rows = client.query(query, &[&(timeout_seconds as f64)]).await?;
+        let mut user_ids: Vec<Uuid> = rows.iter().map(|row| row.get(0)).collect();
+        user_ids.sort();
+        user_ids.dedup();
+        Ok(user_ids)
+    }
+}
+
+/// Combines the rows of a user: online on any node, idle on all of them, or offline without any
+fn cluster_status(nodes: i64, online_anywhere: bool) -> UserStatus {
+    match (nodes, online_anywhere) {
+        (0, _) => UserStatus::Offline,
+        (_, true) => UserStatus::Online,
+        (_, false) => UserStatus::Idle,
+    }
+}
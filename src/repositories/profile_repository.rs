@@ -0,0 +1,146 @@
+// repositories/profile_repository.rs
+
+use deadpool_postgres::Client;
+use tokio_postgres::{Error, Row};
+use uuid::Uuid;
+
+use crate::models::profile::{OwnProfile, UpdateProfileRequest, UserProfile};
+
+const PROFILE_COLUMNS: &str =
+    "id, username, display_name, bio, avatar_ref, status_text, status_emoji";
+
+pub struct ProfileRepository;
+
+impl ProfileRepository {
+    /// Fetches the public profile of a user by username
+    pub async fn get_profile_by_username(
+        client: &Client,
+        username: &str,
+    ) -> Result<Option<UserProfile>, Error> {
+        let query = format!("SELECT {} FROM users WHERE username = $1", PROFILE_COLUMNS);
+        let row = client.query_opt(&query, &[&username]).await?;
+        Ok(row.as_ref().map(row_to_profile))
+    }
+
+    /// Fetches the profile of a user together with their private account details
+    pub async fn get_own_profile(client: &Client, user_id: Uuid) -> Result<Option<OwnProfile>, Error> {
+        let query = format!(
+            "SELECT {}, email, email_verified, totp_enabled, discoverable, deletion_scheduled_at FROM users WHERE id = $1",
+            PROFILE_COLUMNS
+        );
+        let row = client.query_opt(&query, &[&user_id]).await?;
+        Ok(row.map(|row| OwnProfile {
+            profile: row_to_profile(&row),
+            email: row.get(7),
+            email_verified: row.get(8),
+            two_factor_enabled: row.get(9),
+            discoverable: row.get(10),
+            deletion_scheduled_at: row.get(11),
+        }))
+    }
+
+    /// Applies a partial profile update and returns the updated profile
+    ///
+    /// `NULL` parameters keep the current value; empty strings clear it.
+    pub async fn update_profile(
+        client: &Client,
+        user_id: Uuid,
+        update: &UpdateProfileRequest,
+    ) -> Result<Option<UserProfile>, Error> {
+        let query = format!(
+            "UPDATE users SET
+                display_name = CASE WHEN $2::TEXT IS NULL THEN display_name ELSE NULLIF($2, '') END,
+                bio = CASE WHEN $3::TEXT IS NULL THEN bio ELSE NULLIF($3, '') END,
+                avatar_ref = CASE WHEN $4::TEXT IS NULL THEN avatar_ref ELSE NULLIF($4, '') END,
+                status_text = CASE WHEN $5::TEXT IS NULL THEN status_text ELSE NULLIF($5, '') END,
+                status_emoji = CASE WHEN $6::TEXT IS NULL THEN status_emoji ELSE NULLIF($6, '') END,
+                discoverable = COALESCE($7, discoverable)
+            WHERE id = $1
+            RETURNING {}",
+            PROFILE_COLUMNS
+        );
+        let row = client
+            .query_opt(
+                &query,
+                &[
+                    &user_id,
+                    &update.display_name,
+                    &update.bio,
+                    &update.avatar_ref,
+                    &update.status_text,
+                    &update.status_emoji,
+                    &update.discoverable,
+                ],
+            )
+            .await?;
+        Ok(row.as_ref().map(row_to_profile))
+    }
+
+    /// Searches discoverable users by username and display name
+    ///
+    /// Prefix matches rank above fuzzy (trigram) matches; an exact username match comes first.
+    pub async fn search_profiles(
+        client: &Client,
+        searcher_id: Uuid,
+        term: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<UserProfile>, Error> {
+        let term = term.to_lowercase();
+        let prefix_pattern = format!("{}%", escape_like(&term));
+        let query = format!(
+            "SELECT {} FROM users
+            WHERE discoverable AND id <> $3
+              AND NOT EXISTS (
+                  SELECT 1 FROM user_blocks WHERE blocker_id = $3 AND blocked_id = users.id
+              )
+              AND (lower(username) LIKE $2
+                   OR lower(display_name) LIKE $2
+                   OR lower(username) % $1
+                   OR lower(display_name) % $1)
+            ORDER BY
+                (lower(username) = $1) DESC,
+                (lower(username) LIKE $2 OR COALESCE(lower(display_name), '') LIKE $2) DESC,
+                GREATEST(
+                    similarity(lower(username), $1),
+                    similarity(COALESCE(lower(display_name), ''), $1)
+                ) DESC,
+                username
+            LIMIT $4 OFFSET $5",
+            PROFILE_COLUMNS
+        );
+        let rows = client
+            .query(&query, &[&term, &prefix_pattern, &searcher_id, &limit, &offset])
+            .await?;
+        Ok(rows.iter().map(row_to_profile).collect())
+    }
+
+    /// Lists the chats a user has joined, used to fan out profile changes
+    pub async fn get_joined_chat_ids(client: &Client, user_id: Uuid) -> Result<Vec<Uuid>, Error> {
+        let query = "
+            SELECT chat_id FROM chat_members
+            WHERE user_id = $1 AND (status = 'accepted' OR is_creator = true)
+        ";
+        let rows = client.query(query, &[&user_id]).await?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+}
+
+fn row_to_profile(row: &Row) -> UserProfile {
+    UserProfile {
+        id: row.get(0),
+        username: row.get(1),
+        display_name: row.get(2),
+        bio: row.get(3),
+        avatar_ref: row.get(4),
+        status_text: row.get(5),
+        status_emoji: row.get(6),
+    }
+}
+
+/// Escapes the LIKE wildcards in user input
+fn escape_like(term: &str) -> String {
+    term.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
@@ -0,0 +1,40 @@
+// repositories/request_throttle_repository.rs
+
+use chrono::NaiveDateTime;
+use deadpool_postgres::Client;
+use tokio_postgres::Error;
+
+pub struct RequestThrottleRepository;
+
+impl RequestThrottleRepository {
+    /// Counts a request and returns the number of requests in the current window with its start
+    ///
+    /// A new window starts once the previous one is older than `window_seconds`.
+    pub async fn record_request(
+        client: &Client,
+        scope: &str,
+        key: &str,
+        window_seconds: i64,
+    ) -> Result<(i32, NaiveDateTime), Error> {
+        let query = "
+            INSERT INTO request_throttles (scope, key, request_count, window_started_at)
+            VALUES ($1, $2, 1, NOW())
+            ON CONFLICT (scope, key) DO UPDATE SET
+                request_count = CASE
+                    WHEN request_throttles.window_started_at < NOW() - make_interval(secs => $3::BIGINT)
+                    THEN 1
+                    ELSE request_throttles.request_count + 1
+                END,
+                window_started_at = CASE
+                    WHEN request_throttles.window_started_at < NOW() - make_interval(secs => $3::BIGINT)
+                    THEN NOW()
+                    ELSE request_throttles.window_started_at
+                END
+            RETURNING request_count, window_started_at
+        ";
+        let row = client
+            .query_one(query, &[&scope, &key, &window_seconds])
+            .await?;
+        Ok((row.get(0), row.get(1)))
+    }
+}
@@ -0,0 +1,137 @@
+// repositories/two_factor_repository.rs
+
+use chrono::NaiveDateTime;
+use deadpool_postgres::{Client, Transaction};
+use tokio_postgres::Error;
+use uuid::Uuid;
+
+/// TOTP settings stored for a user
+pub struct TotpState {
+    pub username: String,
+    pub secret: Option<String>,
+    pub enabled: bool,
+}
+
+pub struct TwoFactorRepository;
+
+impl TwoFactorRepository {
+    /// Fetches the TOTP settings of a user
+    pub async fn get_totp_state(client: &Client, user_id: Uuid) -> Result<Option<TotpState>, Error> {
+        let query = "SELECT username, totp_secret, totp_enabled FROM users WHERE id = $1";
+        let row = client.query_opt(query, &[&user_id]).await?;
+        Ok(row.map(|row| TotpState {
+            username: row.get(0),
+            secret: row.get(1),
+            enabled: row.get(2),
+        }))
+    }
+
+    /// Stores a secret that is not active until it is confirmed with a code
+    pub async fn set_pending_secret(client: &Client, user_id: Uuid, secret: &str) -> Result<(), Error> {
+        let query = "
+            UPDATE users SET totp_secret = $1, totp_enabled = FALSE, totp_last_step = NULL
+            WHERE id = $2
+        ";
+        client.execute(query, &[&secret, &user_id]).await?;
+        Ok(())
+    }
+
+    /// Remembers the last accepted time step so a code cannot be replayed
+    ///
+    /// Returns false when this step or a later one was already used, including by a
+    /// concurrent login; the check and the update are a single statement.
+    pub async fn record_used_step(client: &Client, user_id: Uuid, step: i64) -> Result<bool, Error> {
+        let query = "
+            UPDATE users SET totp_last_step = $1
+            WHERE id = $2 AND (totp_last_step IS NULL OR totp_last_step < $1)
+        ";
+        let updated = client.execute(query, &[&step, &user_id]).await?;
+        Ok(updated == 1)
+    }
+
+    /// Activates the pending secret and replaces the recovery codes
+    pub async fn enable(
+        transaction: &Transaction<'_>,
+        user_id: Uuid,
+        step: i64,
+        recovery_code_hashes: &[String],
+    ) -> Result<(), Error> {
+        let query = "UPDATE users SET totp_enabled = TRUE, totp_last_step = $1 WHERE id = $2";
+        transaction.execute(query, &[&step, &user_id]).await?;
+
+        let delete_query = "DELETE FROM totp_recovery_codes WHERE user_id = $1";
+        transaction.execute(delete_query, &[&user_id]).await?;
+
+        let insert_query = "INSERT INTO totp_recovery_codes (user_id, code_hash) VALUES ($1, $2)";
+        for code_hash in recovery_code_hashes {
+            transaction.execute(insert_query, &[&user_id, code_hash]).await?;
+        }
+        Ok(())
+    }
+
+    /// Turns two-factor authentication off and forgets the secret and recovery codes
+    pub async fn disable(transaction: &Transaction<'_>, user_id: Uuid) -> Result<(), Error> {
+        let query = "
+            UPDATE users SET totp_secret = NULL, totp_enabled = FALSE, totp_last_step = NULL
+            WHERE id = $1
+        ";
+        transaction.execute(query, &[&user_id]).await?;
+
+        let delete_query = "DELETE FROM totp_recovery_codes WHERE user_id = $1";
+        transaction.execute(delete_query, &[&user_id]).await?;
+        Ok(())
+    }
+
+    /// Marks an unused recovery code as used, returning whether one matched
+    pub async fn consume_recovery_code(client: &Client, user_id: Uuid, code_hash: &str) -> Result<bool, Error> {
+        let query = "
+            UPDATE totp_recovery_codes SET used_at = NOW()
+            WHERE id = (
+                SELECT id FROM totp_recovery_codes
+                WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+                LIMIT 1
+            )
+        ";
+        let updated = client.execute(query, &[&user_id, &code_hash]).await?;
+        Ok(updated > 0)
+    }
+
+    /// Counts the recovery codes a user has not used yet
+    pub async fn remaining_recovery_codes(client: &Client, user_id: Uuid) -> Result<i64, Error> {
+        let query = "SELECT COUNT(*) FROM totp_recovery_codes WHERE user_id = $1 AND used_at IS NULL";
+        Ok(client.query_one(query, &[&user_id]).await?.get(0))
+    }
+
+    /// Stores a login challenge issued after a correct password
+    pub async fn create_login_challenge(
+        client: &Client,
+        user_id: Uuid,
+        token_hash: &str,
+        expires_at: NaiveDateTime,
+    ) -> Result<(), Error> {
+        let query = "INSERT INTO login_challenges (user_id, token_hash, expires_at) VALUES ($1, $2, $3)";
+        client.execute(query, &[&user_id, &token_hash, &expires_at]).await?;
+        Ok(())
+    }
+
+    /// Records an attempt on a pending challenge and returns its user and attempt count
+    pub async fn register_challenge_attempt(
+        client: &Client,
+        token_hash: &str,
+    ) -> Result<Option<(Uuid, i32)>, Error> {
+        let query = "
+            UPDATE login_challenges SET attempts = attempts + 1
+            WHERE token_hash = $1 AND expires_at > NOW()
+            RETURNING user_id, attempts
+        ";
+        let row = client.query_opt(query, &[&token_hash]).await?;
+        Ok(row.map(|row| (row.get(0), row.get(1))))
+    }
+
+    /// Deletes a challenge once it has been redeemed or exhausted
+    pub async fn delete_login_challenge(client: &Client, token_hash: &str) -> Result<(), Error> {
+        let query = "DELETE FROM login_challenges WHERE token_hash = $1 OR expires_at <= NOW()";
+        client.execute(query, &[&token_hash]).await?;
+        Ok(())
+    }
+}
@@ -1,58 +1,163 @@
 // src/router.rs
 
-use std::sync::{Arc, Mutex};
-
 use crate::app_state::AppState;
+use crate::handlers::account_handlers;
+use crate::handlers::admin_handlers;
 use crate::handlers::auth_handlers;
+use crate::handlers::block_handlers;
+use crate::handlers::friend_handlers;
 use crate::handlers::chat_handlers::{create_chat, get_chat_messages, send_message_handler};
 use crate::handlers::invitation_handlers::respond_to_invitation;
+use crate::handlers::password_handlers;
+use crate::handlers::presence_handlers;
+use crate::handlers::profile_handlers;
+use crate::handlers::two_factor_handlers;
+use crate::middleware::admin_middleware::admin_middleware;
+use crate::middleware::two_factor_middleware::two_factor_middleware;
 use crate::middleware::{auth_middleware, ws_auth_middleware};
 use crate::routes::app_routes::auth_middleware::auth_middleware;
-use crate::routes::app_routes::ws_auth_middleware::ws_auth_middleware;
-use crate::websocket::connection_manager::ConnectionManager;
+use crate::routes::app_routes::ws_auth_middleware::{event_stream_auth_middleware, ws_auth_middleware};
+use crate::websocket::events::events_handler;
 use crate::websocket::handlers::websocket_handler;
 use axum::middleware::from_fn;
 use axum::{
-    routing::{get, post},
+    routing::{delete, get, post},
     Extension, Router,
 };
-use deadpool_postgres::Pool;
 use tower_http::trace::TraceLayer;
 
-pub fn create_router(db: Pool) -> Router {
-    // Inicializa o ConnectionManager com o pool de banco de dados
-    let connections = ConnectionManager::new(db.clone());
-
-    // Cria o AppState com o ConnectionManager e o pool de banco de dados
-    let state = AppState {
-        connections,
-        db,
-        current_user_id: None,
-    };
-
+pub fn create_router(state: AppState) -> Router {
     Router::new()
         .route("/", get(|| async { "Hello, world!" }))
         .route("/register", post(auth_handlers::register))
         .route("/login", post(auth_handlers::login))
+        .route("/.well-known/jwks.json", get(auth_handlers::jwks))
+        .route("/login/2fa", post(two_factor_handlers::login_two_factor))
+        .route(
+            "/2fa/enroll",
+            post(two_factor_handlers::enroll).route_layer(from_fn(auth_middleware)),
+        )
+        .route(
+            "/2fa/confirm",
+            post(two_factor_handlers::confirm).route_layer(from_fn(auth_middleware)),
+        )
+        .route(
+            "/2fa/disable",
+            post(two_factor_handlers::disable).route_layer(from_fn(auth_middleware)),
+        )
+        .route("/verify-email/:token", get(auth_handlers::verify_email))
+        .route("/verify-email/resend", post(auth_handlers::resend_verification))
+        .route("/password/policy", get(password_handlers::password_policy))
+        .route("/password/forgot", post(password_handlers::forgot_password))
+        .route("/password/reset", post(password_handlers::reset_password))
+        .route(
+            "/me",
+            get(profile_handlers::get_me)
+                .patch(profile_handlers::update_me)
+                .delete(account_handlers::delete_account)
+                .route_layer(from_fn(auth_middleware)),
+        )
+        .route(
+            "/me/export",
+            get(account_handlers::export_data).route_layer(from_fn(auth_middleware)),
+        )
+        .route(
+            "/me/cancel-deletion",
+            post(account_handlers::cancel_deletion).route_layer(from_fn(auth_middleware)),
+        )
+        .route(
+            "/me/password",
+            post(account_handlers::change_password).route_layer(from_fn(auth_middleware)),
+        )
+        .route(
+            "/me/email",
+            post(account_handlers::change_email).route_layer(from_fn(auth_middleware)),
+        )
+        .route(
+            "/confirm-email-change/:token",
+            get(account_handlers::confirm_email_change),
+        )
+        .route(
+            "/me/blocks",
+            get(block_handlers::list_blocks).route_layer(from_fn(auth_middleware)),
+        )
+        .route(
+            "/friends/requests",
+            get(friend_handlers::list_requests)
+                .post(friend_handlers::send_request)
+                .route_layer(from_fn(auth_middleware)),
+        )
+        .route(
+            "/friends/respond",
+            post(friend_handlers::respond_to_request).route_layer(from_fn(auth_middleware)),
+        )
+        .route(
+            "/contacts",
+            get(friend_handlers::list_contacts).route_layer(from_fn(auth_middleware)),
+        )
+        .route(
+            "/contacts/:username",
+            delete(friend_handlers::remove_contact).route_layer(from_fn(auth_middleware)),
+        )
+        .route(
+            "/presence",
+            get(presence_handlers::get_presence).route_layer(from_fn(auth_middleware)),
+        )
+        .route(
+            "/users/search",
+            get(profile_handlers::search_users).route_layer(from_fn(auth_middleware)),
+        )
+        .route(
+            "/users/:username",
+            get(profile_handlers::get_user).route_layer(from_fn(auth_middleware)),
+        )
+        .route(
+            "/users/:username/block",
+            post(block_handlers::block_user)
+                .delete(block_handlers::unblock_user)
+                .route_layer(from_fn(auth_middleware)),
+        )
         .route(
             "/ws",
-            get(websocket_handler).route_layer(from_fn(ws_auth_middleware)),
+            get(websocket_handler)
+                .route_layer(from_fn(two_factor_middleware))
+                .route_layer(from_fn(ws_auth_middleware)),
+        )
+        .route(
+            "/events",
+            get(events_handler)
+                .route_layer(from_fn(two_factor_middleware))
+                .route_layer(from_fn(event_stream_auth_middleware)),
         )
         .route(
             "/create_chat",
-            post(create_chat).route_layer(from_fn(auth_middleware)),
+            post(create_chat)
+                .route_layer(from_fn(two_factor_middleware))
+                .route_layer(from_fn(auth_middleware)),
         )
         .route(
             "/get_messages/:chat_id",
-            get(get_chat_messages).route_layer(from_fn(auth_middleware)),
+            get(get_chat_messages)
+                .route_layer(from_fn(two_factor_middleware))
+                .route_layer(from_fn(auth_middleware)),
         )
         .route(
             "/send_message",
-            post(send_message_handler).route_layer(from_fn(auth_middleware)),
+            post(send_message_handler)
+                .route_layer(from_fn(two_factor_middleware))
+                .route_layer(from_fn(auth_middleware)),
         )
         .route(
             "/invites/respond",
-            post(respond_to_invitation).route_layer(from_fn(auth_middleware)),
+            post(respond_to_invitation)
+                .route_layer(from_fn(two_factor_middleware))
+                .route_layer(from_fn(auth_middleware)),
+        )
+        .route(
+            "/admin/users/:username/unlock",
+            post(admin_handlers::unlock_account)
+                .route_layer(from_fn(admin_middleware))
+                .route_layer(from_fn(auth_middleware)),
         )
         .layer(TraceLayer::new_for_http())
         .layer(Extension(state))
@@ -0,0 +1,565 @@
+// services/account_service.rs
+
+use axum::{
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use bcrypt::{hash, verify, DEFAULT_COST};
+use deadpool_postgres::Pool;
+use serde_json::json;
+use std::{net::IpAddr, sync::Arc, time::Duration};
+use tokio_postgres::error::SqlState;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    app_state::AppState,
+    config::AppConfig,
+    models::account::{ChangeEmailRequest, ChangePasswordRequest, DataExport, DeleteAccountRequest},
+    repositories::{
+        account_repository::AccountRepository, audit_repository::AuditRepository,
+        auth_repository::AuthRepository, block_repository::BlockRepository,
+        password_reset_repository::PasswordResetRepository,
+        profile_repository::ProfileRepository,
+    },
+    services::{
+        mailer::{Email, Mailer},
+        session_cache::SessionCache,
+    },
+    utils::{
+        password_validator::PasswordValidator,
+        token::{generate_token, hash_token},
+    },
+    websocket::connection_manager::ConnectionManager,
+};
+
+pub struct AccountService {
+    pool: Pool,
+    config: Arc<AppConfig>,
+    mailer: Arc<dyn Mailer>,
+    password_validator: Arc<PasswordValidator>,
+    sessions: Arc<SessionCache>,
+    connections: ConnectionManager,
+}
+
+impl AccountService {
+    pub fn new(state: &AppState) -> Self {
+        AccountService {
+            pool: state.db.clone(),
+            config: state.config.clone(),
+            mailer: state.mailer.clone(),
+            password_validator: state.password_validator.clone(),
+            sessions: state.sessions.clone(),
+            connections: state.connections.clone(),
+        }
+    }
+
+    /// Changes the password of a logged-in user and revokes their other sessions
+    pub async fn change_password(
+        &self,
+        user_id: Uuid,
+        session_id: Uuid,
+        payload: ChangePasswordRequest,
+        ip: Option<IpAddr>,
+    ) -> impl IntoResponse {
+        let mut client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database connection error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        let current_hash = match AuthRepository::get_password_hash(&client, user_id).await {
+            Ok(Some(hash)) if verify(&payload.current_password, &hash).unwrap_or(false) => hash,
+            Ok(_) => {
+                return (
+                    StatusCode::UNAUTHORIZED,
+                    Json(json!({ "error": "Invalid current password" })),
+                )
+                    .into_response();
+            }
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        let violations = self.password_validator.violations(&payload.new_password);
+        if !violations.is_empty() {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "error": "Password does not meet the password policy",
+                    "violations": violations
+                })),
+            )
+                .into_response();
+        }
+        if verify(&payload.new_password, &current_hash).unwrap_or(false) {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "The new password must differ from the current password" })),
+            )
+                .into_response();
+        }
+
+        let hashed_password = match hash(&payload.new_password, DEFAULT_COST) {
+            Ok(hashed) => hashed,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Password processing error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        let result = async {
+            let transaction = client.transaction().await?;
+            PasswordResetRepository::update_password(&transaction, user_id, &hashed_password).await?;
+            let revoked =
+                PasswordResetRepository::revoke_other_sessions(&transaction, user_id, session_id).await?;
+            AuditRepository::record(
+                &transaction,
+                user_id,
+                "password_changed",
+                &json!({ "revoked_sessions": revoked }),
+                ip,
+            )
+            .await?;
+            transaction.commit().await?;
+            Ok::<_, tokio_postgres::Error>(revoked)
+        }
+        .await;
+
+        match result {
+            Ok(revoked) => {
+                self.sessions.forget_user(user_id, Some(session_id));
+                self.connections.disconnect_sessions(user_id, Some(session_id));
+                (
+                    StatusCode::OK,
+                    Json(json!({
+                        "message": "Password changed",
+                        "revoked_sessions": revoked
+                    })),
+                )
+                    .into_response()
+            }
+            Err(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to change password" })),
+            )
+                .into_response(),
+        }
+    }
+
+    /// Starts an email change by sending a confirmation link to the new address
+    ///
+    /// The address on the account only changes once the link is opened.
+    pub async fn change_email(
+        &self,
+        user_id: Uuid,
+        payload: ChangeEmailRequest,
+        ip: Option<IpAddr>,
+    ) -> impl IntoResponse {
+        if let Err(errors) = payload.validate() {
+            return (StatusCode::BAD_REQUEST, Json(json!({ "errors": errors }))).into_response();
+        }
+
+        let mut client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database connection error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        match AuthRepository::get_password_hash(&client, user_id).await {
+            Ok(Some(hash)) if verify(&payload.password, &hash).unwrap_or(false) => {}
+            Ok(_) => {
+                return (
+                    StatusCode::UNAUTHORIZED,
+                    Json(json!({ "error": "Invalid password" })),
+                )
+                    .into_response();
+            }
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database error" })),
+                )
+                    .into_response();
+            }
+        }
+
+        match AuthRepository::find_user_by_email(&client, &payload.new_email).await {
+            Ok(Some((owner_id, _))) if owner_id == user_id => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({ "error": "This is already your email address" })),
+                )
+                    .into_response();
+            }
+            Ok(Some(_)) => {
+                return (
+                    StatusCode::CONFLICT,
+                    Json(json!({ "error": "Email address is already in use" })),
+                )
+                    .into_response();
+            }
+            Ok(None) => {}
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database error" })),
+                )
+                    .into_response();
+            }
+        }
+
+        let token = generate_token();
+        let ttl_hours = self.config.email_verification.token_ttl_hours;
+        let expires_at = (chrono::Utc::now() + chrono::Duration::hours(ttl_hours)).naive_utc();
+
+        let result = async {
+            let transaction = client.transaction().await?;
+            AccountRepository::create_email_change(
+                &transaction,
+                user_id,
+                &payload.new_email,
+                &hash_token(&token),
+                expires_at,
+            )
+            .await?;
+            AuditRepository::record(
+                &transaction,
+                user_id,
+                "email_change_requested",
+                &json!({ "new_email": payload.new_email }),
+                ip,
+            )
+            .await?;
+            transaction.commit().await
+        }
+        .await;
+
+        if result.is_err() {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to start email change" })),
+            )
+                .into_response();
+        }
+
+        let email = Email {
+            to: payload.new_email,
+            subject: "Confirm your new rustle_chat email address".to_string(),
+            body: format!(
+                "Hi,\n\nPlease confirm that this is your new rustle_chat email address by opening the link below:\n\n{}/confirm-email-change/{}\n\nThe link expires in {} hours. If you did not request this change, you can ignore this email.",
+                self.config.mailer.app_base_url,
+                token,
+                ttl_hours
+            ),
+        };
+        if let Err(e) = self.mailer.send(email).await {
+            log::error!("Failed to send email change confirmation: {}", e);
+        }
+
+        (
+            StatusCode::ACCEPTED,
+            Json(json!({ "message": "A confirmation link has been sent to the new address" })),
+        )
+            .into_response()
+    }
+
+    /// Completes an email change from the link sent to the new address
+    pub async fn confirm_email_change(&self, token: &str, ip: Option<IpAddr>) -> impl IntoResponse {
+        let mut client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database connection error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        let transaction = match client.transaction().await {
+            Ok(transaction) => transaction,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        let (user_id, new_email) =
+            match AccountRepository::consume_email_change(&transaction, &hash_token(token)).await {
+                Ok(Some(change)) => change,
+                Ok(None) => {
+                    return (
+                        StatusCode::BAD_REQUEST,
+                        Json(json!({ "error": "Invalid or expired confirmation link" })),
+                    )
+                        .into_response();
+                }
+                Err(_) => {
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(json!({ "error": "Database error" })),
+                    )
+                        .into_response();
+                }
+            };
+
+        let result = async {
+            let old_email = AccountRepository::update_email(&transaction, user_id, &new_email).await?;
+            AuditRepository::record(
+                &transaction,
+                user_id,
+                "email_changed",
+                &json!({ "old_email": old_email, "new_email": new_email }),
+                ip,
+            )
+            .await?;
+            transaction.commit().await?;
+            Ok::<_, tokio_postgres::Error>(old_email)
+        }
+        .await;
+
+        let old_email = match result {
+            Ok(old_email) => old_email,
+            // Another account took the address after the change was requested
+            Err(e) if e.code() == Some(&SqlState::UNIQUE_VIOLATION) => {
+                return (
+                    StatusCode::CONFLICT,
+                    Json(json!({ "error": "Email address is already in use" })),
+                )
+                    .into_response();
+            }
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Failed to change email" })),
+                )
+                    .into_response();
+            }
+        };
+
+        // Let the previous address know, in case the change was not made by its owner
+        let notice = Email {
+            to: old_email,
+            subject: "Your rustle_chat email address was changed".to_string(),
+            body: format!(
+                "Hi,\n\nThe email address of your rustle_chat account was changed to {}.\n\nIf you did not make this change, reset your password and contact support.",
+                new_email
+            ),
+        };
+        if let Err(e) = self.mailer.send(notice).await {
+            log::error!("Failed to send email change notice: {}", e);
+        }
+
+        (StatusCode::OK, Json(json!({ "message": "Email address changed" }))).into_response()
+    }
+
+    /// Builds a downloadable JSON archive of the personal data held about the user
+    pub async fn export(&self, user_id: Uuid) -> impl IntoResponse {
+        let client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database connection error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        let export = async {
+            let profile = match ProfileRepository::get_own_profile(&client, user_id).await? {
+                Some(profile) => profile,
+                None => return Ok(None),
+            };
+            Ok::<_, tokio_postgres::Error>(Some(DataExport {
+                exported_at: chrono::Utc::now().naive_utc(),
+                roles: AuthRepository::get_roles(&client, user_id).await?,
+                sessions: AccountRepository::get_sessions(&client, user_id).await?,
+                memberships: AccountRepository::get_memberships(&client, user_id).await?,
+                invitations: AccountRepository::get_invitations(&client, user_id).await?,
+                messages: AccountRepository::get_authored_messages(&client, user_id).await?,
+                blocked_users: BlockRepository::get_blocked_users(&client, user_id).await?,
+                audit_events: AuditRepository::get_events(&client, user_id).await?,
+                profile,
+            }))
+        }
+        .await;
+
+        match export {
+            Ok(Some(export)) => {
+                let filename = format!(
+                    "attachment; filename=\"rustle_chat-export-{}.json\"",
+                    export.profile.profile.username
+                );
+                (
+                    StatusCode::OK,
+                    [(header::CONTENT_DISPOSITION, filename)],
+                    Json(json!(export)),
+                )
+                    .into_response()
+            }
+            Ok(None) => {
+                (StatusCode::NOT_FOUND, Json(json!({ "error": "User not found" }))).into_response()
+            }
+            Err(e) => {
+                log::error!("Failed to export data of user {}: {}", user_id, e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database error" })),
+                )
+                    .into_response()
+            }
+        }
+    }
+
+    /// Schedules the deletion of the account after the configured grace period
+    ///
+    /// With a grace period of zero the account is deleted right away.
+    pub async fn delete_account(&self, user_id: Uuid, payload: DeleteAccountRequest) -> impl IntoResponse {
+        let client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database connection error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        // Deletion is irreversible once the grace period ends, so ask for the password again
+        match AuthRepository::get_password_hash(&client, user_id).await {
+            Ok(Some(hash)) if verify(&payload.password, &hash).unwrap_or(false) => {}
+            Ok(_) => {
+                return (
+                    StatusCode::UNAUTHORIZED,
+                    Json(json!({ "error": "Invalid password" })),
+                )
+                    .into_response();
+            }
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database error" })),
+                )
+                    .into_response();
+            }
+        }
+
+        let grace_period_hours = self.config.account_deletion.grace_period_hours;
+        if grace_period_hours <= 0 {
+            return match AccountRepository::delete_user(&client, user_id).await {
+                Ok(()) => {
+                    self.sessions.forget_user(user_id, None);
+                    self.connections.disconnect_sessions(user_id, None);
+                    log::info!("Deleted account {}", user_id);
+                    (StatusCode::OK, Json(json!({ "message": "Account deleted" }))).into_response()
+                }
+                Err(_) => (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Failed to delete account" })),
+                )
+                    .into_response(),
+            };
+        }
+
+        let delete_at =
+            (chrono::Utc::now() + chrono::Duration::hours(grace_period_hours)).naive_utc();
+        match AccountRepository::schedule_deletion(&client, user_id, delete_at).await {
+            Ok(()) => (
+                StatusCode::ACCEPTED,
+                Json(json!({
+                    "message": "Account deletion scheduled",
+                    "deletion_scheduled_at": delete_at
+                })),
+            )
+                .into_response(),
+            Err(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to schedule account deletion" })),
+            )
+                .into_response(),
+        }
+    }
+
+    /// Cancels a pending account deletion
+    pub async fn cancel_deletion(&self, user_id: Uuid) -> impl IntoResponse {
+        let client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database connection error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        match AccountRepository::cancel_deletion(&client, user_id).await {
+            Ok(true) => (
+                StatusCode::OK,
+                Json(json!({ "message": "Account deletion cancelled" })),
+            )
+                .into_response(),
+            Ok(false) => (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "No account deletion is pending" })),
+            )
+                .into_response(),
+            Err(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Database error" })),
+            )
+                .into_response(),
+        }
+    }
+
+    /// Periodically deletes the accounts whose grace period has ended and closes their connections
+    pub async fn run_purge_task(pool: Pool, connections: ConnectionManager, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+
+            let client = match pool.get().await {
+                Ok(client) => client,
+                Err(e) => {
+                    log::error!("Account purge skipped, no database connection: {}", e);
+                    continue;
+                }
+            };
+            match AccountRepository::purge_due_accounts(&client).await {
+                Ok(deleted) if !deleted.is_empty() => {
+                    log::info!("Purged {} deleted account(s): {:?}", deleted.len(), deleted);
+                    for &user_id in &deleted {
+                        connections.disconnect_sessions(user_id, None);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => log::error!("Account purge failed: {}", e),
+            }
+        }
+    }
+}
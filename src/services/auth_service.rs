@@ -1,25 +1,58 @@
-use axum::{http::StatusCode, response::IntoResponse, Json};
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
 use bcrypt::{hash, verify, DEFAULT_COST};
+use chrono::NaiveDateTime;
 use deadpool_postgres::Pool;
 use serde_json::json;
-use std::sync::Arc;
+use std::{net::IpAddr, sync::Arc};
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
-    models::user::{LoginData, RegisterData},
-    repositories::auth_repository::AuthRepository,
-    services::jwt_service::create_jwt,
-    utils::password_validator::PasswordValidator,
+    app_state::AppState,
+    config::AppConfig,
+    models::{
+        two_factor::TwoFactorLoginRequest,
+        user::{LoginData, RegisterData, ResendVerificationRequest},
+    },
+    repositories::{
+        auth_repository::AuthRepository,
+        email_verification_repository::EmailVerificationRepository,
+        two_factor_repository::TwoFactorRepository,
+    },
+    services::{
+        jwt_service::KeyRing,
+        login_protection_service::LoginProtectionService,
+        request_throttle_service::RequestThrottleService,
+        mailer::{Email, Mailer},
+        two_factor_service::TwoFactorService,
+    },
+    utils::{
+        password_validator::PasswordValidator,
+        token::{generate_token, hash_token},
+    },
 };
 
 pub struct AuthService {
     pool: Pool,
+    mailer: Arc<dyn Mailer>,
+    config: Arc<AppConfig>,
+    password_validator: Arc<PasswordValidator>,
+    keys: Arc<KeyRing>,
 }
 
 impl AuthService {
-    pub fn new(pool: Pool) -> Self {
-        AuthService { pool }
+    pub fn new(state: &AppState) -> Self {
+        AuthService {
+            pool: state.db.clone(),
+            mailer: state.mailer.clone(),
+            config: state.config.clone(),
+            password_validator: state.password_validator.clone(),
+            keys: state.keys.clone(),
+        }
     }
 
     pub async fn register_user(&self, payload: RegisterData) -> impl IntoResponse {
@@ -28,6 +61,19 @@ impl AuthService {
             return (StatusCode::BAD_REQUEST, Json(json!({ "errors": errors }))).into_response();
         }
 
+        // Validate password against the configured policy, reporting every failed rule
+        let violations = self.password_validator.violations(&payload.password);
+        if !violations.is_empty() {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "error": "Password does not meet the password policy",
+                    "violations": violations
+                })),
+            )
+                .into_response();
+        }
+
         let client = match self.pool.get().await {
             Ok(client) => client,
             Err(_) => {
@@ -58,17 +104,6 @@ impl AuthService {
             }
         }
 
-        // Validate password
-        if !PasswordValidator::validate(&payload.password) {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(json!({
-                    "error": "Password must meet security requirements"
-                })),
-            )
-                .into_response();
-        }
-
         // Hash password and create user
         let hashed_password = match hash(&payload.password, DEFAULT_COST) {
             Ok(hashed) => hashed,
@@ -81,21 +116,106 @@ impl AuthService {
             }
         };
 
-        match AuthRepository::create_user(&client, &payload, &hashed_password).await {
-            Ok(_) => (
-                StatusCode::CREATED,
-                Json(json!({ "message": "User registered successfully" })),
+        let user_id = match AuthRepository::create_user(&client, &payload, &hashed_password).await {
+            Ok(user_id) => user_id,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Failed to create user" })),
+                )
+                    .into_response();
+            }
+        };
+
+        // A delivery failure must not undo the registration; the user can ask for a resend
+        if let Err(e) = self
+            .send_verification_email(&client, user_id, &payload.username, &payload.email)
+            .await
+        {
+            log::error!("Failed to send verification email: {}", e);
+        }
+
+        (
+            StatusCode::CREATED,
+            Json(json!({
+                "message": "User registered successfully. Check your email to verify your address"
+            })),
+        )
+            .into_response()
+    }
+
+    /// Marks the email of the token owner as verified
+    pub async fn verify_email(&self, token: &str) -> impl IntoResponse {
+        let mut client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database connection error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        let transaction = match client.transaction().await {
+            Ok(transaction) => transaction,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        match EmailVerificationRepository::consume_verification_token(&transaction, &hash_token(token)).await {
+            Ok(Some(_)) => match transaction.commit().await {
+                Ok(_) => (
+                    StatusCode::OK,
+                    Json(json!({ "message": "Email verified successfully" })),
+                )
+                    .into_response(),
+                Err(_) => (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Failed to verify email" })),
+                )
+                    .into_response(),
+            },
+            Ok(None) => (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "Invalid or expired verification token" })),
             )
                 .into_response(),
             Err(_) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({ "error": "Failed to create user" })),
+                Json(json!({ "error": "Database error" })),
             )
                 .into_response(),
         }
     }
 
-    pub async fn login_user(&self, payload: LoginData) -> impl IntoResponse {
+    /// Sends a new verification email, at most once per configured interval
+    ///
+    /// Requests are throttled per email and per client IP for every input. Past that, the
+    /// response is the same whether or not an unverified account exists, including when the
+    /// per-user resend interval has not elapsed or the email could not be sent.
+    pub async fn resend_verification(
+        &self,
+        payload: ResendVerificationRequest,
+        client_ip: Option<IpAddr>,
+    ) -> impl IntoResponse {
+        if let Err(errors) = payload.validate() {
+            return (StatusCode::BAD_REQUEST, Json(json!({ "errors": errors }))).into_response();
+        }
+
+        let accepted = (
+            StatusCode::OK,
+            Json(json!({
+                "message": "If an unverified account exists for this email, a verification link has been sent"
+            })),
+        )
+            .into_response();
+
         let client = match self.pool.get().await {
             Ok(client) => client,
             Err(_) => {
@@ -107,14 +227,218 @@ impl AuthService {
             }
         };
 
-        // Get user credentials
-        let (user_id, stored_password) =
+        let throttle = RequestThrottleService::new(&self.config.email_throttle);
+        match throttle
+            .check(&client, "resend_verification", &payload.email, client_ip)
+            .await
+        {
+            Ok(Some(until)) => return RequestThrottleService::throttled_response(until),
+            Ok(None) => (),
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database error" })),
+                )
+                    .into_response();
+            }
+        }
+
+        let (user_id, username) =
+            match AuthRepository::find_verification_status_by_email(&client, &payload.email).await {
+                Ok(Some((user_id, username, false))) => (user_id, username),
+                Ok(_) => return accepted,
+                Err(_) => {
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(json!({ "error": "Database error" })),
+                    )
+                        .into_response();
+                }
+            };
+
+        // Throttle resends per user, without telling the requester
+        let interval = chrono::Duration::seconds(self.config.email_verification.resend_interval_seconds);
+        match EmailVerificationRepository::last_sent_at(&client, user_id).await {
+            Ok(Some(last_sent_at)) if last_sent_at + interval > chrono::Utc::now().naive_utc() => {
+                log::info!("Skipping verification email for user {}: sent recently", user_id);
+                return accepted;
+            }
+            Ok(_) => (),
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database error" })),
+                )
+                    .into_response();
+            }
+        }
+
+        if let Err(e) = self
+            .send_verification_email(&client, user_id, &username, &payload.email)
+            .await
+        {
+            log::error!("Failed to send verification email: {}", e);
+        }
+        accepted
+    }
+
+    /// Issues a verification token for a user and emails the verification link
+    async fn send_verification_email(
+        &self,
+        client: &deadpool_postgres::Client,
+        user_id: Uuid,
+        username: &str,
+        email: &str,
+    ) -> Result<(), String> {
+        let token = generate_token();
+        let expires_at = (chrono::Utc::now()
+            + chrono::Duration::hours(self.config.email_verification.token_ttl_hours))
+        .naive_utc();
+
+        EmailVerificationRepository::create_verification_token(client, user_id, &hash_token(&token), expires_at)
+            .await
+            .map_err(|e| format!("Failed to store verification token: {}", e))?;
+
+        let email = Email {
+            to: email.to_string(),
+            subject: "Verify your rustle_chat email address".to_string(),
+            body: format!(
+                "Hi {},\n\nPlease confirm your email address by opening the link below:\n\n{}/verify-email/{}\n\nThe link expires in {} hours.",
+                username,
+                self.config.mailer.app_base_url,
+                token,
+                self.config.email_verification.token_ttl_hours
+            ),
+        };
+
+        self.mailer.send(email).await.map_err(|e| e.to_string())
+    }
+
+    pub async fn login_user(&self, payload: LoginData, client_ip: Option<IpAddr>) -> impl IntoResponse {
+        let client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database connection error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        // Refuse to check the password while the username or the IP is locked out
+        let login_protection = LoginProtectionService::new(&self.config.login_protection);
+        match login_protection
+            .locked_until(&client, &payload.username, client_ip)
+            .await
+        {
+            Ok(Some(locked_until)) => return Self::locked_response(locked_until),
+            Ok(None) => (),
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Authentication error" })),
+                )
+                    .into_response();
+            }
+        }
+
+        // Get user credentials and verify the password
+        let credentials =
             match AuthRepository::get_user_credentials(&client, &payload.username).await {
-                Ok(Some(creds)) => creds,
+                Ok(Some(creds)) if verify(&payload.password, &creds.password).unwrap_or(false) => creds,
+                Ok(_) => {
+                    return match login_protection
+                        .record_failure(&client, &payload.username, client_ip)
+                        .await
+                    {
+                        Ok(Some(locked_until)) => Self::locked_response(locked_until),
+                        Ok(None) => (
+                            StatusCode::UNAUTHORIZED,
+                            Json(json!({ "error": "Invalid credentials" })),
+                        )
+                            .into_response(),
+                        Err(_) => (
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            Json(json!({ "error": "Authentication error" })),
+                        )
+                            .into_response(),
+                    };
+                }
+                Err(_) => {
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(json!({ "error": "Authentication error" })),
+                    )
+                        .into_response();
+                }
+            };
+
+        // Optionally require a verified email address
+        if self.config.email_verification.required_for_login && !credentials.email_verified {
+            return (
+                StatusCode::FORBIDDEN,
+                Json(json!({ "error": "Email address has not been verified" })),
+            )
+                .into_response();
+        }
+
+        // With 2FA enabled the password alone only earns a short-lived challenge, and the
+        // failure counter is only cleared once the second factor is accepted too
+        if credentials.totp_enabled {
+            return match self.create_login_challenge(&client, credentials.id).await {
+                Ok(challenge_token) => (
+                    StatusCode::OK,
+                    Json(json!({
+                        "two_factor_required": true,
+                        "challenge_token": challenge_token,
+                        "expires_in": self.config.two_factor.challenge_ttl_seconds
+                    })),
+                )
+                    .into_response(),
+                Err(_) => (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Failed to create login challenge" })),
+                )
+                    .into_response(),
+            };
+        }
+
+        if let Err(e) = login_protection.record_success(&client, &payload.username).await {
+            log::error!("Failed to reset login failure counter: {}", e);
+        }
+
+        self.issue_session(&client, credentials.id).await
+    }
+
+    /// Exchanges a login challenge and a TOTP or recovery code for a JWT
+    ///
+    /// Wrong codes count as failed logins of the username and the IP, so that fresh
+    /// challenges do not give unlimited guesses to someone who knows the password.
+    pub async fn complete_two_factor_login(
+        &self,
+        payload: TwoFactorLoginRequest,
+        client_ip: Option<IpAddr>,
+    ) -> impl IntoResponse {
+        let client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database connection error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        let challenge_hash = hash_token(&payload.challenge_token);
+        let (user_id, attempts) =
+            match TwoFactorRepository::register_challenge_attempt(&client, &challenge_hash).await {
+                Ok(Some(challenge)) => challenge,
                 Ok(None) => {
                     return (
                         StatusCode::UNAUTHORIZED,
-                        Json(json!({ "error": "Invalid credentials" })),
+                        Json(json!({ "error": "Invalid or expired login challenge" })),
                     )
                         .into_response();
                 }
@@ -127,18 +451,149 @@ impl AuthService {
                 }
             };
 
-        // Verify password
-        if !verify(&payload.password, &stored_password).unwrap_or(false) {
+        let username = match TwoFactorRepository::get_totp_state(&client, user_id).await {
+            Ok(Some(totp)) => totp.username,
+            Ok(None) => {
+                return (
+                    StatusCode::UNAUTHORIZED,
+                    Json(json!({ "error": "Invalid or expired login challenge" })),
+                )
+                    .into_response();
+            }
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Authentication error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        // The lockout of the password step applies to the second step too
+        let login_protection = LoginProtectionService::new(&self.config.login_protection);
+        match login_protection.locked_until(&client, &username, client_ip).await {
+            Ok(Some(locked_until)) => return Self::locked_response(locked_until),
+            Ok(None) => (),
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Authentication error" })),
+                )
+                    .into_response();
+            }
+        }
+
+        if attempts > self.config.two_factor.max_challenge_attempts {
+            let _ = TwoFactorRepository::delete_login_challenge(&client, &challenge_hash).await;
             return (
                 StatusCode::UNAUTHORIZED,
-                Json(json!({ "error": "Invalid credentials" })),
+                Json(json!({ "error": "Too many attempts, please log in again" })),
+            )
+                .into_response();
+        }
+
+        match TwoFactorService::verify_second_factor(&client, user_id, &payload.code).await {
+            Ok(true) => (),
+            Ok(false) => {
+                return match login_protection.record_failure(&client, &username, client_ip).await {
+                    Ok(Some(locked_until)) => Self::locked_response(locked_until),
+                    Ok(None) => (
+                        StatusCode::UNAUTHORIZED,
+                        Json(json!({ "error": "Invalid code" })),
+                    )
+                        .into_response(),
+                    Err(_) => (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(json!({ "error": "Authentication error" })),
+                    )
+                        .into_response(),
+                };
+            }
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Authentication error" })),
+                )
+                    .into_response();
+            }
+        }
+
+        if TwoFactorRepository::delete_login_challenge(&client, &challenge_hash)
+            .await
+            .is_err()
+        {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Authentication error" })),
             )
                 .into_response();
         }
 
-        // Create JWT and session
-        let token = create_jwt(user_id);
-        match AuthRepository::create_session(&client, user_id, &token).await {
+        if let Err(e) = login_protection.record_success(&client, &username).await {
+            log::error!("Failed to reset login failure counter: {}", e);
+        }
+
+        self.issue_session(&client, user_id).await
+    }
+
+    /// Builds the response for a locked username or IP, telling the client when to retry
+    fn locked_response(locked_until: NaiveDateTime) -> Response {
+        let remaining_ms = (locked_until - chrono::Utc::now().naive_utc()).num_milliseconds();
+        let retry_after = ((remaining_ms + 999) / 1000).max(1);
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after.to_string())],
+            Json(json!({
+                "error": "Too many failed login attempts, try again later",
+                "retry_after_seconds": retry_after,
+                "locked_until": locked_until
+            })),
+        )
+            .into_response()
+    }
+
+    /// Issues a short-lived challenge token for the second login step
+    async fn create_login_challenge(
+        &self,
+        client: &deadpool_postgres::Client,
+        user_id: Uuid,
+    ) -> Result<String, tokio_postgres::Error> {
+        let token = generate_token();
+        let expires_at = (chrono::Utc::now()
+            + chrono::Duration::seconds(self.config.two_factor.challenge_ttl_seconds))
+        .naive_utc();
+        TwoFactorRepository::create_login_challenge(client, user_id, &hash_token(&token), expires_at)
+            .await?;
+        Ok(token)
+    }
+
+    /// Creates a JWT and the matching session for an authenticated user
+    async fn issue_session(&self, client: &deadpool_postgres::Client, user_id: Uuid) -> Response {
+        let roles = match AuthRepository::get_roles(client, user_id).await {
+            Ok(roles) => roles,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        // The session ID is generated here so the token can carry it as `sid`
+        let session_id = Uuid::new_v4();
+        let token = match self.keys.create_jwt(user_id, session_id, roles) {
+            Ok(token) => token,
+            Err(e) => {
+                log::error!("Failed to sign JWT: {}", e);
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Failed to create session" })),
+                )
+                    .into_response();
+            }
+        };
+        match AuthRepository::create_session(client, session_id, user_id, &token).await {
             Ok(_) => (
                 StatusCode::OK,
                 Json(json!({
@@ -153,19 +608,93 @@ impl AuthService {
         }
         .into_response()
     }
+}
 
-    pub async fn get_username(&self, user_id: Uuid) -> Option<String> {
-        let client = self.pool.get().await.unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        database::init::init_db, services::mailer::FileMailer, utils::password_validator::PasswordValidator,
+    };
+    use axum::body::HttpBody;
+    use rand::Rng;
 
-        let query = "SELECT username FROM users WHERE id = $1";
+    const PASSWORD: &str = "Str0ng!Pass";
 
-        match client.query_opt(query, &[&user_id]).await {
-            Ok(Some(row)) => Some(row.get(0)),
-            Ok(None) => None,
-            Err(e) => {
-                eprintln!("Database error: {}", e);
-                None
-            }
+    fn service(pool: Pool) -> AuthService {
+        let mut config = AppConfig::from_env().unwrap();
+        config.login_protection.max_failures_per_username = 3;
+        config.login_protection.max_failures_per_ip = 100;
+        config.jwt.algorithm = "HS256".to_string();
+        config.jwt.secret = Some("test-secret".to_string());
+        AuthService {
+            pool,
+            mailer: Arc::new(FileMailer::new(std::env::temp_dir(), "test@localhost")),
+            password_validator: Arc::new(PasswordValidator::from_config(&config.password_policy).unwrap()),
+            keys: Arc::new(KeyRing::from_config(&config.jwt).unwrap()),
+            config: Arc::new(config),
         }
     }
+
+    async fn json_body(response: Response) -> serde_json::Value {
+        let mut body = response.into_body();
+        let mut bytes = Vec::new();
+        while let Some(chunk) = body.data().await {
+            bytes.extend_from_slice(&chunk.unwrap());
+        }
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    // Wrong second factors lock the username even when each comes with a fresh challenge.
+    // Needs a database: `DATABASE_URL=... cargo test second_factor -- --ignored`.
+    #[tokio::test]
+    #[ignore]
+    async fn failed_second_factors_lock_the_username() {
+        let pool = init_db().await.unwrap();
+        let service = service(pool.clone());
+        let client = pool.get().await.unwrap();
+
+        let username = format!("twofactor{}", rand::thread_rng().gen::<u32>());
+        let ip: IpAddr = format!("203.0.113.{}", rand::thread_rng().gen_range(1..255)).parse().unwrap();
+        let user_id: Uuid = client
+            .query_one(
+                "INSERT INTO users (username, email, password, totp_secret, totp_enabled)
+                 VALUES ($1, $2, $3, 'GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ', TRUE) RETURNING id",
+                &[&username, &format!("{}@example.com", username), &hash(PASSWORD, 4).unwrap()],
+            )
+            .await
+            .unwrap()
+            .get(0);
+
+        let login = || LoginData {
+            username: username.clone(),
+            password: PASSWORD.to_string(),
+        };
+        for attempt in 1..=3 {
+            let response = service.login_user(login(), Some(ip)).await.into_response();
+            assert_eq!(response.status(), StatusCode::OK, "password step {}", attempt);
+            let challenge_token = json_body(response).await["challenge_token"].as_str().unwrap().to_string();
+
+            let request = TwoFactorLoginRequest {
+                challenge_token,
+                code: "wrong-code".to_string(),
+            };
+            let response = service.complete_two_factor_login(request, Some(ip)).await.into_response();
+            let expected = if attempt < 3 { StatusCode::UNAUTHORIZED } else { StatusCode::TOO_MANY_REQUESTS };
+            assert_eq!(response.status(), expected, "second step {}", attempt);
+        }
+
+        // The right password no longer earns a challenge
+        let response = service.login_user(login(), Some(ip)).await.into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        client.execute("DELETE FROM users WHERE id = $1", &[&user_id]).await.unwrap();
+        client
+            .execute(
+                "DELETE FROM login_attempts WHERE key = $1 OR key = $2",
+                &[&username, &ip.to_string()],
+            )
+            .await
+            .unwrap();
+    }
 }
@@ -0,0 +1,159 @@
+// services/block_service.rs
+
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use deadpool_postgres::Pool;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    models::block::BlockListUpdate,
+    repositories::{block_repository::BlockRepository, profile_repository::ProfileRepository},
+    websocket::{connection_manager::ConnectionManager, types::WebSocketMessage},
+};
+
+/// Manages the block lists of users
+///
+/// Blocking is one-way and never reported to the blocked user: their invitations
+/// are silently dropped and their messages are flagged for the blocker only.
+pub struct BlockService {
+    pool: Pool,
+    connections: ConnectionManager,
+}
+
+impl BlockService {
+    pub fn new(state: &AppState) -> Self {
+        BlockService {
+            pool: state.db.clone(),
+            connections: state.connections.clone(),
+        }
+    }
+
+    /// Lists the users blocked by the authenticated user
+    pub async fn list(&self, user_id: Uuid) -> impl IntoResponse {
+        let client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database connection error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        match BlockRepository::get_blocked_users(&client, user_id).await {
+            Ok(blocked) => (StatusCode::OK, Json(json!(blocked))).into_response(),
+            Err(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Database error" })),
+            )
+                .into_response(),
+        }
+    }
+
+    /// Blocks a user by username
+    pub async fn block(&self, user_id: Uuid, username: &str) -> impl IntoResponse {
+        let client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database connection error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        let target_id = match ProfileRepository::get_profile_by_username(&client, username).await {
+            Ok(Some(profile)) => profile.id,
+            Ok(None) => {
+                return (StatusCode::NOT_FOUND, Json(json!({ "error": "User not found" })))
+                    .into_response();
+            }
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        if target_id == user_id {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "You cannot block yourself" })),
+            )
+                .into_response();
+        }
+
+        match BlockRepository::block(&client, user_id, target_id).await {
+            Ok(_) => {
+                self.notify_connections(user_id, target_id, true).await;
+                (StatusCode::OK, Json(json!({ "message": "User blocked" }))).into_response()
+            }
+            Err(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to block user" })),
+            )
+                .into_response(),
+        }
+    }
+
+    /// Removes a user from the block list
+    pub async fn unblock(&self, user_id: Uuid, username: &str) -> impl IntoResponse {
+        let client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database connection error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        let target_id = match ProfileRepository::get_profile_by_username(&client, username).await {
+            Ok(Some(profile)) => profile.id,
+            Ok(None) => {
+                return (StatusCode::NOT_FOUND, Json(json!({ "error": "User not found" })))
+                    .into_response();
+            }
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        match BlockRepository::unblock(&client, user_id, target_id).await {
+            Ok(true) => {
+                self.notify_connections(user_id, target_id, false).await;
+                (StatusCode::OK, Json(json!({ "message": "User unblocked" }))).into_response()
+            }
+            Ok(false) => (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "User is not blocked" })),
+            )
+                .into_response(),
+            Err(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to unblock user" })),
+            )
+                .into_response(),
+        }
+    }
+
+    /// Tells the blocker's open connections, which flag the messages of blocked senders
+    /// without querying the block list for every message
+    async fn notify_connections(&self, user_id: Uuid, target_id: Uuid, blocked: bool) {
+        let update = WebSocketMessage::BlockListUpdated(BlockListUpdate {
+            user_id: target_id,
+            blocked,
+        });
+        let _ = self.connections.send_direct_message(user_id, update).await;
+    }
+}
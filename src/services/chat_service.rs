@@ -3,10 +3,39 @@
 use std::sync::Arc;
 use uuid::Uuid;
 use crate::{
-    models::{chat::Chat, message::Message},
+    models::{chat::Chat, message::{Message, MessageRangeQuery}},
     repositories::chat_repository::ChatRepository,
+    websocket::{connection_manager::ConnectionManager, types::{ChatMessage, WebSocketMessage}},
 };
 use deadpool_postgres::{Pool, Transaction};
+use thiserror::Error;
+
+/// Reasons a message cannot be sent, with a stable code for clients
+#[derive(Error, Debug)]
+pub enum SendMessageError {
+    /// The sender is not a member of the chat
+    #[error("User is not a member of this chat.")]
+    NotMember,
+
+    /// The message is empty
+    #[error("Message cannot be empty.")]
+    Empty,
+
+    /// Storing or broadcasting the message failed; the details are only logged
+    #[error("The message could not be sent.")]
+    Internal(String),
+}
+
+impl SendMessageError {
+    /// Machine-readable code sent to clients in error frames
+    pub fn code(&self) -> &'static str {
+        match self {
+            SendMessageError::NotMember => "not_member",
+            SendMessageError::Empty => "empty_message",
+            SendMessageError::Internal(_) => "internal_error",
+        }
+    }
+}
 
 pub struct ChatService;
 
@@ -37,41 +66,95 @@ impl ChatService {
         })
     }
 
-    /// Fetches all messages in a specific chat
-    pub async fn get_chat_messages(pool: Pool, chat_id: Uuid) -> Result<Vec<Message>, String> {
+    /// Fetches the messages of a chat within an optional time range, as seen by the viewer
+    pub async fn get_chat_messages(pool: Pool, chat_id: Uuid, viewer_id: Uuid, range: MessageRangeQuery) -> Result<Vec<Message>, String> {
         let mut client = pool.get().await.map_err(|e| format!("Failed to get DB client: {}", e))?;
         let transaction = client.transaction().await.map_err(|e| format!("Failed to start transaction: {}", e))?;
 
         // Fetch messages for the chat
-        ChatRepository::get_chat_messages(&transaction, chat_id)
+        ChatRepository::get_chat_messages(&transaction, chat_id, viewer_id, &range)
             .await.map_err(|e| format!("Error fetching messages: {}", e))
     }
 
-    /// Sends a message in a chat
-    pub async fn send_message(pool: Pool, chat_id: Uuid, sender_id: Uuid, message_text: String) -> Result<Message, String> {
-        let message_id = Uuid::new_v4();
+    /// Lists the chats the user has joined or created, with the sequence number of their last message
+    pub async fn get_member_chats(pool: Pool, user_id: Uuid) -> Result<Vec<(Uuid, i64)>, String> {
         let mut client = pool.get().await.map_err(|e| format!("Failed to get DB client: {}", e))?;
         let transaction = client.transaction().await.map_err(|e| format!("Failed to start transaction: {}", e))?;
 
+        ChatRepository::get_member_chats(&transaction, user_id)
+            .await.map_err(|e| format!("Error fetching chats: {}", e))
+    }
+
+    /// Sends a message in a chat
+    ///
+    /// When the sender already stored a message under `client_id` in this chat, that message is
+    /// returned instead of storing a copy. The flag tells whether the message was just stored.
+    pub async fn send_message(pool: Pool, chat_id: Uuid, sender_id: Uuid, message_text: String, client_id: Option<Uuid>) -> Result<(Message, bool), SendMessageError> {
+        if message_text.trim().is_empty() {
+            return Err(SendMessageError::Empty);
+        }
+
+        let message_id = Uuid::new_v4();
+        let mut client = pool.get().await.map_err(|e| SendMessageError::Internal(format!("Failed to get DB client: {}", e)))?;
+        let transaction = client.transaction().await.map_err(|e| SendMessageError::Internal(format!("Failed to start transaction: {}", e)))?;
+
         // Check if the sender is a member of the chat
         let is_member = ChatRepository::check_user_membership(&transaction, chat_id, sender_id)
-            .await.map_err(|e| format!("Error checking chat membership: {}", e))?;
+            .await.map_err(|e| SendMessageError::Internal(format!("Error checking chat membership: {}", e)))?;
         
         if !is_member {
-            return Err("User is not a member of this chat.".to_string());
+            return Err(SendMessageError::NotMember);
+        }
+
+        // A retry of a message that was already stored
+        if let Some(client_id) = client_id {
+            let existing = ChatRepository::get_message_by_client_id(&transaction, chat_id, sender_id, client_id)
+                .await.map_err(|e| SendMessageError::Internal(format!("Error looking up message: {}", e)))?;
+            if let Some(message) = existing {
+                return Ok((message, false));
+            }
         }
 
         // Insert the message
-        ChatRepository::insert_message(&transaction, message_id, chat_id, sender_id, &message_text)
-            .await.map_err(|e| format!("Error inserting message: {}", e))?;
+        let inserted = ChatRepository::insert_message(&transaction, message_id, chat_id, sender_id, &message_text, client_id)
+            .await.map_err(|e| SendMessageError::Internal(format!("Error inserting message: {}", e)))?;
+
+        // The same retry, sent to another instance, stored the message in the meantime. Dropping
+        // the transaction rolls back the sequence number taken for the copy.
+        if let (false, Some(client_id)) = (inserted, client_id) {
+            let existing = ChatRepository::get_message_by_client_id(&transaction, chat_id, sender_id, client_id)
+                .await.map_err(|e| SendMessageError::Internal(format!("Error looking up message: {}", e)))?;
+            return existing
+                .map(|message| (message, false))
+                .ok_or_else(|| SendMessageError::Internal("Message stored under this client id not found".to_string()));
+        }
 
         // Fetch the inserted message
         let message = ChatRepository::get_message_by_id(&transaction, message_id)
-            .await.map_err(|e| format!("Error retrieving message: {}", e))?;
+            .await.map_err(|e| SendMessageError::Internal(format!("Error retrieving message: {}", e)))?;
 
         // Commit the transaction
-        transaction.commit().await.map_err(|e| format!("Failed to commit transaction: {}", e))?;
+        transaction.commit().await.map_err(|e| SendMessageError::Internal(format!("Failed to commit transaction: {}", e)))?;
         
+        Ok((message, true))
+    }
+
+    /// Stores a message and broadcasts it to the members connected to the chat
+    ///
+    /// Messages of a chat are broadcast one at a time, in sequence order, so that a client
+    /// never receives a message before one with a lower sequence number. A resent message
+    /// is returned again but not broadcast twice.
+    pub async fn post_message(pool: Pool, connections: &ConnectionManager, chat_id: Uuid, sender_id: Uuid, message_text: String, client_id: Option<Uuid>) -> Result<Message, SendMessageError> {
+        let lock = connections.chat_send_lock(chat_id);
+        let _guard = lock.lock().await;
+
+        let (message, created) = Self::send_message(pool, chat_id, sender_id, message_text, client_id).await?;
+
+        if created {
+            let chat_msg = WebSocketMessage::Chat(ChatMessage::from(message.clone()));
+            connections.broadcast_to_chat(chat_id, chat_msg);
+        }
+
         Ok(message)
     }
 }
@@ -0,0 +1,147 @@
+// services/friend_service.rs
+
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use chrono::Utc;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    models::friend::{FriendRequestNotification, FriendRequestResponse, SendFriendRequest},
+    repositories::friend_repository::FriendRepository,
+    websocket::{connection_manager::ConnectionManager, types::WebSocketMessage},
+};
+
+pub struct FriendService {
+    repository: FriendRepository,
+    connections: ConnectionManager,
+}
+
+impl FriendService {
+    pub fn new(state: &AppState) -> Self {
+        FriendService {
+            repository: FriendRepository::new(state.db.clone()),
+            connections: state.connections.clone(),
+        }
+    }
+
+    /// Sends a friend request and pushes it to the addressee if they are online
+    ///
+    /// Requests to a user who has blocked the requester are stored as usual
+    /// but never shown or pushed to the addressee.
+    pub async fn send_request(&self, user_id: Uuid, payload: SendFriendRequest) -> impl IntoResponse {
+        let addressee_id = match self.repository.get_user_id_by_username(&payload.username).await {
+            Ok(Some(id)) => id,
+            Ok(None) => {
+                return (StatusCode::NOT_FOUND, Json(json!({ "error": "User not found" })))
+                    .into_response();
+            }
+            Err(e) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": e }))).into_response();
+            }
+        };
+
+        if addressee_id == user_id {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "You cannot send a friend request to yourself" })),
+            )
+                .into_response();
+        }
+
+        let request = match self.repository.create_request(user_id, addressee_id).await {
+            Ok(Some(request)) => request,
+            Ok(None) => {
+                return (
+                    StatusCode::CONFLICT,
+                    Json(json!({ "error": "A friend request is already pending or you are already contacts" })),
+                )
+                    .into_response();
+            }
+            Err(e) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": e }))).into_response();
+            }
+        };
+
+        if !self.repository.has_blocked(addressee_id, user_id).await.unwrap_or(true) {
+            self.notify(addressee_id, request.id, user_id, &request.status).await;
+        }
+
+        (StatusCode::CREATED, Json(json!(request))).into_response()
+    }
+
+    /// Accepts or declines a friend request; the requester is notified of acceptances only
+    pub async fn respond(&self, user_id: Uuid, payload: FriendRequestResponse) -> impl IntoResponse {
+        match self
+            .repository
+            .update_request_status(payload.request_id, user_id, payload.accept)
+            .await
+        {
+            Ok(request) => {
+                if payload.accept {
+                    self.notify(request.requester_id, request.id, user_id, &request.status).await;
+                }
+                (StatusCode::OK, Json(json!(request))).into_response()
+            }
+            Err(e) => (StatusCode::NOT_FOUND, Json(json!({ "error": e }))).into_response(),
+        }
+    }
+
+    /// Lists the pending friend requests of the user
+    pub async fn list_requests(&self, user_id: Uuid) -> impl IntoResponse {
+        match self.repository.get_pending_requests(user_id).await {
+            Ok(requests) => (StatusCode::OK, Json(json!(requests))).into_response(),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": e }))).into_response(),
+        }
+    }
+
+    /// Lists the contacts of the user with their online presence on any node
+    pub async fn list_contacts(&self, user_id: Uuid) -> impl IntoResponse {
+        match self.repository.get_contacts(user_id).await {
+            Ok(contacts) => (StatusCode::OK, Json(json!(contacts))).into_response(),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": e }))).into_response(),
+        }
+    }
+
+    /// Removes a user from the contacts of the user, on both sides
+    pub async fn remove_contact(&self, user_id: Uuid, username: &str) -> impl IntoResponse {
+        let contact_id = match self.repository.get_user_id_by_username(username).await {
+            Ok(Some(id)) => id,
+            Ok(None) => {
+                return (StatusCode::NOT_FOUND, Json(json!({ "error": "User not found" })))
+                    .into_response();
+            }
+            Err(e) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": e }))).into_response();
+            }
+        };
+
+        match self.repository.remove_contact(user_id, contact_id).await {
+            Ok(true) => (StatusCode::OK, Json(json!({ "message": "Contact removed" }))).into_response(),
+            Ok(false) => (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "User is not a contact" })),
+            )
+                .into_response(),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": e }))).into_response(),
+        }
+    }
+
+    /// Pushes a friend request event to a user, if they are online
+    async fn notify(&self, recipient_id: Uuid, request_id: Uuid, from_id: Uuid, status: &str) {
+        let notification = WebSocketMessage::FriendRequest(FriendRequestNotification {
+            request_id,
+            user_id: from_id,
+            username: self
+                .connections
+                .get_user_from_db(from_id)
+                .await
+                .unwrap_or_default(),
+            status: status.to_string(),
+            timestamp: Utc::now().naive_utc(),
+        });
+
+        // Offline users see the request the next time they list their requests
+        let _ = self.connections.send_direct_message(recipient_id, notification).await;
+    }
+}
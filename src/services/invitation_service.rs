@@ -25,12 +25,16 @@ impl InvitationService {
         self.repository.update_invitation_status(invitation_id, user_id, accepted).await
     }
 
+    /// Sends an invitation and returns its ID along with the invitee's ID
+    ///
+    /// Returns `Ok(None)` when the invitee has blocked the inviter: the invitation
+    /// is dropped, but the inviter sees the same outcome as for a delivered one.
     pub async fn send_invitation(
         &self,
         chat_id: Uuid,
         inviter_id: Uuid,
         invitee_username: &str,
-    ) -> Result<Uuid, String> {
+    ) -> Result<Option<(Uuid, Uuid)>, String> {
         // Busca o ID do usuário baseado no nome
         let invitee_id = match self.repository.get_user_id_by_username(&invitee_username).await {
             Ok(Some(id)) => id,
@@ -38,8 +42,15 @@ impl InvitationService {
             Err(e) => return Err(e),
         };
 
+        if self.repository.has_blocked(invitee_id, inviter_id).await? {
+            return Ok(None);
+        }
+
         // Envia o convite
-        self.repository.send_invitation(chat_id, inviter_id, invitee_id).await
+        self.repository
+            .send_invitation(chat_id, inviter_id, invitee_id)
+            .await
+            .map(|invitation_id| Some((invitation_id, invitee_id)))
     }
 
     pub async fn add_user_to_chat(
@@ -0,0 +1,110 @@
+// services/login_protection_service.rs
+
+use chrono::NaiveDateTime;
+use deadpool_postgres::Client;
+use std::net::IpAddr;
+
+use crate::{config::LoginProtectionConfig, repositories::login_attempt_repository::LoginAttemptRepository};
+
+const USERNAME_SCOPE: &str = "username";
+const IP_SCOPE: &str = "ip";
+
+/// Tracks failed logins per username and per IP address and locks them out
+/// with an exponentially growing delay once a threshold is reached.
+pub struct LoginProtectionService<'a> {
+    config: &'a LoginProtectionConfig,
+}
+
+impl<'a> LoginProtectionService<'a> {
+    pub fn new(config: &'a LoginProtectionConfig) -> Self {
+        LoginProtectionService { config }
+    }
+
+    /// Returns the latest time until which the username or the IP is locked
+    pub async fn locked_until(
+        &self,
+        client: &Client,
+        username: &str,
+        ip: Option<IpAddr>,
+    ) -> Result<Option<NaiveDateTime>, tokio_postgres::Error> {
+        let username_lock =
+            LoginAttemptRepository::get_locked_until(client, USERNAME_SCOPE, username)
+                .await?;
+        let ip_lock = match ip {
+            Some(ip) => LoginAttemptRepository::get_locked_until(client, IP_SCOPE, &ip.to_string()).await?,
+            None => None,
+        };
+        Ok(username_lock.max(ip_lock))
+    }
+
+    /// Records a failed login and applies a lockout when a threshold is crossed
+    ///
+    /// Returns the end of the lockout, if one was applied.
+    pub async fn record_failure(
+        &self,
+        client: &Client,
+        username: &str,
+        ip: Option<IpAddr>,
+    ) -> Result<Option<NaiveDateTime>, tokio_postgres::Error> {
+        let username_lock = self
+            .register(
+                client,
+                USERNAME_SCOPE,
+                username,
+                self.config.max_failures_per_username,
+            )
+            .await?;
+        let ip_lock = match ip {
+            Some(ip) => {
+                self.register(client, IP_SCOPE, &ip.to_string(), self.config.max_failures_per_ip)
+                    .await?
+            }
+            None => None,
+        };
+        Ok(username_lock.max(ip_lock))
+    }
+
+    /// Clears the failure counter of a username after a successful login
+    pub async fn record_success(&self, client: &Client, username: &str) -> Result<(), tokio_postgres::Error> {
+        LoginAttemptRepository::clear(client, USERNAME_SCOPE, username).await?;
+        Ok(())
+    }
+
+    /// Removes the lock and failure counter of a username (admin unlock)
+    ///
+    /// Returns whether there was anything to clear.
+    pub async fn unlock_username(client: &Client, username: &str) -> Result<bool, tokio_postgres::Error> {
+        let cleared = LoginAttemptRepository::clear(client, USERNAME_SCOPE, username).await?;
+        Ok(cleared > 0)
+    }
+
+    async fn register(
+        &self,
+        client: &Client,
+        scope: &str,
+        key: &str,
+        threshold: i32,
+    ) -> Result<Option<NaiveDateTime>, tokio_postgres::Error> {
+        let failures =
+            LoginAttemptRepository::record_failure(client, scope, key, self.config.failure_window_seconds).await?;
+        if failures < threshold {
+            return Ok(None);
+        }
+
+        let locked_until = (chrono::Utc::now() + self.lockout_duration(failures - threshold)).naive_utc();
+        LoginAttemptRepository::lock_until(client, scope, key, locked_until).await?;
+        log::warn!("Login locked for {} {} after {} failed attempts", scope, key, failures);
+        Ok(Some(locked_until))
+    }
+
+    /// Doubles the lockout for every failure beyond the threshold, up to the configured maximum
+    fn lockout_duration(&self, failures_over_threshold: i32) -> chrono::Duration {
+        let exponent = failures_over_threshold.clamp(0, 30) as u32;
+        let seconds = self
+            .config
+            .base_lockout_seconds
+            .saturating_mul(2i64.saturating_pow(exponent))
+            .min(self.config.max_lockout_seconds);
+        chrono::Duration::seconds(seconds)
+    }
+}
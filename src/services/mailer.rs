@@ -0,0 +1,178 @@
+// services/mailer.rs
+
+use async_trait::async_trait;
+use chrono::Utc;
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+use std::{path::PathBuf, sync::Arc};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::config::MailerConfig;
+
+/// Errors that can occur while configuring or using a mailer
+#[derive(Error, Debug)]
+pub enum MailerError {
+    /// Error when the mailer configuration is invalid
+    #[error("Invalid mailer configuration: {0}")]
+    Config(String),
+
+    /// Error when the email cannot be built (e.g. invalid address)
+    #[error("Failed to build email: {0}")]
+    Build(String),
+
+    /// Error when the email cannot be delivered
+    #[error("Failed to deliver email: {0}")]
+    Delivery(String),
+}
+
+/// An outgoing plain-text email
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Pluggable email delivery backend
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    /// Delivers a single email
+    async fn send(&self, email: Email) -> Result<(), MailerError>;
+}
+
+/// Sends emails through an SMTP server (e.g. a local Mailpit/MailHog sink)
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    /// Creates a new SMTP mailer without TLS, intended for local relays and sinks
+    pub fn new(
+        host: &str,
+        port: u16,
+        credentials: Option<(String, String)>,
+        from: &str,
+    ) -> Result<Self, MailerError> {
+        let from = from
+            .parse::<Mailbox>()
+            .map_err(|e| MailerError::Config(format!("Invalid MAIL_FROM: {}", e)))?;
+
+        let mut builder = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host).port(port);
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            from,
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: Email) -> Result<(), MailerError> {
+        let to = email
+            .to
+            .parse::<Mailbox>()
+            .map_err(|e| MailerError::Build(e.to_string()))?;
+
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(email.subject)
+            .body(email.body)
+            .map_err(|e| MailerError::Build(e.to_string()))?;
+
+        self.transport
+            .send(message)
+            .await
+            .map_err(|e| MailerError::Delivery(e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// Writes each email to a file in a directory, useful for development and tests
+pub struct FileMailer {
+    dir: PathBuf,
+    from: String,
+}
+
+impl FileMailer {
+    /// Creates a new file mailer writing into `dir`
+    pub fn new(dir: PathBuf, from: &str) -> Self {
+        Self {
+            dir,
+            from: from.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: Email) -> Result<(), MailerError> {
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| MailerError::Delivery(e.to_string()))?;
+
+        let file_name = format!(
+            "{}-{}.eml",
+            Utc::now().format("%Y%m%d%H%M%S"),
+            Uuid::new_v4()
+        );
+        let contents = format!(
+            "From: {}\nTo: {}\nSubject: {}\n\n{}\n",
+            self.from, email.to, email.subject, email.body
+        );
+
+        tokio::fs::write(self.dir.join(file_name), contents)
+            .await
+            .map_err(|e| MailerError::Delivery(e.to_string()))
+    }
+}
+
+/// Logs emails instead of sending them
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, email: Email) -> Result<(), MailerError> {
+        log::info!(
+            "Email to {} - {}\n{}",
+            email.to,
+            email.subject,
+            email.body
+        );
+        Ok(())
+    }
+}
+
+/// Builds the mailer selected by the mailer configuration
+///
+/// Supported backends are `smtp`, `file` and `log` (the default).
+///
+/// # Returns
+/// * `Result<Arc<dyn Mailer>, MailerError>` - The configured mailer or an error
+pub fn mailer_from_config(config: &MailerConfig) -> Result<Arc<dyn Mailer>, MailerError> {
+    match config.backend.as_str() {
+        "smtp" => {
+            let credentials = match (&config.smtp_username, &config.smtp_password) {
+                (Some(username), Some(password)) => Some((username.clone(), password.clone())),
+                _ => None,
+            };
+            Ok(Arc::new(SmtpMailer::new(
+                &config.smtp_host,
+                config.smtp_port,
+                credentials,
+                &config.from,
+            )?))
+        }
+        "file" => Ok(Arc::new(FileMailer::new(PathBuf::from(&config.outbox_dir), &config.from))),
+        "log" => Ok(Arc::new(LogMailer)),
+        other => Err(MailerError::Config(format!("Unknown MAILER_BACKEND: {}", other))),
+    }
+}
@@ -1,4 +1,15 @@
+pub mod account_service;
 pub mod auth_service;
+pub mod block_service;
+pub mod friend_service;
 pub mod jwt_service;
 pub mod chat_service;
-pub mod invitation_service;
\ No newline at end of file
+pub mod invitation_service;
+pub mod login_protection_service;
+pub mod mailer;
+pub mod password_service;
+pub mod presence_service;
+pub mod profile_service;
+pub mod request_throttle_service;
+pub mod session_cache;
+pub mod two_factor_service;
\ No newline at end of file
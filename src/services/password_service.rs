@@ -0,0 +1,256 @@
+// services/password_service.rs
+
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use bcrypt::{hash, DEFAULT_COST};
+use deadpool_postgres::Pool;
+use serde_json::json;
+use std::{net::IpAddr, sync::Arc};
+use validator::Validate;
+
+use crate::{
+    app_state::AppState,
+    config::AppConfig,
+    models::password::{ForgotPasswordRequest, ResetPasswordRequest},
+    repositories::{
+        auth_repository::AuthRepository, password_reset_repository::PasswordResetRepository,
+    },
+    services::{
+        mailer::{Email, Mailer},
+        request_throttle_service::RequestThrottleService,
+        session_cache::SessionCache,
+    },
+    utils::{
+        password_validator::PasswordValidator,
+        token::{generate_token, hash_token},
+    },
+    websocket::connection_manager::ConnectionManager,
+};
+
+/// How long a password reset token stays valid
+const RESET_TOKEN_TTL_MINUTES: i64 = 30;
+
+pub struct PasswordService {
+    pool: Pool,
+    mailer: Arc<dyn Mailer>,
+    config: Arc<AppConfig>,
+    password_validator: Arc<PasswordValidator>,
+    sessions: Arc<SessionCache>,
+    connections: ConnectionManager,
+}
+
+impl PasswordService {
+    pub fn new(state: &AppState) -> Self {
+        PasswordService {
+            pool: state.db.clone(),
+            mailer: state.mailer.clone(),
+            config: state.config.clone(),
+            password_validator: state.password_validator.clone(),
+            sessions: state.sessions.clone(),
+            connections: state.connections.clone(),
+        }
+    }
+
+    /// Returns the active password policy
+    pub fn policy(&self) -> impl IntoResponse {
+        (StatusCode::OK, Json(self.password_validator.policy()))
+    }
+
+    /// Issues a reset token and emails it to the account owner
+    ///
+    /// The response is the same whether or not the email belongs to an account,
+    /// so the endpoint cannot be used to discover registered addresses. Requests are throttled
+    /// per email and per client IP for every input.
+    pub async fn forgot_password(
+        &self,
+        payload: ForgotPasswordRequest,
+        client_ip: Option<IpAddr>,
+    ) -> impl IntoResponse {
+        if let Err(errors) = payload.validate() {
+            return (StatusCode::BAD_REQUEST, Json(json!({ "errors": errors }))).into_response();
+        }
+
+        let accepted = (
+            StatusCode::OK,
+            Json(json!({
+                "message": "If an account exists for this email, a reset link has been sent"
+            })),
+        )
+            .into_response();
+
+        let client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database connection error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        let throttle = RequestThrottleService::new(&self.config.email_throttle);
+        match throttle
+            .check(&client, "forgot_password", &payload.email, client_ip)
+            .await
+        {
+            Ok(Some(until)) => return RequestThrottleService::throttled_response(until),
+            Ok(None) => (),
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database error" })),
+                )
+                    .into_response();
+            }
+        }
+
+        let (user_id, username) =
+            match AuthRepository::find_user_by_email(&client, &payload.email).await {
+                Ok(Some(user)) => user,
+                Ok(None) => return accepted,
+                Err(_) => {
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(json!({ "error": "Database error" })),
+                    )
+                        .into_response();
+                }
+            };
+
+        // Only the hash of the token is stored; the raw token travels by email
+        let token = generate_token();
+        let expires_at =
+            (chrono::Utc::now() + chrono::Duration::minutes(RESET_TOKEN_TTL_MINUTES)).naive_utc();
+
+        if PasswordResetRepository::create_reset_token(&client, user_id, &hash_token(&token), expires_at)
+            .await
+            .is_err()
+        {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to create reset token" })),
+            )
+                .into_response();
+        }
+
+        // The API only accepts the token by POST, so link to the client page when there is one
+        let link = match &self.config.mailer.password_reset_url {
+            Some(url) => {
+                let separator = if url.contains('?') { '&' } else { '?' };
+                format!("\n\nOr open: {}{}token={}", url, separator, token)
+            }
+            None => String::new(),
+        };
+        let email = Email {
+            to: payload.email,
+            subject: "Reset your rustle_chat password".to_string(),
+            body: format!(
+                "Hi {},\n\nUse the token below to reset your password. It expires in {} minutes.\n\n{}{}\n\nIf you did not request this, you can ignore this email.",
+                username,
+                RESET_TOKEN_TTL_MINUTES,
+                token,
+                link
+            ),
+        };
+
+        if let Err(e) = self.mailer.send(email).await {
+            log::error!("Failed to send password reset email: {}", e);
+        }
+
+        accepted
+    }
+
+    /// Redeems a reset token, sets the new password and revokes all sessions of the user
+    pub async fn reset_password(&self, payload: ResetPasswordRequest) -> impl IntoResponse {
+        // Validate the new password before consuming the token
+        let violations = self.password_validator.violations(&payload.new_password);
+        if !violations.is_empty() {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "error": "Password does not meet the password policy",
+                    "violations": violations
+                })),
+            )
+                .into_response();
+        }
+
+        let hashed_password = match hash(&payload.new_password, DEFAULT_COST) {
+            Ok(hashed) => hashed,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Password processing error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        let mut client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database connection error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        let transaction = match client.transaction().await {
+            Ok(transaction) => transaction,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        let user_id =
+            match PasswordResetRepository::consume_reset_token(&transaction, &hash_token(&payload.token))
+                .await
+            {
+                Ok(Some(user_id)) => user_id,
+                Ok(None) => {
+                    return (
+                        StatusCode::BAD_REQUEST,
+                        Json(json!({ "error": "Invalid or expired reset token" })),
+                    )
+                        .into_response();
+                }
+                Err(_) => {
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(json!({ "error": "Database error" })),
+                    )
+                        .into_response();
+                }
+            };
+
+        let result = async {
+            PasswordResetRepository::update_password(&transaction, user_id, &hashed_password).await?;
+            PasswordResetRepository::revoke_user_sessions(&transaction, user_id).await?;
+            transaction.commit().await
+        }
+        .await;
+
+        match result {
+            Ok(_) => {
+                self.sessions.forget_user(user_id, None);
+                self.connections.disconnect_sessions(user_id, None);
+                (
+                    StatusCode::OK,
+                    Json(json!({ "message": "Password has been reset" })),
+                )
+                    .into_response()
+            }
+            Err(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to reset password" })),
+            )
+                .into_response(),
+        }
+    }
+}
@@ -0,0 +1,281 @@
+// services/presence_service.rs
+
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use chrono::{NaiveDateTime, Utc};
+use deadpool_postgres::Pool;
+use serde_json::json;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    models::presence::{PresenceQuery, UserPresence},
+    repositories::presence_repository::PresenceRepository,
+    websocket::{
+        connection_manager::ConnectionManager,
+        types::{PresenceUpdate, UserStatus, WebSocketMessage},
+    },
+};
+
+/// Largest number of users accepted by a single presence query
+const MAX_PRESENCE_USERS: usize = 100;
+
+/// Tracks user-level presence, combining all of a user's WebSocket connections on every node
+///
+/// Each node records in `user_presence` whether it sees the user online or idle, and a change
+/// is announced only when it changes the user's status over all nodes. Changes are pushed to
+/// the user's contacts and chat co-members as `Presence` frames on their direct channel.
+pub struct PresenceService {
+    pool: Pool,
+    connections: ConnectionManager,
+}
+
+impl PresenceService {
+    pub fn new(state: &AppState) -> Self {
+        PresenceService {
+            pool: state.db.clone(),
+            connections: state.connections.clone(),
+        }
+    }
+
+    /// Records that a user opened their first connection on this node
+    pub async fn came_online(&self, user_id: Uuid) {
+        let now = Utc::now().naive_utc();
+        self.record_last_seen(user_id, now).await;
+        self.update(user_id, UserStatus::Online, Some(now)).await;
+    }
+
+    /// Records that a user idle on this node is active again
+    pub async fn became_active(&self, user_id: Uuid) {
+        self.update(user_id, UserStatus::Online, Some(Utc::now().naive_utc()))
+            .await;
+    }
+
+    /// Records that a user closed their last connection on this node
+    pub async fn went_offline(&self, user_id: Uuid) {
+        let now = Utc::now().naive_utc();
+        self.record_last_seen(user_id, now).await;
+        self.update(user_id, UserStatus::Offline, Some(now)).await;
+    }
+
+    /// Returns the presence and last-seen time of the requested users
+    ///
+    /// Users the viewer may not follow (neither contacts nor chat co-members, or who have
+    /// blocked the viewer) are left out, as if they did not exist.
+    pub async fn get_presence(&self, viewer_id: Uuid, query: PresenceQuery) -> impl IntoResponse {
+        let user_ids: Result<Vec<Uuid>, _> = query
+            .users
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(Uuid::parse_str)
+            .collect();
+        let user_ids = match user_ids {
+            Ok(ids) if !ids.is_empty() && ids.len() <= MAX_PRESENCE_USERS => ids,
+            Ok(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({
+                        "error": format!("Provide between 1 and {} user IDs", MAX_PRESENCE_USERS)
+                    })),
+                )
+                    .into_response();
+            }
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({ "error": "Invalid user ID" })),
+                )
+                    .into_response();
+            }
+        };
+
+        let client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database connection error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        match PresenceRepository::get_presence(&client, viewer_id, &user_ids).await {
+            Ok(rows) => {
+                let presence: Vec<UserPresence> = rows
+                    .into_iter()
+                    .map(|(user_id, username, status, last_seen_at)| UserPresence {
+                        user_id,
+                        username,
+                        status,
+                        last_seen_at,
+                    })
+                    .collect();
+                (StatusCode::OK, Json(json!(presence))).into_response()
+            }
+            Err(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Database error" })),
+            )
+                .into_response(),
+        }
+    }
+
+    /// Periodically marks users without recent activity as idle, and shows the other nodes
+    /// that this one still runs
+    pub async fn run_idle_task(
+        pool: Pool,
+        connections: ConnectionManager,
+        idle_after: chrono::Duration,
+        interval: Duration,
+    ) {
+        let service = PresenceService { pool, connections };
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+
+            match service.pool.get().await {
+                Ok(client) => {
+                    if let Err(e) = PresenceRepository::refresh_node(&client, service.connections.node_id).await {
+                        log::error!("Failed to refresh node presence: {}", e);
+                    }
+                }
+                Err(e) => log::error!("Failed to refresh node presence: {}", e),
+            }
+
+            let idle = match service.connections.mark_idle_users(Utc::now() - idle_after) {
+                Ok(idle) => idle,
+                Err(e) => {
+                    log::error!("Idle presence check failed: {}", e);
+                    continue;
+                }
+            };
+            for (user_id, last_activity) in idle {
+                let last_seen = last_activity.naive_utc();
+                service.record_last_seen(user_id, last_seen).await;
+                service.update(user_id, UserStatus::Idle, Some(last_seen)).await;
+            }
+        }
+    }
+
+    /// Periodically evicts connections that stopped answering and announces their users as offline
+    ///
+    /// This is a backstop for sockets whose task is stuck, e.g. on a half-open TCP connection;
+    /// live sockets are normally closed earlier by missed pongs. The presence recorded by nodes
+    /// that stopped without cleaning up, silent for `node_timeout_seconds`, is dropped too.
+    pub async fn run_reaper_task(
+        pool: Pool,
+        connections: ConnectionManager,
+        stale_after: chrono::Duration,
+        interval: Duration,
+        node_timeout_seconds: i64,
+    ) {
+        let service = PresenceService { pool, connections };
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+
+            let offline = match service.connections.evict_stale_connections(Utc::now() - stale_after) {
+                Ok(offline) => offline,
+                Err(e) => {
+                    log::error!("Stale connection sweep failed: {}", e);
+                    continue;
+                }
+            };
+            for user_id in offline {
+                service.went_offline(user_id).await;
+            }
+
+            service.remove_dead_nodes(node_timeout_seconds).await;
+        }
+    }
+
+    // Drops the presence of stopped nodes, announcing the users left without any node as offline
+    async fn remove_dead_nodes(&self, node_timeout_seconds: i64) {
+        let client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(e) => {
+                log::error!("Failed to sweep node presence: {}", e);
+                return;
+            }
+        };
+        let user_ids = match PresenceRepository::remove_dead_nodes(&client, node_timeout_seconds).await {
+            Ok(user_ids) => user_ids,
+            Err(e) => {
+                log::error!("Failed to sweep node presence: {}", e);
+                return;
+            }
+        };
+        for user_id in user_ids {
+            match PresenceRepository::get_status(&client, user_id).await {
+                Ok(status) => self.publish(user_id, status, None).await,
+                Err(e) => log::error!("Failed to read presence: {}", e),
+            }
+        }
+    }
+
+    // Records the status of a user on this node, and publishes their status over all nodes if
+    // this changed it. Without the database, the status seen by this node is published.
+    async fn update(&self, user_id: Uuid, status: UserStatus, last_seen_at: Option<NaiveDateTime>) {
+        let result = async {
+            let mut client = self.pool.get().await.map_err(|e| e.to_string())?;
+            let transaction = client.transaction().await.map_err(|e| e.to_string())?;
+            let change =
+                PresenceRepository::set_node_status(&transaction, user_id, self.connections.node_id, status)
+                    .await
+                    .map_err(|e| e.to_string())?;
+            transaction.commit().await.map_err(|e| e.to_string())?;
+            Ok::<_, String>(change)
+        }
+        .await;
+
+        match result {
+            Ok((before, after)) if before != after => self.publish(user_id, after, last_seen_at).await,
+            Ok(_) => {}
+            Err(e) => {
+                log::error!("Failed to record presence: {}", e);
+                self.publish(user_id, status, last_seen_at).await;
+            }
+        }
+    }
+
+    async fn record_last_seen(&self, user_id: Uuid, seen_at: NaiveDateTime) {
+        match self.pool.get().await {
+            Ok(client) => {
+                if let Err(e) = PresenceRepository::set_last_seen(&client, user_id, seen_at).await {
+                    log::error!("Failed to record last seen time: {}", e);
+                }
+            }
+            Err(e) => log::error!("Failed to record last seen time: {}", e),
+        }
+    }
+
+    /// Pushes a presence change to the online members of the user's audience
+    async fn publish(&self, user_id: Uuid, status: UserStatus, last_seen_at: Option<NaiveDateTime>) {
+        let audience = match self.pool.get().await {
+            Ok(client) => PresenceRepository::get_audience(&client, user_id).await,
+            Err(e) => {
+                log::error!("Failed to publish presence: {}", e);
+                return;
+            }
+        };
+        let audience = match audience {
+            Ok(audience) => audience,
+            Err(e) => {
+                log::error!("Failed to publish presence: {}", e);
+                return;
+            }
+        };
+
+        let update = WebSocketMessage::Presence(PresenceUpdate {
+            user_id,
+            status,
+            last_seen_at,
+        });
+        // Recipients may be connected to another node, so the whole audience is addressed
+        if let Err(e) = self.connections.send_to_users(audience, update) {
+            log::error!("Failed to publish presence: {}", e);
+        }
+    }
+}
@@ -0,0 +1,186 @@
+// services/profile_service.rs
+
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use deadpool_postgres::Pool;
+use serde_json::json;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    app_state::AppState,
+    models::profile::{UpdateProfileRequest, UserProfile, UserSearchQuery, UserSearchResults},
+    repositories::profile_repository::ProfileRepository,
+    websocket::{connection_manager::ConnectionManager, types::WebSocketMessage},
+};
+
+/// Default and maximum page size of the user directory search
+const DEFAULT_SEARCH_LIMIT: i64 = 20;
+const MAX_SEARCH_LIMIT: i64 = 50;
+
+/// Longest accepted search term, in characters
+const MAX_SEARCH_TERM_LENGTH: usize = 50;
+
+pub struct ProfileService {
+    pool: Pool,
+    connections: ConnectionManager,
+}
+
+impl ProfileService {
+    pub fn new(state: &AppState) -> Self {
+        ProfileService {
+            pool: state.db.clone(),
+            connections: state.connections.clone(),
+        }
+    }
+
+    /// Returns the profile and account details of the authenticated user
+    pub async fn get_own_profile(&self, user_id: Uuid) -> impl IntoResponse {
+        let client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database connection error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        match ProfileRepository::get_own_profile(&client, user_id).await {
+            Ok(Some(profile)) => (StatusCode::OK, Json(json!(profile))).into_response(),
+            Ok(None) => {
+                (StatusCode::NOT_FOUND, Json(json!({ "error": "User not found" }))).into_response()
+            }
+            Err(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Database error" })),
+            )
+                .into_response(),
+        }
+    }
+
+    /// Returns the public profile of a user
+    pub async fn get_profile(&self, username: &str) -> impl IntoResponse {
+        let client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database connection error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        match ProfileRepository::get_profile_by_username(&client, username).await {
+            Ok(Some(profile)) => (StatusCode::OK, Json(json!(profile))).into_response(),
+            Ok(None) => {
+                (StatusCode::NOT_FOUND, Json(json!({ "error": "User not found" }))).into_response()
+            }
+            Err(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Database error" })),
+            )
+                .into_response(),
+        }
+    }
+
+    /// Searches the user directory, excluding the searching user, users they blocked and non-discoverable users
+    pub async fn search(&self, user_id: Uuid, query: UserSearchQuery) -> impl IntoResponse {
+        let term = query.q.trim();
+        if term.is_empty() || term.chars().count() > MAX_SEARCH_TERM_LENGTH {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "error": format!("The search term must be between 1 and {} characters long", MAX_SEARCH_TERM_LENGTH)
+                })),
+            )
+                .into_response();
+        }
+
+        let limit = query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT).clamp(1, MAX_SEARCH_LIMIT);
+        let offset = query.offset.unwrap_or(0).max(0);
+
+        let client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database connection error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        // Fetch one extra row to know whether there is a next page
+        match ProfileRepository::search_profiles(&client, user_id, term, limit + 1, offset).await {
+            Ok(mut results) => {
+                let has_more = results.len() as i64 > limit;
+                results.truncate(limit as usize);
+                let page = UserSearchResults {
+                    results,
+                    limit,
+                    offset,
+                    has_more,
+                };
+                (StatusCode::OK, Json(json!(page))).into_response()
+            }
+            Err(e) => {
+                log::error!("User search failed: {}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database error" })),
+                )
+                    .into_response()
+            }
+        }
+    }
+
+    /// Updates the profile of the authenticated user and pushes the change to their chats
+    pub async fn update_profile(&self, user_id: Uuid, payload: UpdateProfileRequest) -> impl IntoResponse {
+        if let Err(errors) = payload.validate() {
+            return (StatusCode::BAD_REQUEST, Json(json!({ "errors": errors }))).into_response();
+        }
+
+        let client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database connection error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        let profile = match ProfileRepository::update_profile(&client, user_id, &payload).await {
+            Ok(Some(profile)) => profile,
+            Ok(None) => {
+                return (StatusCode::NOT_FOUND, Json(json!({ "error": "User not found" })))
+                    .into_response();
+            }
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Failed to update profile" })),
+                )
+                    .into_response();
+            }
+        };
+
+        // Live updates are best effort; the profile is already saved
+        match ProfileRepository::get_joined_chat_ids(&client, user_id).await {
+            Ok(chat_ids) => self.broadcast_profile(&chat_ids, &profile).await,
+            Err(e) => log::error!("Failed to load chats for profile update: {}", e),
+        }
+
+        (StatusCode::OK, Json(json!(profile))).into_response()
+    }
+
+    async fn broadcast_profile(&self, chat_ids: &[Uuid], profile: &UserProfile) {
+        for chat_id in chat_ids {
+            let message = WebSocketMessage::ProfileUpdated(profile.clone());
+            self.connections.broadcast_to_chat(*chat_id, message);
+        }
+    }
+}
@@ -0,0 +1,86 @@
+// services/request_throttle_service.rs
+
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::NaiveDateTime;
+use deadpool_postgres::Client;
+use serde_json::json;
+use std::net::IpAddr;
+
+use crate::{
+    config::EmailThrottleConfig,
+    repositories::request_throttle_repository::RequestThrottleRepository,
+    utils::token::hash_token,
+};
+
+/// Limits how often an anonymous endpoint may be asked to send emails.
+///
+/// Requests are counted per target email (stored hashed) and per client IP for every input,
+/// whether or not an account exists, so the limit itself reveals nothing about accounts.
+pub struct RequestThrottleService<'a> {
+    config: &'a EmailThrottleConfig,
+}
+
+impl<'a> RequestThrottleService<'a> {
+    pub fn new(config: &'a EmailThrottleConfig) -> Self {
+        RequestThrottleService { config }
+    }
+
+    /// Counts a request for `action` and returns until when the requester must wait, if over the limit
+    pub async fn check(
+        &self,
+        client: &Client,
+        action: &str,
+        email: &str,
+        ip: Option<IpAddr>,
+    ) -> Result<Option<NaiveDateTime>, tokio_postgres::Error> {
+        let email_key = hash_token(&email.trim().to_lowercase());
+        let email_limit = self
+            .register(client, &format!("{}:email", action), &email_key, self.config.max_requests_per_email)
+            .await?;
+        let ip_limit = match ip {
+            Some(ip) => {
+                self.register(client, &format!("{}:ip", action), &ip.to_string(), self.config.max_requests_per_ip)
+                    .await?
+            }
+            None => None,
+        };
+        Ok(email_limit.max(ip_limit))
+    }
+
+    /// Builds the 429 response telling the requester when to try again
+    pub fn throttled_response(until: NaiveDateTime) -> Response {
+        let remaining_ms = (until - chrono::Utc::now().naive_utc()).num_milliseconds();
+        let retry_after = ((remaining_ms + 999) / 1000).max(1);
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after.to_string())],
+            Json(json!({
+                "error": "Too many requests, try again later",
+                "retry_after_seconds": retry_after
+            })),
+        )
+            .into_response()
+    }
+
+    async fn register(
+        &self,
+        client: &Client,
+        scope: &str,
+        key: &str,
+        limit: i32,
+    ) -> Result<Option<NaiveDateTime>, tokio_postgres::Error> {
+        let (requests, window_started_at) =
+            RequestThrottleRepository::record_request(client, scope, key, self.config.window_seconds).await?;
+        if requests <= limit {
+            return Ok(None);
+        }
+        if requests == limit + 1 {
+            log::warn!("Throttling {} for {} after {} requests", scope, key, limit);
+        }
+        Ok(Some(window_started_at + chrono::Duration::seconds(self.config.window_seconds)))
+    }
+}
@@ -0,0 +1,53 @@
+// services/session_cache.rs
+
+use dashmap::DashMap;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Number of cached sessions above which expired entries are swept on insert
+const SWEEP_THRESHOLD: usize = 10_000;
+
+/// Sessions recently found active, so that authenticated requests do not each query the database
+///
+/// Revocations made by this instance take effect immediately. Those made by another instance,
+/// or by the expiry of the session, take effect once the entry is older than the TTL.
+pub struct SessionCache {
+    ttl: Duration,
+    /// Maps session IDs to their user and the time they were last confirmed
+    sessions: DashMap<Uuid, (Uuid, Instant)>,
+}
+
+impl SessionCache {
+    /// Creates a cache keeping sessions for `ttl`; a zero TTL disables caching
+    pub fn new(ttl: Duration) -> Self {
+        SessionCache {
+            ttl,
+            sessions: DashMap::new(),
+        }
+    }
+
+    /// Whether a session of the user was confirmed active within the TTL
+    pub fn is_active(&self, session_id: Uuid, user_id: Uuid) -> bool {
+        match self.sessions.get(&session_id) {
+            Some(entry) => entry.0 == user_id && entry.1.elapsed() < self.ttl,
+            None => false,
+        }
+    }
+
+    /// Remembers a session that the database has just confirmed
+    pub fn remember(&self, session_id: Uuid, user_id: Uuid) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        if self.sessions.len() >= SWEEP_THRESHOLD {
+            self.sessions.retain(|_, (_, checked_at)| checked_at.elapsed() < self.ttl);
+        }
+        self.sessions.insert(session_id, (user_id, Instant::now()));
+    }
+
+    /// Forgets the sessions of a user, except `keep`, once they have been revoked
+    pub fn forget_user(&self, user_id: Uuid, keep: Option<Uuid>) {
+        self.sessions
+            .retain(|session_id, (owner, _)| *owner != user_id || Some(*session_id) == keep);
+    }
+}
@@ -0,0 +1,275 @@
+// services/two_factor_service.rs
+
+use axum::{http::StatusCode, response::IntoResponse, Json};
+use deadpool_postgres::{Client, Pool};
+use serde_json::json;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    config::AppConfig,
+    repositories::two_factor_repository::{TotpState, TwoFactorRepository},
+    utils::{
+        token::{generate_token, hash_token},
+        totp,
+    },
+};
+
+/// Number of recovery codes issued when 2FA is enabled
+const RECOVERY_CODE_COUNT: usize = 10;
+
+pub struct TwoFactorService {
+    pool: Pool,
+    config: Arc<AppConfig>,
+}
+
+impl TwoFactorService {
+    pub fn new(state: &AppState) -> Self {
+        TwoFactorService {
+            pool: state.db.clone(),
+            config: state.config.clone(),
+        }
+    }
+
+    /// Generates a new secret and returns it with an otpauth URI for QR codes
+    ///
+    /// The secret stays inactive until it is confirmed with a valid code.
+    pub async fn enroll(&self, user_id: Uuid) -> impl IntoResponse {
+        let client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database connection error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        let totp_state = match TwoFactorRepository::get_totp_state(&client, user_id).await {
+            Ok(Some(totp_state)) => totp_state,
+            Ok(None) => {
+                return (StatusCode::NOT_FOUND, Json(json!({ "error": "User not found" })))
+                    .into_response();
+            }
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        if totp_state.enabled {
+            return (
+                StatusCode::CONFLICT,
+                Json(json!({ "error": "Two-factor authentication is already enabled" })),
+            )
+                .into_response();
+        }
+
+        let secret = totp::generate_secret();
+        if TwoFactorRepository::set_pending_secret(&client, user_id, &secret)
+            .await
+            .is_err()
+        {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to store secret" })),
+            )
+                .into_response();
+        }
+
+        let otpauth_uri = totp::otpauth_uri(&self.config.two_factor.issuer, &totp_state.username, &secret);
+        (
+            StatusCode::OK,
+            Json(json!({
+                "secret": secret,
+                "otpauth_uri": otpauth_uri
+            })),
+        )
+            .into_response()
+    }
+
+    /// Activates the pending secret once the user proves their app produces valid codes
+    ///
+    /// Returns the recovery codes; they are only shown this once.
+    pub async fn confirm(&self, user_id: Uuid, code: &str) -> impl IntoResponse {
+        let mut client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database connection error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        let secret = match TwoFactorRepository::get_totp_state(&client, user_id).await {
+            Ok(Some(TotpState { enabled: true, .. })) => {
+                return (
+                    StatusCode::CONFLICT,
+                    Json(json!({ "error": "Two-factor authentication is already enabled" })),
+                )
+                    .into_response();
+            }
+            Ok(Some(TotpState { secret: Some(secret), .. })) => secret,
+            Ok(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({ "error": "Start the enrolment first" })),
+                )
+                    .into_response();
+            }
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        let step = match totp::verify_code(&secret, code, chrono::Utc::now().timestamp()) {
+            Some(step) => step,
+            None => {
+                return (StatusCode::BAD_REQUEST, Json(json!({ "error": "Invalid code" })))
+                    .into_response();
+            }
+        };
+
+        let recovery_codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+            .map(|_| generate_recovery_code())
+            .collect();
+        let recovery_code_hashes: Vec<String> = recovery_codes
+            .iter()
+            .map(|code| hash_token(&normalize_recovery_code(code)))
+            .collect();
+
+        let result = async {
+            let transaction = client.transaction().await?;
+            TwoFactorRepository::enable(&transaction, user_id, step, &recovery_code_hashes).await?;
+            transaction.commit().await
+        }
+        .await;
+
+        match result {
+            Ok(_) => (
+                StatusCode::OK,
+                Json(json!({
+                    "message": "Two-factor authentication enabled",
+                    "recovery_codes": recovery_codes
+                })),
+            )
+                .into_response(),
+            Err(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to enable two-factor authentication" })),
+            )
+                .into_response(),
+        }
+    }
+
+    /// Turns two-factor authentication off after checking a TOTP or recovery code
+    pub async fn disable(&self, user_id: Uuid, code: &str) -> impl IntoResponse {
+        let mut client = match self.pool.get().await {
+            Ok(client) => client,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database connection error" })),
+                )
+                    .into_response();
+            }
+        };
+
+        match Self::verify_second_factor(&client, user_id, code).await {
+            Ok(true) => (),
+            Ok(false) => {
+                return (StatusCode::BAD_REQUEST, Json(json!({ "error": "Invalid code" })))
+                    .into_response();
+            }
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": "Database error" })),
+                )
+                    .into_response();
+            }
+        }
+
+        let result = async {
+            let transaction = client.transaction().await?;
+            TwoFactorRepository::disable(&transaction, user_id).await?;
+            transaction.commit().await
+        }
+        .await;
+
+        match result {
+            Ok(_) => (
+                StatusCode::OK,
+                Json(json!({ "message": "Two-factor authentication disabled" })),
+            )
+                .into_response(),
+            Err(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "Failed to disable two-factor authentication" })),
+            )
+                .into_response(),
+        }
+    }
+
+    /// Checks a TOTP code (rejecting replays) or consumes a matching recovery code
+    ///
+    /// Returns `Ok(false)` when 2FA is not enabled for the user or the code does not match.
+    pub async fn verify_second_factor(
+        client: &Client,
+        user_id: Uuid,
+        code: &str,
+    ) -> Result<bool, tokio_postgres::Error> {
+        let secret = match TwoFactorRepository::get_totp_state(client, user_id).await? {
+            Some(TotpState {
+                enabled: true,
+                secret: Some(secret),
+                ..
+            }) => secret,
+            _ => return Ok(false),
+        };
+
+        // Only one login can move the last step forward, so a code is accepted at most once
+        if let Some(step) = totp::verify_code(&secret, code, chrono::Utc::now().timestamp()) {
+            return TwoFactorRepository::record_used_step(client, user_id, step).await;
+        }
+
+        let consumed = TwoFactorRepository::consume_recovery_code(
+            client,
+            user_id,
+            &hash_token(&normalize_recovery_code(code)),
+        )
+        .await?;
+
+        if consumed {
+            let remaining = TwoFactorRepository::remaining_recovery_codes(client, user_id).await?;
+            log::info!("User {} used a recovery code, {} remaining", user_id, remaining);
+        }
+
+        Ok(consumed)
+    }
+}
+
+/// Generates a recovery code in the form `xxxxx-xxxxx`
+fn generate_recovery_code() -> String {
+    let token = generate_token();
+    format!("{}-{}", &token[0..5], &token[5..10])
+}
+
+/// Normalizes a recovery code so dashes, spaces and case do not matter
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
@@ -0,0 +1,94 @@
+// utils/client_ip.rs
+
+use axum::http::HeaderMap;
+use std::net::{IpAddr, SocketAddr};
+
+use crate::config::LoginProtectionConfig;
+
+/// Determines the client IP address of a request
+///
+/// `X-Forwarded-For` is only used when `trust_forwarded_for` is set, since the header is freely
+/// settable by clients that are not behind a proxy. Each proxy appends the address it received
+/// the request from, so only the last `trusted_proxies` entries were written by trusted hops:
+/// the client is the entry that many places from the right, and anything further left may be
+/// forged. A header with fewer entries, or an invalid one, falls back to the peer address.
+pub fn client_ip(headers: &HeaderMap, peer: SocketAddr, config: &LoginProtectionConfig) -> IpAddr {
+    if config.trust_forwarded_for && config.trusted_proxies > 0 {
+        let forwarded = headers
+            .get("X-Forwarded-For")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').nth(config.trusted_proxies - 1))
+            .and_then(|ip| ip.trim().parse::<IpAddr>().ok());
+        if let Some(ip) = forwarded {
+            return ip;
+        }
+    }
+    peer.ip()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PEER: &str = "10.0.0.1:4000";
+
+    fn config(trust_forwarded_for: bool, trusted_proxies: usize) -> LoginProtectionConfig {
+        LoginProtectionConfig {
+            max_failures_per_username: 5,
+            max_failures_per_ip: 20,
+            base_lockout_seconds: 30,
+            max_lockout_seconds: 900,
+            failure_window_seconds: 900,
+            trust_forwarded_for,
+            trusted_proxies,
+        }
+    }
+
+    fn ip_for(forwarded_for: Option<&str>, config: &LoginProtectionConfig) -> IpAddr {
+        let mut headers = HeaderMap::new();
+        if let Some(value) = forwarded_for {
+            headers.insert("X-Forwarded-For", value.parse().unwrap());
+        }
+        client_ip(&headers, PEER.parse().unwrap(), config)
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn ignores_the_header_unless_trusted() {
+        assert_eq!(ip_for(Some("203.0.113.7"), &config(false, 1)), ip("10.0.0.1"));
+        assert_eq!(ip_for(Some("203.0.113.7"), &config(true, 0)), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn takes_the_entry_added_by_the_proxy() {
+        assert_eq!(ip_for(Some("203.0.113.7"), &config(true, 1)), ip("203.0.113.7"));
+        assert_eq!(ip_for(None, &config(true, 1)), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn ignores_entries_forged_by_the_client() {
+        // The client sent "198.51.100.1, 198.51.100.2" and the proxy appended its real address
+        let header = "198.51.100.1, 198.51.100.2, 203.0.113.7";
+        assert_eq!(ip_for(Some(header), &config(true, 1)), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn skips_the_trusted_hops_from_the_right() {
+        // client -> edge proxy -> internal proxy -> server
+        let header = "198.51.100.1, 203.0.113.7, 192.168.1.5";
+        assert_eq!(ip_for(Some(header), &config(true, 2)), ip("203.0.113.7"));
+        assert_eq!(ip_for(Some("2001:db8::1,192.168.1.5"), &config(true, 2)), ip("2001:db8::1"));
+    }
+
+    #[test]
+    fn falls_back_to_the_peer_on_invalid_headers() {
+        assert_eq!(ip_for(Some("not-an-ip"), &config(true, 1)), ip("10.0.0.1"));
+        assert_eq!(ip_for(Some("203.0.113.7, unknown"), &config(true, 1)), ip("10.0.0.1"));
+        assert_eq!(ip_for(Some(""), &config(true, 1)), ip("10.0.0.1"));
+        // Fewer entries than trusted hops: the header did not come through all of them
+        assert_eq!(ip_for(Some("203.0.113.7"), &config(true, 2)), ip("10.0.0.1"));
+    }
+}
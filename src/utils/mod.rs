@@ -1 +1,4 @@
-pub mod password_validator;
\ No newline at end of file
+pub mod client_ip;
+pub mod password_validator;
+pub mod token;
+pub mod totp;
\ No newline at end of file
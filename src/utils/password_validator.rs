@@ -2,8 +2,12 @@
 
 use once_cell::sync::Lazy;
 use regex::Regex;
+use serde::Serialize;
+use std::collections::HashSet;
 use thiserror::Error;
 
+use crate::config::PasswordPolicyConfig;
+
 /// Errors that can occur during password validation
 #[derive(Error, Debug)]
 pub enum PasswordValidationError {
@@ -17,65 +21,229 @@ pub enum PasswordValidationError {
     NoNumber,
     #[error("Password must contain at least one special character")]
     NoSpecialChar,
+    #[error("Password is too common")]
+    TooCommon,
+}
+
+impl PasswordValidationError {
+    /// Stable identifier of the failed rule, for clients that localize messages
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::TooShort(_) => "too_short",
+            Self::NoUppercase => "no_uppercase",
+            Self::NoLowercase => "no_lowercase",
+            Self::NoNumber => "no_number",
+            Self::NoSpecialChar => "no_special_char",
+            Self::TooCommon => "too_common",
+        }
+    }
+}
+
+/// A single failed rule, as returned to clients
+#[derive(Serialize)]
+pub struct PasswordViolation {
+    pub rule: &'static str,
+    pub message: String,
+}
+
+impl From<&PasswordValidationError> for PasswordViolation {
+    fn from(error: &PasswordValidationError) -> Self {
+        Self {
+            rule: error.code(),
+            message: error.to_string(),
+        }
+    }
 }
 
 static UPPERCASE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"[A-Z]").unwrap());
 static LOWERCASE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"[a-z]").unwrap());
 static NUMBER_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\d").unwrap());
-static SPECIAL_CHAR_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r##"[!@#$%^&*(),.?":{}|<>]"##).unwrap());
-
+/// Characters accepted by the special character rule
+const SPECIAL_CHARS: &str = r#"!@#$%^&*(),.?":{}|<>"#;
 
 pub struct PasswordValidator {
     min_length: usize,
-}
-
-impl Default for PasswordValidator {
-    fn default() -> Self {
-        Self { min_length: 8 }
-    }
+    require_uppercase: bool,
+    require_lowercase: bool,
+    require_number: bool,
+    require_special_char: bool,
+    /// Lowercased passwords that are rejected regardless of the other rules
+    denylist: HashSet<String>,
 }
 
 impl PasswordValidator {
-    /// Creates a new password validator with custom minimum length
-    pub fn new(min_length: usize) -> Self {
-        Self { min_length }
+    /// Builds a validator from the configured policy, loading the denylist file if one is set
+    pub fn from_config(config: &PasswordPolicyConfig) -> Result<Self, std::io::Error> {
+        let denylist = match &config.denylist_file {
+            Some(path) => std::fs::read_to_string(path)?
+                .lines()
+                .map(|line| line.trim().to_lowercase())
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .collect(),
+            None => HashSet::new(),
+        };
+
+        Ok(Self {
+            min_length: config.min_length,
+            require_uppercase: config.require_uppercase,
+            require_lowercase: config.require_lowercase,
+            require_number: config.require_number,
+            require_special_char: config.require_special_char,
+            denylist,
+        })
     }
 
-    /// Validates a password and returns a Result with detailed error if validation fails
-    pub fn validate_with_details(&self, password: &str) -> Result<(), PasswordValidationError> {
-        if password.len() < self.min_length {
-            return Err(PasswordValidationError::TooShort(self.min_length));
+    /// Validates a password and returns every rule it breaks if validation fails
+    pub fn validate_with_details(&self, password: &str) -> Result<(), Vec<PasswordValidationError>> {
+        let mut errors = Vec::new();
+
+        if password.chars().count() < self.min_length {
+            errors.push(PasswordValidationError::TooShort(self.min_length));
+        }
+        if self.require_uppercase && !UPPERCASE_REGEX.is_match(password) {
+            errors.push(PasswordValidationError::NoUppercase);
         }
-        if !UPPERCASE_REGEX.is_match(password) {
-            return Err(PasswordValidationError::NoUppercase);
+        if self.require_lowercase && !LOWERCASE_REGEX.is_match(password) {
+            errors.push(PasswordValidationError::NoLowercase);
         }
-        if !LOWERCASE_REGEX.is_match(password) {
-            return Err(PasswordValidationError::NoLowercase);
+        if self.require_number && !NUMBER_REGEX.is_match(password) {
+            errors.push(PasswordValidationError::NoNumber);
         }
-        if !NUMBER_REGEX.is_match(password) {
-            return Err(PasswordValidationError::NoNumber);
+        if self.require_special_char && !password.chars().any(|c| SPECIAL_CHARS.contains(c)) {
+            errors.push(PasswordValidationError::NoSpecialChar);
         }
-        if !SPECIAL_CHAR_REGEX.is_match(password) {
-            return Err(PasswordValidationError::NoSpecialChar);
+        if self.denylist.contains(&password.to_lowercase()) {
+            errors.push(PasswordValidationError::TooCommon);
         }
 
-        Ok(())
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
     }
 
-    /// Simple validation that returns true if password meets all requirements
-    pub fn validate(password: &str) -> bool {
-        Self::default().validate_with_details(password).is_ok()
+    /// Validates a password and returns every failed rule in a client-friendly form
+    pub fn violations(&self, password: &str) -> Vec<PasswordViolation> {
+        match self.validate_with_details(password) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.iter().map(PasswordViolation::from).collect(),
+        }
     }
 
-    /// Returns a string describing all password requirements
-    pub const fn requirements() -> &'static str {
-        "Password must:
-         - Be at least 8 characters long
-         - Contain at least one uppercase letter
-         - Contain at least one lowercase letter
-         - Contain at least one number
-         - Contain at least one special character (!@#$%^&*(),.?\":{}|<>)"
+    /// Returns a description of every password requirement of the current policy
+    pub fn requirements(&self) -> Vec<String> {
+        let mut requirements = vec![format!("Be at least {} characters long", self.min_length)];
+        if self.require_uppercase {
+            requirements.push("Contain at least one uppercase letter".to_string());
+        }
+        if self.require_lowercase {
+            requirements.push("Contain at least one lowercase letter".to_string());
+        }
+        if self.require_number {
+            requirements.push("Contain at least one number".to_string());
+        }
+        if self.require_special_char {
+            requirements.push(format!(
+                "Contain at least one special character ({})",
+                SPECIAL_CHARS
+            ));
+        }
+        if !self.denylist.is_empty() {
+            requirements.push("Not be a commonly used password".to_string());
+        }
+        requirements
+    }
+
+    /// Returns the policy as JSON for the policy endpoint
+    pub fn policy(&self) -> serde_json::Value {
+        serde_json::json!({
+            "min_length": self.min_length,
+            "require_uppercase": self.require_uppercase,
+            "require_lowercase": self.require_lowercase,
+            "require_number": self.require_number,
+            "require_special_char": self.require_special_char,
+            "special_chars": SPECIAL_CHARS,
+            "rejects_common_passwords": !self.denylist.is_empty(),
+            "requirements": self.requirements(),
+        })
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(min_length: usize, denylist_file: Option<String>) -> PasswordPolicyConfig {
+        PasswordPolicyConfig {
+            min_length,
+            require_uppercase: true,
+            require_lowercase: true,
+            require_number: true,
+            require_special_char: true,
+            denylist_file,
+        }
+    }
+
+    fn rules(validator: &PasswordValidator, password: &str) -> Vec<&'static str> {
+        validator.violations(password).iter().map(|violation| violation.rule).collect()
+    }
+
+    #[test]
+    fn reports_every_rule_a_password_breaks() {
+        let validator = PasswordValidator::from_config(&config(8, None)).unwrap();
+        assert_eq!(
+            rules(&validator, "abc"),
+            vec!["too_short", "no_uppercase", "no_number", "no_special_char"]
+        );
+        assert_eq!(rules(&validator, "ABCDEFGH1"), vec!["no_lowercase", "no_special_char"]);
+        assert!(rules(&validator, "Str0ng!Pass").is_empty());
+
+        let violations = validator.violations("abc");
+        assert_eq!(violations[0].message, "Password must be at least 8 characters long");
+    }
+
+    #[test]
+    fn accepts_each_special_character_of_the_policy() {
+        let validator = PasswordValidator::from_config(&config(8, None)).unwrap();
+        for special in SPECIAL_CHARS.chars() {
+            let password = format!("Passw0rd{}", special);
+            assert!(rules(&validator, &password).is_empty(), "{:?}", password);
+        }
+        assert_eq!(rules(&validator, "Passw0rd_"), vec!["no_special_char"]);
+    }
+
+    #[test]
+    fn applies_the_configured_minimum_length() {
+        let validator = PasswordValidator::from_config(&config(12, None)).unwrap();
+        assert_eq!(rules(&validator, "Str0ng!Pass"), vec!["too_short"]);
+        assert!(rules(&validator, "Str0ng!Passw").is_empty());
+        // Characters are counted, not bytes
+        assert!(rules(&validator, "Str0ng!Pässw").is_empty());
+        assert_eq!(rules(&validator, "Str0ng!Päss"), vec!["too_short"]);
+    }
 
+    #[test]
+    fn rejects_passwords_from_the_denylist_file() {
+        let path = std::env::temp_dir().join(format!("rustle_chat-denylist-{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "# common passwords\nPassw0rd!\n\n  Welcome1!  \n").unwrap();
+        let validator =
+            PasswordValidator::from_config(&config(8, Some(path.to_string_lossy().into_owned()))).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        // Matched without regard to case, on top of the other rules
+        assert_eq!(rules(&validator, "passw0rd!"), vec!["no_uppercase", "too_common"]);
+        assert_eq!(rules(&validator, "PASSW0RD!"), vec!["no_lowercase", "too_common"]);
+        assert_eq!(rules(&validator, "Welcome1!"), vec!["too_common"]);
+        // Comment lines are not passwords
+        assert!(!rules(&validator, "# common passwords").contains(&"too_common"));
+        assert!(rules(&validator, "Str0ng!Pass").is_empty());
+        assert_eq!(validator.policy()["rejects_common_passwords"], true);
+    }
+
+    #[test]
+    fn fails_to_load_a_missing_denylist_file() {
+        let missing = std::env::temp_dir().join(format!("rustle_chat-missing-{}.txt", uuid::Uuid::new_v4()));
+        assert!(PasswordValidator::from_config(&config(8, Some(missing.to_string_lossy().into_owned()))).is_err());
+    }
+}
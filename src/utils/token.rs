@@ -0,0 +1,19 @@
+// utils/token.rs
+
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+/// Number of random bytes in a generated token (hex encoded to twice this length)
+const TOKEN_BYTES: usize = 32;
+
+/// Generates a random, URL-safe token suitable for single-use links
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Hashes a token with SHA-256 so only the digest needs to be stored
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
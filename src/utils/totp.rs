@@ -0,0 +1,163 @@
+// utils/totp.rs
+
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::{rngs::OsRng, RngCore};
+use sha1::Sha1;
+
+/// Length of the shared secret in bytes (160 bits, as recommended by RFC 4226)
+const SECRET_BYTES: usize = 20;
+/// Time step in seconds (RFC 6238 default)
+const STEP_SECONDS: i64 = 30;
+/// Number of digits in a generated code
+const DIGITS: u32 = 6;
+/// Number of steps before and after the current one that are still accepted (clock drift)
+const ALLOWED_DRIFT_STEPS: i64 = 1;
+
+/// Generates a new random TOTP secret, encoded as unpadded base32
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; SECRET_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    BASE32_NOPAD.encode(&bytes)
+}
+
+/// Builds an `otpauth://` URI that authenticator apps can import (usually as a QR code)
+pub fn otpauth_uri(issuer: &str, account: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        urlencode(issuer),
+        urlencode(account),
+        secret,
+        urlencode(issuer),
+        DIGITS,
+        STEP_SECONDS
+    )
+}
+
+/// Returns the time step a Unix timestamp falls into
+pub fn time_step(unix_time: i64) -> i64 {
+    unix_time.div_euclid(STEP_SECONDS)
+}
+
+/// Computes the code for a secret at a given time step (RFC 4226 HOTP with a time counter)
+pub fn code_at_step(secret: &str, step: i64) -> Option<String> {
+    let key = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let mut mac = Hmac::<Sha1>::new_from_slice(&key).ok()?;
+    mac.update(&(step as u64).to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    // Dynamic truncation
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+
+    Some(format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    ))
+}
+
+/// Verifies a code against a secret, tolerating a small clock drift
+///
+/// Returns the matching time step so callers can reject replays of the same code.
+pub fn verify_code(secret: &str, code: &str, unix_time: i64) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let current = time_step(unix_time);
+    (current - ALLOWED_DRIFT_STEPS..=current + ALLOWED_DRIFT_STEPS)
+        .find(|step| code_at_step(secret, *step).as_deref() == Some(code))
+}
+
+/// Percent-encodes a label component of the otpauth URI
+fn urlencode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The RFC 6238 SHA-1 key, "12345678901234567890", in base32
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    // RFC 6238 appendix B, keeping the last six of the eight digits
+    const RFC_VECTORS: [(i64, &str); 6] = [
+        (59, "287082"),
+        (1_111_111_109, "081804"),
+        (1_111_111_111, "050471"),
+        (1_234_567_890, "005924"),
+        (2_000_000_000, "279037"),
+        (20_000_000_000, "353130"),
+    ];
+
+    #[test]
+    fn codes_match_the_rfc_6238_vectors() {
+        for (unix_time, code) in RFC_VECTORS {
+            assert_eq!(code_at_step(RFC_SECRET, time_step(unix_time)).as_deref(), Some(code), "{}", unix_time);
+            assert_eq!(verify_code(RFC_SECRET, code, unix_time), Some(time_step(unix_time)), "{}", unix_time);
+        }
+    }
+
+    #[test]
+    fn codes_match_the_rfc_4226_vectors() {
+        let codes = ["755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583", "399871", "520489"];
+        for (counter, code) in codes.iter().enumerate() {
+            assert_eq!(code_at_step(RFC_SECRET, counter as i64).as_deref(), Some(*code), "{}", counter);
+        }
+    }
+
+    #[test]
+    fn codes_are_accepted_one_step_either_way() {
+        let now = 1_234_567_890;
+        let current = time_step(now);
+        for drift in -ALLOWED_DRIFT_STEPS..=ALLOWED_DRIFT_STEPS {
+            let code = code_at_step(RFC_SECRET, current + drift).unwrap();
+            assert_eq!(verify_code(RFC_SECRET, &code, now), Some(current + drift), "{}", drift);
+        }
+        for drift in [-ALLOWED_DRIFT_STEPS - 1, ALLOWED_DRIFT_STEPS + 1] {
+            let code = code_at_step(RFC_SECRET, current + drift).unwrap();
+            assert_eq!(verify_code(RFC_SECRET, &code, now), None, "{}", drift);
+        }
+    }
+
+    #[test]
+    fn steps_change_on_period_boundaries() {
+        assert_eq!(time_step(0), 0);
+        assert_eq!(time_step(STEP_SECONDS - 1), 0);
+        assert_eq!(time_step(STEP_SECONDS), 1);
+        assert_eq!(time_step(-1), -1);
+    }
+
+    #[test]
+    fn malformed_codes_and_secrets_are_rejected() {
+        let code = code_at_step(RFC_SECRET, time_step(59)).unwrap();
+        assert_eq!(verify_code(RFC_SECRET, &format!(" {} ", code), 59), Some(time_step(59)));
+        for code in ["", "28708", "2870820", "28708a", "２８７０８２"] {
+            assert_eq!(verify_code(RFC_SECRET, code, 59), None, "{:?}", code);
+        }
+        assert_eq!(code_at_step("not base32!", 1), None);
+    }
+
+    #[test]
+    fn generated_secrets_decode_to_the_secret_length() {
+        let secret = generate_secret();
+        assert_eq!(BASE32_NOPAD.decode(secret.as_bytes()).unwrap().len(), SECRET_BYTES);
+        assert_ne!(secret, generate_secret());
+    }
+}
@@ -0,0 +1,315 @@
+// websocket/codec.rs
+
+use axum::extract::ws::Message;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+/// Errors that can occur while encoding or decoding a frame
+#[derive(Error, Debug)]
+pub enum CodecError {
+    /// Error when a value cannot be written in the connection's format
+    #[error("Failed to encode frame: {0}")]
+    Encode(String),
+
+    /// Error when a frame is not a valid value in the connection's format
+    #[error("Failed to decode frame: {0}")]
+    Decode(String),
+}
+
+/// Encoding of the frames of a WebSocket connection, chosen by the client when it connects
+///
+/// Every format carries the same structure: `WebSocketMessage` variants are maps with a single
+/// key naming the variant, and structs are maps keyed by field name. JSON is sent as text
+/// frames, the binary formats as binary frames. The binary formats write UUIDs as 16 bytes and
+/// accept them as bytes or strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WireFormat {
+    /// JSON text frames
+    #[default]
+    Json,
+    /// MessagePack binary frames
+    #[serde(rename = "msgpack")]
+    MessagePack,
+    /// CBOR binary frames
+    Cbor,
+}
+
+impl WireFormat {
+    /// WebSocket subprotocols naming each format, in the server's order of preference
+    pub const SUBPROTOCOLS: [&'static str; 3] = ["msgpack", "cbor", "json"];
+
+    /// Returns the format named by a negotiated subprotocol
+    pub fn from_subprotocol(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(Self::Json),
+            "msgpack" => Some(Self::MessagePack),
+            "cbor" => Some(Self::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Whether frames in this format are binary frames
+    pub fn is_binary(self) -> bool {
+        self != Self::Json
+    }
+
+    /// Encodes a value as a frame in this format
+    pub fn encode<T: Serialize>(self, value: &T) -> Result<Message, CodecError> {
+        match self {
+            Self::Json => serde_json::to_string(value)
+                .map(Message::Text)
+                .map_err(|e| CodecError::Encode(e.to_string())),
+            Self::MessagePack => rmp_serde::to_vec_named(value)
+                .map(Message::Binary)
+                .map_err(|e| CodecError::Encode(e.to_string())),
+            Self::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes).map_err(|e| CodecError::Encode(e.to_string()))?;
+                Ok(Message::Binary(bytes))
+            }
+        }
+    }
+
+    /// Decodes the payload of a frame in this format
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, CodecError> {
+        match self {
+            Self::Json => serde_json::from_slice(bytes).map_err(|e| CodecError::Decode(e.to_string())),
+            Self::MessagePack => rmp_serde::from_slice(bytes).map_err(|e| CodecError::Decode(e.to_string())),
+            Self::Cbor => ciborium::from_reader(bytes).map_err(|e| CodecError::Decode(e.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{
+            block::BlockListUpdate, friend::FriendRequestNotification, invitation::InvitationNotification,
+            profile::UserProfile,
+        },
+        websocket::types::{
+            AckMessage, ChatMessage, ChatMessageResponse, ClientMessage, ErrorMessage, GapMessage,
+            GoingAwayMessage, PresenceUpdate, ProtocolLimits, SessionInfo, StatusMessage, UserStatus,
+            WebSocketMessage, WelcomeMessage,
+        },
+    };
+    use chrono::NaiveDate;
+    use serde::de::IgnoredAny;
+    use std::collections::BTreeMap;
+    use uuid::Uuid;
+
+    const FORMATS: [WireFormat; 3] = [WireFormat::Json, WireFormat::MessagePack, WireFormat::Cbor];
+
+    fn payload(frame: &Message) -> &[u8] {
+        match frame {
+            Message::Text(text) => text.as_bytes(),
+            Message::Binary(bytes) => bytes,
+            other => panic!("unexpected frame {:?}", other),
+        }
+    }
+
+    // One message of every variant; the match stops compiling when a variant is added
+    fn samples() -> Vec<WebSocketMessage> {
+        let id = Uuid::parse_str("8c2f0b61-5a4e-4f43-9c55-0d7a6b3e2f10").unwrap();
+        let other_id = Uuid::parse_str("1b9d6bcd-bbfd-4b2d-9b5d-ab8dfbbd4bed").unwrap();
+        let timestamp = NaiveDate::from_ymd_opt(2024, 2, 29)
+            .unwrap()
+            .and_hms_micro_opt(23, 59, 58, 123_456)
+            .unwrap();
+
+        let samples = vec![
+            WebSocketMessage::Response(ChatMessageResponse { content: "ok".to_string() }),
+            WebSocketMessage::Chat(ChatMessage {
+                message_id: id,
+                chat_id: other_id,
+                sender_id: None,
+                content: "héllo, 世界 🦀".to_string(),
+                timestamp,
+                seq: i64::MAX,
+                client_id: Some(other_id),
+                sender_blocked: true,
+            }),
+            WebSocketMessage::Status(StatusMessage {
+                chat_id: id,
+                user_id: other_id,
+                status: UserStatus::Typing,
+                timestamp,
+            }),
+            WebSocketMessage::Error(ErrorMessage {
+                code: "not_member".to_string(),
+                message: "Not a member".to_string(),
+                client_id: None,
+            }),
+            WebSocketMessage::Invitation(InvitationNotification {
+                invitation_id: id,
+                chat_id: other_id,
+                inviter_username: "alice".to_string(),
+                timestamp,
+            }),
+            WebSocketMessage::ProfileUpdated(UserProfile {
+                id,
+                username: "alice".to_string(),
+                display_name: Some("Alice".to_string()),
+                bio: None,
+                avatar_ref: Some(String::new()),
+                status_text: None,
+                status_emoji: Some("🌙".to_string()),
+            }),
+            WebSocketMessage::FriendRequest(FriendRequestNotification {
+                request_id: id,
+                user_id: other_id,
+                username: "bob".to_string(),
+                status: "pending".to_string(),
+                timestamp,
+            }),
+            WebSocketMessage::Presence(PresenceUpdate {
+                user_id: id,
+                status: UserStatus::Idle,
+                last_seen_at: Some(timestamp),
+            }),
+            WebSocketMessage::Gap(GapMessage {
+                chat_id: id,
+                missed: u64::MAX,
+                after: None,
+                before: Some(timestamp),
+                after_seq: Some(0),
+            }),
+            WebSocketMessage::Ack(AckMessage {
+                client_id: id,
+                message_id: other_id,
+                chat_id: id,
+                seq: 1,
+                timestamp,
+            }),
+            WebSocketMessage::Welcome(WelcomeMessage {
+                version: 2,
+                min_version: 1,
+                features: vec!["acks".to_string()],
+                limits: ProtocolLimits {
+                    ping_interval_seconds: 30,
+                    max_missed_pongs: 2,
+                    channel_capacity: 100,
+                    max_lag_events: 3,
+                    lag_window_seconds: 60,
+                },
+                server_time: timestamp,
+                session: SessionInfo {
+                    user_id: id,
+                    username: "alice".to_string(),
+                    session_id: None,
+                    expires_at: Some(timestamp),
+                    chat_id: other_id,
+                    format: WireFormat::Cbor,
+                },
+            }),
+            WebSocketMessage::GoingAway(GoingAwayMessage { reconnect_after_ms: 2_500 }),
+            WebSocketMessage::BlockListUpdated(BlockListUpdate { user_id: id, blocked: false }),
+        ];
+
+        let mut covered = [false; 13];
+        for sample in &samples {
+            covered[match sample {
+                WebSocketMessage::Response(_) => 0,
+                WebSocketMessage::Chat(_) => 1,
+                WebSocketMessage::Status(_) => 2,
+                WebSocketMessage::Error(_) => 3,
+                WebSocketMessage::Invitation(_) => 4,
+                WebSocketMessage::ProfileUpdated(_) => 5,
+                WebSocketMessage::FriendRequest(_) => 6,
+                WebSocketMessage::Presence(_) => 7,
+                WebSocketMessage::Gap(_) => 8,
+                WebSocketMessage::Ack(_) => 9,
+                WebSocketMessage::Welcome(_) => 10,
+                WebSocketMessage::GoingAway(_) => 11,
+                WebSocketMessage::BlockListUpdated(_) => 12,
+            }] = true;
+        }
+        assert!(covered.iter().all(|&covered| covered), "a variant has no sample");
+        samples
+    }
+
+    #[test]
+    fn every_variant_round_trips_in_every_format() {
+        for sample in samples() {
+            let expected = serde_json::to_value(&sample).unwrap();
+            for format in FORMATS {
+                let frame = format.encode(&sample).unwrap();
+                assert_eq!(matches!(frame, Message::Binary(_)), format.is_binary());
+
+                let decoded: WebSocketMessage = format.decode(payload(&frame)).unwrap();
+                assert_eq!(serde_json::to_value(&decoded).unwrap(), expected, "{:?}", format);
+            }
+        }
+    }
+
+    #[test]
+    fn binary_formats_carry_the_json_structure() {
+        for sample in samples() {
+            let expected = serde_json::to_value(&sample).unwrap();
+            let variant = expected.as_object().unwrap().keys().next().unwrap().clone();
+            for format in [WireFormat::MessagePack, WireFormat::Cbor] {
+                let frame = format.encode(&sample).unwrap();
+
+                // Read back without the Rust types: a map keyed by the variant, then by field name
+                type Fields = BTreeMap<String, BTreeMap<String, IgnoredAny>>;
+                let value: Fields = match format {
+                    WireFormat::MessagePack => rmp_serde::from_slice(payload(&frame)).unwrap(),
+                    _ => ciborium::from_reader(payload(&frame)).unwrap(),
+                };
+                let fields: Vec<&String> = value[&variant].keys().collect();
+                let expected_fields: Vec<&String> = expected[&variant].as_object().unwrap().keys().collect();
+                assert_eq!(fields, expected_fields, "{:?} {}", format, variant);
+            }
+        }
+    }
+
+    #[test]
+    fn client_frames_decode_in_every_format() {
+        let client_id = Uuid::new_v4();
+        let frames = [
+            serde_json::json!({"type": "typing"}),
+            serde_json::json!({"type": "send", "client_id": client_id, "content": "hi"}),
+            serde_json::json!({"type": "hello", "version": 2, "features": ["threads", "acks"]}),
+        ];
+        for format in FORMATS {
+            let frame = format.encode(&frames[0]).unwrap();
+            assert!(matches!(format.decode(payload(&frame)).unwrap(), ClientMessage::Typing));
+
+            let frame = format.encode(&frames[1]).unwrap();
+            match format.decode(payload(&frame)).unwrap() {
+                ClientMessage::Send { client_id: decoded, content } => {
+                    assert_eq!(decoded, client_id);
+                    assert_eq!(content, "hi");
+                }
+                other => panic!("{:?} decoded as {:?}", format, other),
+            }
+
+            let frame = format.encode(&frames[2]).unwrap();
+            match format.decode(payload(&frame)).unwrap() {
+                ClientMessage::Hello { version, features } => {
+                    assert_eq!(version, 2);
+                    assert_eq!(features, ["threads", "acks"]);
+                }
+                other => panic!("{:?} decoded as {:?}", format, other),
+            }
+        }
+    }
+
+    #[test]
+    fn formats_are_named_by_subprotocol_and_query() {
+        for name in WireFormat::SUBPROTOCOLS {
+            let from_query: WireFormat = serde_json::from_value(serde_json::json!(name)).unwrap();
+            assert_eq!(WireFormat::from_subprotocol(name), Some(from_query));
+        }
+        assert_eq!(WireFormat::from_subprotocol("xml"), None);
+    }
+
+    #[test]
+    fn malformed_frames_are_rejected() {
+        for format in FORMATS {
+            assert!(format.decode::<WebSocketMessage>(&[0xc1, 0xff, 0x00]).is_err());
+        }
+    }
+}
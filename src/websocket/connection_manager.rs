@@ -1,27 +1,89 @@
-use chrono::Utc;
+use chrono::{NaiveDateTime, Utc};
+use dashmap::DashMap;
+use rand::Rng;
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex, RwLock};
-use tokio::sync::broadcast;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, watch, Notify};
 use uuid::Uuid;
 use deadpool_postgres::Pool;
 
-use super::types::{UserStatus, WebSocketMessage};
+use super::pubsub::{ClusterEvent, PubSub};
+use super::types::{GapMessage, GoingAwayMessage, StatusMessage, UserStatus, WebSocketMessage};
+
+// Minimum delay between two typing events of the same user in a chat
+const TYPING_THROTTLE: Duration = Duration::from_secs(2);
+
+// A user stops typing when no typing frame has refreshed the state for this long
+const TYPING_TIMEOUT: Duration = Duration::from_secs(5);
 
 // Represents an online user
 #[derive(Debug, Clone)]
 pub struct OnlineUser {
-    pub id: Uuid, // User ID
     pub username: String, // Username
     pub sender: broadcast::Sender<WebSocketMessage>, // Sender for WebSocket messages
+    pub status: UserStatus, // Presence across all of the user's connections (Online or Idle)
 }
 
-// Connection manager for handling active chats and user connections
+// Connection manager for handling active chats and user connections.
+// Chat rooms live in a sharded map, so operations on different chats rarely wait for each other.
+// Shard guards and the user directory lock are never held across an await.
 #[derive(Clone)]
 pub struct ConnectionManager {
-    pub chats: Arc<Mutex<HashMap<Uuid, ChatRoom>>>, // Maps chat IDs to chat rooms
-    pub connections: Arc<RwLock<HashMap<Uuid, OnlineUser>>>, // Maps user IDs to online users
-    pub usernames: Arc<RwLock<HashMap<String, Uuid>>>, // Maps usernames to user IDs
+    pub chats: Arc<DashMap<Uuid, ChatRoom>>, // Maps chat IDs to chat rooms
+    pub users: Arc<RwLock<UserDirectory>>, // Online users, indexed by ID and by username
     pub db_pool: Pool, // Database connection pool (agora é Pool diretamente)
+    pub channel_capacity: usize, // Messages buffered per channel before slow receivers lag
+    pub send_locks: Arc<DashMap<Uuid, Weak<tokio::sync::Mutex<()>>>>, // Serializes sending in each chat
+    pub pubsub: Arc<dyn PubSub>, // Carries events to the other nodes of the cluster
+    pub shutdown: Arc<watch::Sender<Option<Duration>>>, // Reconnect window, set once the server shuts down
+    pub open_connections: Arc<AtomicUsize>, // WebSocket and event stream connections still open
+    pub drained: Arc<Notify>, // Notified when the last open connection closes
+    pub node_id: Uuid, // Identifies this node in the presence shared by all nodes
+    pub live_connections: Arc<DashMap<Uuid, LiveConnection>>, // Open connections by ID, with the session that opened them
+}
+
+// The session a connection was opened with, to close the connection once the session is revoked
+pub struct LiveConnection {
+    pub user_id: Uuid, // Owner of the session
+    pub session_id: Uuid, // ID of the `sessions` row
+    pub revoked: Arc<Notify>, // Tells the connection its session was revoked
+}
+
+// Keeps a connection counted as open until it is dropped
+pub struct ConnectionGuard {
+    pub id: Uuid, // Identifies the connection in the chat rooms it joins
+    pub evicted: Arc<Notify>, // Notified when the stale connection sweeper removes the connection
+    pub revoked: Arc<Notify>, // Notified when the session the connection was opened with is revoked
+    open_connections: Arc<AtomicUsize>,
+    drained: Arc<Notify>,
+    live_connections: Arc<DashMap<Uuid, LiveConnection>>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.live_connections.remove(&self.id);
+        if self.open_connections.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.drained.notify_waiters();
+        }
+    }
+}
+
+// Online users and their usernames, updated together under one lock so they never disagree
+#[derive(Default)]
+pub struct UserDirectory {
+    pub connections: HashMap<Uuid, OnlineUser>, // Maps user IDs to online users
+    pub usernames: HashMap<String, Uuid>, // Maps usernames to user IDs
+}
+
+impl UserDirectory {
+    // Removes a user and their username entry
+    fn remove(&mut self, user_id: Uuid) -> Option<OnlineUser> {
+        let user = self.connections.remove(&user_id)?;
+        self.usernames.remove(&user.username);
+        Some(user)
+    }
 }
 
 // Represents a chat room
@@ -30,40 +92,120 @@ pub struct ChatRoom {
     pub channel: broadcast::Sender<WebSocketMessage>, // Channel for broadcasting messages
 }
 
-// Represents a user's connection within a chat
+// Represents a user's presence within a chat, over all of their connections to it
 pub struct UserConnection {
     pub sender: broadcast::Sender<WebSocketMessage>, // Sender for WebSocket messages
-    pub status: UserStatus, // User's status (online/offline)
     pub last_activity: chrono::DateTime<chrono::Utc>, // Timestamp of the last activity
+    pub connections: HashMap<Uuid, ChatConnection>, // WebSockets and event streams of the user in the chat, by connection ID
+    pub typing: Option<TypingState>, // Set while the user is typing in the chat
+}
+
+// Represents one WebSocket or event stream of a user within a chat
+pub struct ChatConnection {
+    pub last_heartbeat: chrono::DateTime<chrono::Utc>, // Last sign of life from the client, including pongs
+    pub evicted: Arc<Notify>, // Tells the connection it was evicted
+}
+
+// Tracks a user who is typing, to throttle and expire their typing events
+pub struct TypingState {
+    pub last_broadcast: Instant, // When the chat was last told the user is typing
+    pub last_refresh: Instant, // When the client last reported typing
 }
 
 impl ConnectionManager {
     // Creates a new ConnectionManager instance
-    pub fn new(db_pool: Pool) -> Self {
+    pub fn new(db_pool: Pool, channel_capacity: usize, pubsub: Arc<dyn PubSub>) -> Self {
         Self {
-            chats: Arc::new(Mutex::new(HashMap::new())), // Initialize empty chats
-            connections: Arc::new(RwLock::new(HashMap::new())), // Initialize empty connections
-            usernames: Arc::new(RwLock::new(HashMap::new())), // Initialize empty usernames
+            chats: Arc::new(DashMap::new()), // Initialize empty chats
+            users: Arc::new(RwLock::new(UserDirectory::default())), // Initialize empty user directory
             db_pool, // Initialize the database pool (agora é Pool diretamente)
+            channel_capacity: channel_capacity.max(1), // broadcast channels need room for at least one message
+            send_locks: Arc::new(DashMap::new()), // Initialize empty send locks
+            pubsub, // Initialize the cross-node pub/sub backend
+            shutdown: Arc::new(watch::channel(None).0), // Not shutting down yet
+            open_connections: Arc::new(AtomicUsize::new(0)), // No connections yet
+            drained: Arc::new(Notify::new()), // Nobody waits for the drain yet
+            node_id: Uuid::new_v4(), // A new identity on every start
+            live_connections: Arc::new(DashMap::new()), // No connections yet
+        }
+    }
+
+    // Tells every connection that the server is shutting down; clients are asked to reconnect
+    // after a random delay within `reconnect_window`
+    pub fn shut_down(&self, reconnect_window: Duration) {
+        self.shutdown.send_replace(Some(reconnect_window));
+    }
+
+    // Returns a receiver for `going_away`, to be created when the connection opens
+    pub fn shutdown_receiver(&self) -> watch::Receiver<Option<Duration>> {
+        self.shutdown.subscribe()
+    }
+
+    // Counts a connection opened with a session of a user as open until the returned guard is dropped
+    pub fn track_connection(&self, user_id: Uuid, session_id: Uuid) -> ConnectionGuard {
+        self.open_connections.fetch_add(1, Ordering::SeqCst);
+        let id = Uuid::new_v4();
+        let revoked = Arc::new(Notify::new());
+        self.live_connections.insert(
+            id,
+            LiveConnection {
+                user_id,
+                session_id,
+                revoked: revoked.clone(),
+            },
+        );
+        ConnectionGuard {
+            id,
+            evicted: Arc::new(Notify::new()),
+            revoked,
+            open_connections: self.open_connections.clone(),
+            drained: self.drained.clone(),
+            live_connections: self.live_connections.clone(),
         }
     }
 
-    // Adds a user to a chat room and returns a message receiver for WebSocket communication
+    // Closes the connections of a user opened with sessions that were revoked, on every node;
+    // `keep` is the session still in use, if any
+    pub fn disconnect_sessions(&self, user_id: Uuid, keep: Option<Uuid>) {
+        self.close_sessions(user_id, keep);
+        self.share(ClusterEvent::SessionsRevoked { user_id, keep });
+    }
+
+    // Tells the local connections of a user opened with another session than `keep` to close
+    fn close_sessions(&self, user_id: Uuid, keep: Option<Uuid>) {
+        for connection in self.live_connections.iter() {
+            if connection.user_id == user_id && Some(connection.session_id) != keep {
+                // Stored as a permit if the connection is not waiting right now
+                connection.revoked.notify_one();
+            }
+        }
+    }
+
+    // Waits until every open connection has closed
+    pub async fn drained(&self) {
+        loop {
+            // Registered before checking, so that the last connection closing in between is not missed
+            let notified = self.drained.notified();
+            if self.open_connections.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    // Adds a connection of a user to a chat room and returns a message receiver for WebSocket communication,
+    // along with whether this is the user's first connection to the chat (i.e. they just joined it)
     pub async fn add_user_to_chat(
         &self,
         chat_id: Uuid, // The ID of the chat
         user_id: Uuid, // The ID of the user
-    ) -> Result<broadcast::Receiver<WebSocketMessage>, String> {
+        connection: &ConnectionGuard, // The connection joining the chat
+    ) -> Result<(broadcast::Receiver<WebSocketMessage>, bool), String> {
         log::info!("Adding user {} to chat {}", user_id, chat_id);
-        
-        let mut chats = self.chats.lock().map_err(|e| {
-            log::error!("Error locking chats: {}", e);
-            format!("Failed to lock chat rooms: {}", e)
-        })?;
     
-        let chat_room = chats.entry(chat_id).or_insert_with(|| {
+        let mut chat_room = self.chats.entry(chat_id).or_insert_with(|| {
             log::info!("Creating new chat room {}", chat_id);
-            let (tx, _) = broadcast::channel(100); // Create a new broadcast channel for the chat
+            let (tx, _) = broadcast::channel(self.channel_capacity); // Create a new broadcast channel for the chat
             ChatRoom {
                 users: HashMap::new(), // Initialize empty users list
                 channel: tx,
@@ -71,113 +213,393 @@ impl ConnectionManager {
         });
     
         // If the user is not already in the chat, add them
-        if !chat_room.users.contains_key(&user_id) {
+        let joined = !chat_room.users.contains_key(&user_id);
+        let user_conn = chat_room.users.entry(user_id).or_insert_with(|| {
             log::info!("Adding new user {} to chat {}", user_id, chat_id);
-            let (tx, _) = broadcast::channel(100); // Create a new sender for the user
-            let user_conn = UserConnection {
+            let (tx, _) = broadcast::channel(self.channel_capacity); // Create a new sender for the user
+            UserConnection {
                 sender: tx,
-                status: UserStatus::Online, // Set user status to online
                 last_activity: Utc::now(),
-            };
-            chat_room.users.insert(user_id, user_conn); // Insert user into the chat
-        }
+                connections: HashMap::new(),
+                typing: None,
+            }
+        });
+        user_conn.connections.insert(
+            connection.id,
+            ChatConnection {
+                last_heartbeat: Utc::now(),
+                evicted: connection.evicted.clone(),
+            },
+        );
     
         log::info!("User {} successfully added to chat {}", user_id, chat_id);
-        Ok(chat_room.channel.subscribe()) // Return the receiver to listen for messages
+        Ok((chat_room.channel.subscribe(), joined)) // Return the receiver to listen for messages
     }
 
-    // Removes a user from a chat room
-    pub fn remove_user_from_chat(&self, chat_id: Uuid, user_id: Uuid) -> Result<(), String> {
-        let mut chats = self.chats.lock().map_err(|_| "Failed to lock chat rooms")?;
+    // Removes a connection of a user from a chat room; returns whether it was the user's last
+    // connection to the chat (i.e. they left it), which is false if it had already been removed
+    pub fn remove_user_from_chat(&self, chat_id: Uuid, user_id: Uuid, connection_id: Uuid) -> Result<bool, String> {
+        let mut left = false;
+        if let Some(mut chat_room) = self.chats.get_mut(&chat_id) {
+            if let Some(user_conn) = chat_room.users.get_mut(&user_id) {
+                if user_conn.connections.remove(&connection_id).is_some() && user_conn.connections.is_empty() {
+                    chat_room.users.remove(&user_id); // Remove user from chat
+                    left = true;
+                }
+            }
+        }
 
-        if let Some(chat_room) = chats.get_mut(&chat_id) {
-            chat_room.users.remove(&user_id); // Remove user from chat
+        // If no users are left in the chat, remove the chat room itself, unless someone joined meanwhile
+        self.chats.remove_if(&chat_id, |_, chat_room| chat_room.users.is_empty());
 
-            // If no users are left in the chat, remove the chat room itself
-            if chat_room.users.is_empty() {
-                chats.remove(&chat_id);
+        Ok(left)
+    }
+
+    // Broadcasts a message to everyone connected to a chat, on this node and the others
+    pub fn broadcast_to_chat(&self, chat_id: Uuid, message: WebSocketMessage) {
+        let chat_room = self.chats.get(&chat_id);
+        self.send_to_chat(chat_room.as_deref(), chat_id, message);
+    }
+
+    // Returns the lock held while a message of the chat is stored and broadcast, so that
+    // messages are broadcast in the order of their sequence numbers
+    pub fn chat_send_lock(&self, chat_id: Uuid) -> Arc<tokio::sync::Mutex<()>> {
+        let mut entry = self.send_locks.entry(chat_id).or_default();
+        if let Some(lock) = entry.upgrade() {
+            return lock;
+        }
+        let lock = Arc::new(tokio::sync::Mutex::new(()));
+        *entry = Arc::downgrade(&lock);
+        lock
+    }
+
+    // Sends a message to the members of a chat connected to this node and to the other nodes.
+    // Called while holding the chat's entry, so that typing updates reach every node in order.
+    fn send_to_chat(&self, chat_room: Option<&ChatRoom>, chat_id: Uuid, message: WebSocketMessage) {
+        if let Some(chat_room) = chat_room {
+            let _ = chat_room.channel.send(message.clone());
+        }
+        self.share(ClusterEvent::Chat { chat_id, message });
+    }
+
+    // Publishes an event already delivered on this node to the other nodes
+    fn share(&self, event: ClusterEvent) {
+        if let Err(e) = self.pubsub.publish(&event) {
+            log::error!("{}", e);
+        }
+    }
+
+    // Delivers an event published by another node to the matching connections of this node
+    pub fn deliver_local(&self, event: &ClusterEvent) {
+        match event {
+            ClusterEvent::Chat { chat_id, message } => {
+                if let Some(chat_room) = self.chats.get(chat_id) {
+                    let _ = chat_room.channel.send(message.clone());
+                }
+            }
+            ClusterEvent::Direct { user_ids, message } => {
+                if let Ok(users) = self.users.read() {
+                    for user_id in user_ids {
+                        if let Some(user) = users.connections.get(user_id) {
+                            let _ = user.sender.send(message.clone());
+                        }
+                    }
+                }
+            }
+            ClusterEvent::SessionsRevoked { user_id, keep } => self.close_sessions(*user_id, *keep),
+            ClusterEvent::Dropped { missed } => {
+                self.report_relay_gap(*missed, None, Some(Utc::now().naive_utc()))
             }
         }
+    }
 
-        Ok(())
+    // Tells the local connections of every chat that events relayed from other nodes may have
+    // been lost between `after` and `before`; each connection adds where its client stands
+    pub fn report_relay_gap(&self, missed: u64, after: Option<NaiveDateTime>, before: Option<NaiveDateTime>) {
+        log::warn!("Reporting a gap in the events relayed from other nodes to {} chats", self.chats.len());
+        for chat_room in self.chats.iter() {
+            let gap = WebSocketMessage::Gap(GapMessage {
+                chat_id: *chat_room.key(),
+                missed,
+                after,
+                before,
+                after_seq: None,
+            });
+            let _ = chat_room.channel.send(gap);
+        }
     }
 
-    // Broadcasts a message to all users in a specific chat room
-    pub fn broadcast_message(
-        &self,
-        message: WebSocketMessage, // The message to broadcast
-        chat_id: Uuid, // The ID of the chat room
-        sender_id: Uuid, // The ID of the user sending the message
-    ) -> Result<(), String> {
-        let chats = self.chats.lock().map_err(|_| "Failed to lock chat rooms")?;
+    // Marks a user as typing in a chat and tells the other members, at most once per TYPING_THROTTLE
+    pub fn start_typing(&self, chat_id: Uuid, user_id: Uuid) -> Result<(), String> {
+        let mut chat_room = match self.chats.get_mut(&chat_id) {
+            Some(chat_room) => chat_room,
+            None => return Ok(()),
+        };
+        let user_conn = match chat_room.users.get_mut(&user_id) {
+            Some(user_conn) => user_conn,
+            None => return Ok(()),
+        };
+
+        let now = Instant::now();
+        let (notify, started) = match user_conn.typing.as_mut() {
+            Some(state) => {
+                state.last_refresh = now;
+                let notify = now.duration_since(state.last_broadcast) >= TYPING_THROTTLE;
+                if notify {
+                    state.last_broadcast = now;
+                }
+                (notify, false)
+            }
+            None => {
+                user_conn.typing = Some(TypingState {
+                    last_broadcast: now,
+                    last_refresh: now,
+                });
+                (true, true)
+            }
+        };
+
+        if notify {
+            self.send_to_chat(Some(&*chat_room), chat_id, typing_status(chat_id, user_id, UserStatus::Typing));
+        }
+        drop(chat_room);
 
-        if let Some(chat_room) = chats.get(&chat_id) {
-            let _ = chat_room.channel.send(message); // Send the message to all users in the chat
+        // One expiry watcher per typing streak
+        if started {
+            let manager = self.clone();
+            tokio::spawn(async move { manager.expire_typing(chat_id, user_id).await });
         }
 
         Ok(())
     }
 
-    // Broadcasts a message to a chat room asynchronously
-    pub async fn broadcast_to_chat(
-        &self,
-        chat_id: Uuid, // The ID of the chat room
-        sender_id: Uuid, // The ID of the user sending the message
-        message: WebSocketMessage, // The message to broadcast
-    ) -> Result<(), String> {
-        let chats = self.chats.lock().map_err(|_| "Failed to lock chat rooms")?;
-
-        if let Some(chat_room) = chats.get(&chat_id) {
-            let _ = chat_room.channel.send(message); // Send the message to all users in the chat
+    // Clears the typing state of a user, telling the chat they stopped if they were typing
+    pub fn stop_typing(&self, chat_id: Uuid, user_id: Uuid) -> Result<(), String> {
+        if let Some(mut chat_room) = self.chats.get_mut(&chat_id) {
+            let was_typing = chat_room
+                .users
+                .get_mut(&user_id)
+                .and_then(|user_conn| user_conn.typing.take())
+                .is_some();
+            if was_typing {
+                self.send_to_chat(Some(&*chat_room), chat_id, typing_status(chat_id, user_id, UserStatus::Online));
+            }
         }
 
         Ok(())
     }
 
-    // Updates the status of a user in a chat room (e.g., online/offline)
-    pub fn update_user_status(
+    // Waits until the typing state of a user has not been refreshed for TYPING_TIMEOUT, then clears it
+    async fn expire_typing(&self, chat_id: Uuid, user_id: Uuid) {
+        loop {
+            let deadline = {
+                let mut chat_room = match self.chats.get_mut(&chat_id) {
+                    Some(chat_room) => chat_room,
+                    None => return,
+                };
+                let deadline = match chat_room.users.get(&user_id).and_then(|c| c.typing.as_ref()) {
+                    Some(state) => state.last_refresh + TYPING_TIMEOUT,
+                    None => return, // Already stopped
+                };
+                if deadline <= Instant::now() {
+                    if let Some(user_conn) = chat_room.users.get_mut(&user_id) {
+                        user_conn.typing = None;
+                    }
+                    self.send_to_chat(Some(&*chat_room), chat_id, typing_status(chat_id, user_id, UserStatus::Online));
+                    return;
+                }
+                deadline
+            };
+            tokio::time::sleep_until(deadline.into()).await;
+        }
+    }
+
+    // Registers a WebSocket connection of a user and returns a receiver for messages sent directly to them,
+    // along with whether this is the user's first connection (i.e. they just came online)
+    pub fn register_user(
         &self,
-        chat_id: Uuid,
         user_id: Uuid,
-        status: UserStatus, // The new status for the user
-    ) -> Result<(), String> {
-        let mut chats = self.chats.lock().map_err(|_| "Failed to lock chat rooms")?;
+        username: String,
+    ) -> Result<(broadcast::Receiver<WebSocketMessage>, bool), String> {
+        let mut users = self.users.write().map_err(|_| "Failed to lock user directory")?;
+        let UserDirectory { connections, usernames } = &mut *users;
+
+        // A user with several sockets shares one direct channel
+        let came_online = !connections.contains_key(&user_id);
+        let user = connections.entry(user_id).or_insert_with(|| {
+            let (tx, _) = broadcast::channel(self.channel_capacity);
+            OnlineUser {
+                username: username.clone(),
+                sender: tx,
+                status: UserStatus::Online,
+            }
+        });
+        usernames.insert(username, user_id);
+
+        Ok((user.sender.subscribe(), came_online))
+    }
+
+    // Unregisters a user once their last WebSocket connection has dropped its receiver;
+    // returns whether the user went offline
+    pub fn unregister_user(&self, user_id: Uuid) -> Result<bool, String> {
+        let mut users = self.users.write().map_err(|_| "Failed to lock user directory")?;
 
-        if let Some(chat_room) = chats.get_mut(&chat_id) {
+        let unused = users
+            .connections
+            .get(&user_id)
+            .is_some_and(|user| user.sender.receiver_count() == 0);
+        if unused {
+            users.remove(user_id);
+        }
+
+        Ok(unused)
+    }
+
+    // Records activity of a user in a chat; returns true if this brings them back from Idle
+    pub fn touch(&self, chat_id: Uuid, user_id: Uuid) -> Result<bool, String> {
+        if let Some(mut chat_room) = self.chats.get_mut(&chat_id) {
             if let Some(user_conn) = chat_room.users.get_mut(&user_id) {
-                user_conn.status = status; // Update the user's status
-                user_conn.last_activity = chrono::Utc::now(); // Update the last activity timestamp
+                user_conn.last_activity = Utc::now();
             }
         }
 
+        let mut users = self.users.write().map_err(|_| "Failed to lock user directory")?;
+        match users.connections.get_mut(&user_id) {
+            Some(user) if user.status == UserStatus::Idle => {
+                user.status = UserStatus::Online;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    // Records that a connection of a user in a chat is still alive (any frame, including pongs)
+    pub fn heartbeat(&self, chat_id: Uuid, user_id: Uuid, connection_id: Uuid) -> Result<(), String> {
+        if let Some(mut chat_room) = self.chats.get_mut(&chat_id) {
+            if let Some(connection) = chat_room
+                .users
+                .get_mut(&user_id)
+                .and_then(|user_conn| user_conn.connections.get_mut(&connection_id))
+            {
+                connection.last_heartbeat = Utc::now();
+            }
+        }
         Ok(())
     }
 
-    // Sends a direct message to a specific user
+    // Evicts connections without a heartbeat since `cutoff`, announcing users left without a
+    // connection to a chat as Offline to it; returns the users left without any connection
+    pub fn evict_stale_connections(&self, cutoff: chrono::DateTime<Utc>) -> Result<Vec<Uuid>, String> {
+        let mut evicted = Vec::new();
+        let mut still_connected = std::collections::HashSet::new();
+        for mut entry in self.chats.iter_mut() {
+            let chat_id = *entry.key();
+            let chat_room = entry.value_mut();
+            {
+                let mut left = Vec::new();
+                for (user_id, user_conn) in chat_room.users.iter_mut() {
+                    user_conn.connections.retain(|connection_id, connection| {
+                        let stale = connection.last_heartbeat < cutoff;
+                        if stale {
+                            log::info!("Evicting stale connection {} of user {} from chat {}", connection_id, user_id, chat_id);
+                            // Stored as a permit if the connection is not waiting right now
+                            connection.evicted.notify_one();
+                        }
+                        !stale
+                    });
+                    if user_conn.connections.is_empty() {
+                        left.push(*user_id);
+                    }
+                }
+                for user_id in left {
+                    let offline = WebSocketMessage::Status(StatusMessage {
+                        chat_id,
+                        user_id,
+                        status: UserStatus::Offline,
+                        timestamp: Utc::now().naive_utc(),
+                    });
+                    self.send_to_chat(Some(&*chat_room), chat_id, offline);
+                    chat_room.users.remove(&user_id);
+                    evicted.push(user_id);
+                }
+                still_connected.extend(chat_room.users.keys().copied());
+            }
+        }
+        self.chats.retain(|_, chat_room| !chat_room.users.is_empty());
+
+        // Forget the send locks of chats nobody is sending to
+        self.send_locks.retain(|_, lock| lock.strong_count() > 0);
+
+        // Users with no live connection left go offline even if their socket task is stuck
+        let mut users = self.users.write().map_err(|_| "Failed to lock user directory")?;
+        let mut offline = Vec::new();
+        for user_id in evicted {
+            if still_connected.contains(&user_id) || offline.contains(&user_id) {
+                continue;
+            }
+            if users.remove(user_id).is_some() {
+                offline.push(user_id);
+            }
+        }
+
+        Ok(offline)
+    }
+
+    // Marks online users without activity since `cutoff` in any of their chats as Idle,
+    // returning each of them with the time of their last activity
+    pub fn mark_idle_users(
+        &self,
+        cutoff: chrono::DateTime<Utc>,
+    ) -> Result<Vec<(Uuid, chrono::DateTime<Utc>)>, String> {
+        // Latest activity of each user over all of their chat rooms
+        let mut last_activity: HashMap<Uuid, chrono::DateTime<Utc>> = HashMap::new();
+        for chat_room in self.chats.iter() {
+            for (user_id, user_conn) in &chat_room.users {
+                let latest = last_activity.entry(*user_id).or_insert(user_conn.last_activity);
+                if user_conn.last_activity > *latest {
+                    *latest = user_conn.last_activity;
+                }
+            }
+        }
+
+        let mut users = self.users.write().map_err(|_| "Failed to lock user directory")?;
+        let mut idle = Vec::new();
+        for (user_id, user) in users.connections.iter_mut() {
+            if user.status != UserStatus::Online {
+                continue;
+            }
+            if let Some(&latest) = last_activity.get(user_id) {
+                if latest < cutoff {
+                    user.status = UserStatus::Idle;
+                    idle.push((*user_id, latest));
+                }
+            }
+        }
+
+        Ok(idle)
+    }
+
+    // Sends a direct message to a specific user, on whichever node they are connected
     pub async fn send_direct_message(
         &self,
         user_id: Uuid, // The ID of the user to send the message to
         message: WebSocketMessage, // The message to send
     ) -> Result<(), String> {
-        let connections = self.connections.read().map_err(|_| "Failed to lock user connections")?;
-
-        if let Some(user) = connections.get(&user_id) {
-            user.sender
-                .send(message) // Send the message to the user's sender
-                .map_err(|_| "Failed to send message")?;
-            Ok(())
-        } else {
-            Err("User not found".to_string()) // Return error if user not found
-        }
+        self.send_to_users(vec![user_id], message)
     }
 
-    // Retrieves an online user by their username
-    pub async fn get_online_user(&self, username: &str) -> Option<OnlineUser> {
-        let usernames = self.usernames.read().ok()?; // Get the usernames map
-        let user_id = usernames.get(username)?; // Find the user ID by username
+    // Sends the same direct message to several users, with a single event for the other nodes
+    pub fn send_to_users(&self, user_ids: Vec<Uuid>, message: WebSocketMessage) -> Result<(), String> {
+        let users = self.users.read().map_err(|_| "Failed to lock user directory")?;
 
-        let connections = self.connections.read().ok()?; // Get the connections map
-        connections.get(user_id).cloned() // Return the user's details
+        for user_id in &user_ids {
+            if let Some(user) = users.connections.get(user_id) {
+                let _ = user.sender.send(message.clone()); // Send the message to the user's sender
+            }
+        }
+        self.share(ClusterEvent::Direct { user_ids, message });
+
+        Ok(())
     }
 
     // Example function to interact with the DB
@@ -189,4 +611,105 @@ impl ConnectionManager {
     }
 }
 
+// Resolves once the server starts shutting down, with the notice to send the client
+pub async fn going_away(shutdown: &mut watch::Receiver<Option<Duration>>) -> GoingAwayMessage {
+    let reconnect_window = shutdown
+        .wait_for(Option::is_some)
+        .await
+        .map(|reconnect_window| reconnect_window.unwrap_or_default());
+    let reconnect_window = match reconnect_window {
+        Ok(reconnect_window) => reconnect_window,
+        // The manager owns the sender, so this only happens when the process is exiting
+        Err(_) => return std::future::pending().await,
+    };
+
+    // Spread the reconnections so that the clients do not all come back at once
+    let window_ms = reconnect_window.as_millis() as u64;
+    GoingAwayMessage {
+        reconnect_after_ms: rand::thread_rng().gen_range(0..=window_ms),
+    }
+}
+
+// Builds the chat status sent when a user starts (Typing) or stops (Online) typing
+fn typing_status(chat_id: Uuid, user_id: Uuid, status: UserStatus) -> WebSocketMessage {
+    WebSocketMessage::Status(StatusMessage {
+        chat_id,
+        user_id,
+        status,
+        timestamp: Utc::now().naive_utc(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::websocket::pubsub::InProcessPubSub;
+    use deadpool_postgres::{Config, Runtime};
+    use tokio_postgres::NoTls;
 
+    const ROOMS: usize = 5_000;
+    const USERS_PER_ROOM: usize = 4;
+    const MESSAGES_PER_ROOM: usize = 50;
+
+    // The pool is never used, so it does not need a reachable database
+    fn manager() -> ConnectionManager {
+        let mut config = Config::new();
+        config.host = Some("localhost".to_string());
+        config.dbname = Some("smoke_test".to_string());
+        let pool = config.create_pool(Some(Runtime::Tokio1), NoTls).unwrap();
+        ConnectionManager::new(pool, 100, Arc::new(InProcessPubSub))
+    }
+
+    // Smoke test rather than a benchmark: joins, broadcasts, typing, heartbeats and leaves in
+    // thousands of rooms at once must neither panic nor deadlock, and leave no room behind.
+    // There is no recorded baseline; the printed rate only helps compare runs on one machine.
+    // Run with `cargo test --release concurrent_rooms_smoke_test -- --ignored --nocapture`.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    #[ignore]
+    async fn concurrent_rooms_smoke_test() {
+        let manager = manager();
+        let started = std::time::Instant::now();
+
+        let tasks: Vec<_> = (0..ROOMS)
+            .map(|room| {
+                let manager = manager.clone();
+                tokio::spawn(async move {
+                    let chat_id = Uuid::new_v4();
+                    let users: Vec<Uuid> = (0..USERS_PER_ROOM).map(|_| Uuid::new_v4()).collect();
+                    let connection = manager.track_connection(users[0], Uuid::new_v4());
+                    let mut receivers = Vec::new();
+                    for (i, user_id) in users.iter().enumerate() {
+                        receivers.push(manager.add_user_to_chat(chat_id, *user_id, &connection).await.unwrap().0);
+                        receivers.push(manager.register_user(*user_id, format!("user-{}-{}", room, i)).unwrap().0);
+                    }
+                    for i in 0..MESSAGES_PER_ROOM {
+                        let user_id = users[i % USERS_PER_ROOM];
+                        manager.broadcast_to_chat(chat_id, typing_status(chat_id, user_id, UserStatus::Online));
+                        manager.heartbeat(chat_id, user_id, connection.id).unwrap();
+                        manager.touch(chat_id, user_id).unwrap();
+                        tokio::task::yield_now().await;
+                    }
+                    drop(receivers);
+                    for user_id in users {
+                        manager.remove_user_from_chat(chat_id, user_id, connection.id).unwrap();
+                        manager.unregister_user(user_id).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let elapsed = started.elapsed();
+        let operations = ROOMS * (USERS_PER_ROOM * 4 + MESSAGES_PER_ROOM * 3);
+        println!(
+            "{} rooms, {} operations in {:?} ({:.0} operations/s)",
+            ROOMS,
+            operations,
+            elapsed,
+            operations as f64 / elapsed.as_secs_f64()
+        );
+        assert!(manager.chats.is_empty());
+    }
+}
@@ -0,0 +1,376 @@
+// websocket/events.rs
+
+use axum::{
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+    Extension,
+};
+use chrono::Utc;
+use futures_util::stream::{self, BoxStream, StreamExt};
+use hyper::{HeaderMap, StatusCode};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    time::Duration,
+};
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    mpsc,
+};
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    models::message::MessageRangeQuery,
+    services::{chat_service::ChatService, presence_service::PresenceService},
+};
+
+use super::{
+    connection_manager::{going_away, ConnectionManager},
+    handlers::{apply_block_update, is_blocked_sender, load_blocked_users, replay_gap},
+    types::{ChatMessage, GapMessage, StatusMessage, UserStatus, WebSocketMessage},
+};
+
+// Position of an event stream in each chat: the sequence number of the last chat message sent.
+// It is the id of the events carrying chat messages, written as `chat_id:seq` pairs separated
+// by commas, so that `Last-Event-ID` resumes every chat where the stream stopped.
+#[derive(Default)]
+struct Cursor(HashMap<Uuid, i64>);
+
+impl Cursor {
+    fn parse(value: &str) -> Option<Self> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (chat_id, seq) = pair.split_once(':')?;
+                Some((Uuid::parse_str(chat_id).ok()?, seq.parse().ok()?))
+            })
+            .collect::<Option<HashMap<_, _>>>()
+            .map(Cursor)
+    }
+
+    fn to_event_id(&self) -> String {
+        let mut pairs: Vec<String> = self
+            .0
+            .iter()
+            .map(|(chat_id, seq)| format!("{}:{}", chat_id, seq))
+            .collect();
+        pairs.sort();
+        pairs.join(",")
+    }
+}
+
+// Streams the events of all the user's chats as Server-Sent Events, for clients that cannot
+// keep a WebSocket open. Events are the same JSON as on the WebSocket; messages are sent
+// through `POST /send_message`.
+pub async fn events_handler(
+    headers: HeaderMap,
+    Extension(state): Extension<AppState>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let user_id = state
+        .current_user_id
+        .ok_or((StatusCode::UNAUTHORIZED, "Not authenticated".to_string()))?;
+    let session_id = state
+        .current_session_id
+        .ok_or((StatusCode::UNAUTHORIZED, "Not authenticated".to_string()))?;
+
+    // Browsers send the id of the last event they received when they reconnect
+    let resume_from = match headers.get("Last-Event-ID") {
+        Some(value) => Some(
+            value
+                .to_str()
+                .ok()
+                .and_then(Cursor::parse)
+                .ok_or((StatusCode::BAD_REQUEST, "Invalid Last-Event-ID".to_string()))?,
+        ),
+        None => None,
+    };
+
+    let blocked_users = load_blocked_users(&state.db, user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let chat_ids = ChatService::get_member_chats(state.db.clone(), user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .into_iter()
+        .map(|(chat_id, _)| chat_id)
+        .collect();
+
+    // The stream ends when the client goes away and the task stops when it cannot send anymore
+    let (tx, rx) = mpsc::channel(state.connections.channel_capacity);
+    tokio::spawn(run_event_stream(state, user_id, session_id, chat_ids, blocked_users, resume_from, tx));
+
+    let events = stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|event| (Ok::<_, Infallible>(event), rx))
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+// Feeds an event stream from the same ConnectionManager subscriptions as a WebSocket
+async fn run_event_stream(
+    state: AppState,
+    user_id: Uuid,
+    session_id: Uuid,
+    chat_ids: Vec<Uuid>,
+    mut blocked_users: HashSet<Uuid>,
+    resume_from: Option<Cursor>,
+    tx: mpsc::Sender<Event>,
+) {
+    let conn_manager = &state.connections;
+    let db_pool = state.db.clone();
+
+    // Shutdown waits for the stream to end, and tells it when to; revoking the session ends it too
+    let connection = conn_manager.track_connection(user_id, session_id);
+    let mut shutdown = conn_manager.shutdown_receiver();
+
+    // Subscribe before reading the database, so later messages wait in the channels
+    let mut joined = Vec::new();
+    let mut newly_joined = Vec::new();
+    let mut subscriptions = Vec::new();
+    for chat_id in chat_ids {
+        match conn_manager.add_user_to_chat(chat_id, user_id, &connection).await {
+            Ok((rx, first)) => {
+                joined.push(chat_id);
+                if first {
+                    newly_joined.push(chat_id);
+                }
+                subscriptions.push(chat_events(chat_id, rx));
+            }
+            Err(e) => eprintln!("Failed to add user to chat: {}", e),
+        }
+    }
+    let mut subscriptions = stream::select_all(subscriptions);
+
+    let presence = PresenceService::new(&state);
+    let registered = match conn_manager.get_user_from_db(user_id).await {
+        Ok(username) => conn_manager.register_user(user_id, username),
+        Err(e) => Err(format!("Failed to load username: {}", e)),
+    };
+    let mut direct_rx = match registered {
+        Ok((rx, came_online)) => {
+            if came_online {
+                presence.came_online(user_id).await;
+            }
+            rx
+        }
+        Err(e) => {
+            eprintln!("Failed to register event stream: {}", e);
+            leave_chats(conn_manager, &joined, user_id, connection.id);
+            return;
+        }
+    };
+
+    // Chats where another connection of the user is open already know they are online
+    for &chat_id in &newly_joined {
+        let status_msg = WebSocketMessage::Status(StatusMessage {
+            chat_id,
+            user_id,
+            status: UserStatus::Online,
+            timestamp: Utc::now().naive_utc(),
+        });
+        conn_manager.broadcast_to_chat(chat_id, status_msg);
+    }
+
+    // Start each chat where the client stopped, or at its latest message
+    let latest: HashMap<Uuid, i64> = ChatService::get_member_chats(db_pool.clone(), user_id)
+        .await
+        .unwrap_or_else(|e| {
+            eprintln!("Failed to load chats: {}", e);
+            Vec::new()
+        })
+        .into_iter()
+        .collect();
+    let mut cursor = Cursor::default();
+    for chat_id in &joined {
+        let start = resume_from
+            .as_ref()
+            .and_then(|resume_from| resume_from.0.get(chat_id))
+            .or_else(|| latest.get(chat_id));
+        cursor.0.insert(*chat_id, start.copied().unwrap_or(0));
+    }
+
+    // The first event carries the position, for clients that reconnect before any message.
+    // Once the client is gone, sends fail and the loop below stops right away.
+    let _ = tx
+        .send(Event::default().id(cursor.to_event_id()).comment("connected"))
+        .await;
+
+    // Replay what the client missed; messages already replayed are skipped when they arrive live.
+    // Past the replay limit, only the latest messages are replayed, after a gap for the others.
+    if resume_from.is_some() {
+        'replay: for &chat_id in &joined {
+            let after_seq = cursor.0.get(&chat_id).copied().unwrap_or(0);
+            let range = MessageRangeQuery {
+                after_seq: Some(after_seq),
+                latest: Some(state.config.websocket.max_replay_messages.max(1)),
+                ..Default::default()
+            };
+            let missed = match ChatService::get_chat_messages(db_pool.clone(), chat_id, user_id, range).await {
+                Ok(messages) => messages,
+                Err(e) => {
+                    eprintln!("Failed to load missed messages: {}", e);
+                    Vec::new()
+                }
+            };
+            if let Some(gap) = missed.first().and_then(|first| replay_gap(chat_id, after_seq, first)) {
+                if let Some(event) = to_event(&WebSocketMessage::Gap(gap)) {
+                    if tx.send(event).await.is_err() {
+                        break 'replay;
+                    }
+                }
+            }
+            for message in missed {
+                cursor.0.insert(chat_id, message.seq);
+                if let Some(event) = to_event(&WebSocketMessage::Chat(ChatMessage::from(message))) {
+                    if tx.send(event.id(cursor.to_event_id())).await.is_err() {
+                        break 'replay;
+                    }
+                }
+            }
+        }
+    }
+
+    // Only messages up to where the stream started can arrive twice. Messages relayed from
+    // other instances may arrive slightly out of order, and are delivered all the same.
+    let covered = cursor.0.clone();
+
+    // Keep the subscriptions from being swept as stale while the client listens
+    let heartbeat_interval = Duration::from_secs(state.config.websocket.ping_interval_seconds.max(1));
+    let mut heartbeat = tokio::time::interval(heartbeat_interval);
+
+    loop {
+        tokio::select! {
+            _ = tx.closed() => break,
+            // The stale connection sweeper removed the stream from a chat; the client reconnects
+            _ = connection.evicted.notified() => break,
+            // The session was revoked; reconnecting with it fails authentication
+            _ = connection.revoked.notified() => break,
+            // `retry` makes EventSource wait before reconnecting
+            notice = going_away(&mut shutdown) => {
+                let retry = Duration::from_millis(notice.reconnect_after_ms);
+                if let Some(event) = to_event(&WebSocketMessage::GoingAway(notice)) {
+                    let _ = tx.send(event.retry(retry)).await;
+                }
+                break;
+            }
+            _ = heartbeat.tick() => {
+                for &chat_id in &joined {
+                    let _ = conn_manager.heartbeat(chat_id, user_id, connection.id);
+                }
+            }
+            Some((chat_id, result)) = subscriptions.next() => {
+                let outgoing = match result {
+                    // The client fell behind and the channel dropped its oldest events
+                    Err(missed) => {
+                        log::warn!("Event stream of user {} lagged behind in chat {} and missed {} events", user_id, chat_id, missed);
+                        to_event(&WebSocketMessage::Gap(GapMessage {
+                            chat_id,
+                            missed,
+                            after: None,
+                            before: None,
+                            after_seq: cursor.0.get(&chat_id).copied(),
+                        }))
+                    }
+                    // Already replayed from the database
+                    Ok(WebSocketMessage::Chat(ref chat_msg))
+                        if covered.get(&chat_id).is_some_and(|&seq| chat_msg.seq <= seq) => None,
+                    Ok(WebSocketMessage::Chat(mut chat_msg)) => {
+                        let seq = cursor.0.entry(chat_id).or_default();
+                        *seq = chat_msg.seq.max(*seq);
+                        chat_msg.sender_blocked = is_blocked_sender(&blocked_users, user_id, chat_msg.sender_id);
+                        to_event(&WebSocketMessage::Chat(chat_msg)).map(|event| event.id(cursor.to_event_id()))
+                    }
+                    // Events relayed from other instances were lost
+                    Ok(WebSocketMessage::Gap(mut gap)) => {
+                        gap.after_seq = cursor.0.get(&chat_id).copied();
+                        to_event(&WebSocketMessage::Gap(gap))
+                    }
+                    // Members never receive their own status, including their typing events
+                    Ok(WebSocketMessage::Status(ref status)) if status.user_id == user_id => None,
+                    Ok(msg @ (WebSocketMessage::Status(_) | WebSocketMessage::ProfileUpdated(_))) => to_event(&msg),
+                    Ok(_) => None,
+                };
+                if let Some(event) = outgoing {
+                    if tx.send(event).await.is_err() {
+                        break;
+                    }
+                }
+            }
+            result = direct_rx.recv() => {
+                match result {
+                    Ok(msg) => {
+                        apply_block_update(&mut blocked_users, &msg);
+                        if let Some(event) = to_event(&msg) {
+                            if tx.send(event).await.is_err() {
+                                break;
+                            }
+                        }
+                    }
+                    Err(RecvError::Lagged(missed)) => {
+                        log::warn!("User {} missed {} direct events", user_id, missed);
+                        // A change of the block list may be among them
+                        match load_blocked_users(&db_pool, user_id).await {
+                            Ok(reloaded) => blocked_users = reloaded,
+                            Err(e) => eprintln!("{}", e),
+                        }
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }
+    }
+
+    leave_chats(conn_manager, &joined, user_id, connection.id);
+    drop(direct_rx);
+    if let Ok(true) = conn_manager.unregister_user(user_id) {
+        presence.went_offline(user_id).await;
+    }
+}
+
+// Turns a chat's broadcast channel into a stream of its events, with the number of events
+// missed when the stream lagged behind
+fn chat_events(
+    chat_id: Uuid,
+    rx: broadcast::Receiver<WebSocketMessage>,
+) -> BoxStream<'static, (Uuid, Result<WebSocketMessage, u64>)> {
+    stream::unfold(rx, move |mut rx| async move {
+        match rx.recv().await {
+            Ok(msg) => Some(((chat_id, Ok(msg)), rx)),
+            Err(RecvError::Lagged(missed)) => Some(((chat_id, Err(missed)), rx)),
+            Err(RecvError::Closed) => None,
+        }
+    })
+    .boxed()
+}
+
+// Removes the stream from its chats, telling those the user left with their last connection,
+// unless the stale connection sweeper already did
+fn leave_chats(conn_manager: &ConnectionManager, chat_ids: &[Uuid], user_id: Uuid, connection_id: Uuid) {
+    for &chat_id in chat_ids {
+        if !matches!(conn_manager.remove_user_from_chat(chat_id, user_id, connection_id), Ok(true)) {
+            continue;
+        }
+        let status_msg = WebSocketMessage::Status(StatusMessage {
+            chat_id,
+            user_id,
+            status: UserStatus::Offline,
+            timestamp: Utc::now().naive_utc(),
+        });
+        conn_manager.broadcast_to_chat(chat_id, status_msg);
+    }
+}
+
+// Serializes an event the same way as on the WebSocket
+fn to_event(msg: &WebSocketMessage) -> Option<Event> {
+    match Event::default().json_data(msg) {
+        Ok(event) => Some(event),
+        Err(e) => {
+            eprintln!("Failed to serialize event: {}", e);
+            None
+        }
+    }
+}
@@ -1,25 +1,42 @@
 use axum::{
     extract::{
-        ws::{Message, WebSocket},
+        ws::{close_code, CloseFrame, Message, WebSocket},
         Query, WebSocketUpgrade,
     },
     response::{IntoResponse, Response},
     Extension,
 };
-use chrono::Utc;
+use chrono::{DateTime, NaiveDateTime, Utc};
 use hyper::{HeaderMap, StatusCode};
+use std::{
+    collections::{HashSet, VecDeque},
+    time::Duration,
+};
+use tokio::{
+    sync::broadcast::{self, error::RecvError},
+    time::Instant,
+};
 use uuid::Uuid;
 use deadpool_postgres::{Pool, Client};
 use crate::{
     app_state::AppState,
     middleware::ws_auth_middleware::WebSocketParams,
-    services::jwt_service::validate_token,
-    websocket::types::ChatMessage,
+    models::{
+        message::{Message as StoredMessage, MessageRangeQuery},
+        user::Claims,
+    },
+    repositories::block_repository::BlockRepository,
+    services::{chat_service::ChatService, presence_service::PresenceService},
+    websocket::types::{
+        AckMessage, ChatMessage, ClientMessage, ErrorMessage, ProtocolLimits, SessionInfo, WelcomeMessage,
+    },
 };
 
 use super::{
-    connection_manager::ConnectionManager,
-    types::{StatusMessage, UserStatus, WebSocketMessage},
+    codec::WireFormat,
+    connection_manager::going_away,
+    protocol::{self, CLOSE_UNSUPPORTED_VERSION, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION},
+    types::{GapMessage, StatusMessage, UserStatus, WebSocketMessage},
 };
 
 // Handles the initial WebSocket upgrade request
@@ -32,9 +49,23 @@ pub async fn websocket_handler(
     // Check if the 'Authorization' header is present
     if let Some(authorization) = headers.get("Authorization") {
         if let Ok(token) = authorization.to_str() {
-            if let Some(user_id) = validate_token(token) {
-                return ws.on_upgrade(move |socket| {
-                    handle_websocket_connection(socket, state, params.chat_id, user_id)
+            // The token must belong to the session the middleware verified
+            if let Some((user_id, claims)) = state
+                .keys
+                .decode(token)
+                .ok()
+                .filter(|claims| Uuid::parse_str(&claims.sid).ok() == state.current_session_id)
+                .and_then(|claims| Some((Uuid::parse_str(&claims.sub).ok()?, claims)))
+            {
+                // A negotiated subprotocol takes precedence over the `format` parameter
+                return ws.protocols(WireFormat::SUBPROTOCOLS).on_upgrade(move |socket| {
+                    let format = socket
+                        .protocol()
+                        .and_then(|protocol| protocol.to_str().ok())
+                        .and_then(WireFormat::from_subprotocol)
+                        .or(params.format)
+                        .unwrap_or_default();
+                    handle_websocket_connection(socket, state, params.chat_id, user_id, claims, params.last_seq, format)
                 });
             }
         }
@@ -45,7 +76,6 @@ pub async fn websocket_handler(
 
 // Checks if the user is allowed to send messages in the specified chat
 async fn can_user_send_message(
-    connection_manager: &ConnectionManager,
     db_pool: &Pool,
     chat_id: Uuid,
     user_id: Uuid,
@@ -69,17 +99,11 @@ async fn can_user_send_message(
     match client.query_opt(query, &[&chat_id, &user_id]).await {
         Ok(Some(_)) => {
             log::info!("User with ID {} is authorized in chat ID {}", user_id, chat_id);
-
-            // If authorized in the DB, check if the user is already added in memory
-            if !is_user_in_chat(connection_manager, chat_id, user_id).await? {
-                // If not, add the user to the chat in the ConnectionManager
-                let _ = connection_manager.add_user_to_chat(chat_id, user_id).await?;
-            }
             Ok(true)
         }
         Ok(None) => {
             log::warn!("User with ID {} is not authorized in chat ID {}", user_id, chat_id);
-            Err("User is not authorized in this chat".to_string())
+            Ok(false)
         }
         Err(e) => {
             log::error!("Error checking authorization: {}", e);
@@ -89,60 +113,284 @@ async fn can_user_send_message(
 }
 
 // Handles the WebSocket connection once it has been upgraded
+//
+// The connection starts with the hello/welcome handshake once the user is authorized and
+// subscribed to the chat, so that nothing sent meanwhile is lost. Clients that pass `last_seq`
+// are first sent the messages stored after `last_seq`. Events are encoded in the connection's
+// format; chat messages are full events carrying their sequence number, except for version 1
+// clients that neither resume nor use a binary format, which get their plain text.
 async fn handle_websocket_connection(
     mut socket: WebSocket,
     state: AppState,
     chat_id: Uuid,
     user_id: Uuid,
+    claims: Claims,
+    last_seq: Option<i64>,
+    format: WireFormat,
 ) {
     let conn_manager = &state.connections;
     let db_pool = state.db.clone(); // Clone the DB pool from state
 
+    // The WebSocket handler only upgrades connections whose session was verified
+    let Some(session_id) = state.current_session_id else {
+        let _ = close(&mut socket, close_code::POLICY, "Not authenticated").await;
+        return;
+    };
+
+    // Shutdown waits for the connection to close, and tells it when to; revoking the session closes it too
+    let connection = conn_manager.track_connection(user_id, session_id);
+    let mut shutdown = conn_manager.shutdown_receiver();
+
+    let username = match conn_manager.get_user_from_db(user_id).await {
+        Ok(username) => username,
+        Err(e) => {
+            eprintln!("Failed to load username: {}", e);
+            let _ = close(&mut socket, close_code::ERROR, "Failed to open the connection").await;
+            return;
+        }
+    };
+
+    // Messages from blocked senders are flagged from this set rather than by querying each one
+    let mut blocked_users = match load_blocked_users(&db_pool, user_id).await {
+        Ok(blocked_users) => blocked_users,
+        Err(e) => {
+            eprintln!("{}", e);
+            let _ = close(&mut socket, close_code::ERROR, "Failed to open the connection").await;
+            return;
+        }
+    };
+
     // Verify if the user is allowed to send messages in the chat
-    if let Err(e) = can_user_send_message(conn_manager, &db_pool, chat_id, user_id).await {
-        eprintln!("{}", e);
-        return; // Close the connection if the user is not authorized
+    match can_user_send_message(&db_pool, chat_id, user_id).await {
+        Ok(true) => {}
+        // Close the connection if the user is not authorized
+        Ok(false) => {
+            let _ = close(&mut socket, close_code::POLICY, "Not a member of this chat").await;
+            return;
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            let _ = close(&mut socket, close_code::ERROR, "Failed to open the connection").await;
+            return;
+        }
     }
 
-    // Add the user to the chat (in memory) and get the receiver channel for broadcasted messages
-    let mut rx = match conn_manager.add_user_to_chat(chat_id, user_id).await {
-        Ok(rx) => rx,
+    // Add the user to the chat (in memory) and get the receiver channel for broadcasted messages.
+    // Events sent during the handshake wait in the channels.
+    let (mut rx, joined) = match conn_manager.add_user_to_chat(chat_id, user_id, &connection).await {
+        Ok(added) => added,
         Err(e) => {
             eprintln!("Failed to add user to chat: {}", e);
+            let _ = close(&mut socket, close_code::ERROR, "Failed to open the connection").await;
             return;
         }
     };
 
-    // Broadcast a status message to notify other users that this user is now online
-    let status_msg = WebSocketMessage::Status(StatusMessage {
-        chat_id,
+    // Register the user so invitations and friend requests can reach them directly
+    let presence = PresenceService::new(&state);
+    let mut direct_rx = match conn_manager.register_user(user_id, username.clone()) {
+        Ok((rx, came_online)) => {
+            if came_online {
+                presence.came_online(user_id).await;
+            }
+            rx
+        }
+        Err(e) => {
+            eprintln!("Failed to register connection: {}", e);
+            leave_chat(&state, chat_id, user_id, connection.id);
+            let _ = close(&mut socket, close_code::ERROR, "Failed to open the connection").await;
+            return;
+        }
+    };
+
+    // Broadcast a status message to notify other users that this user is now online,
+    // unless another connection of the user already did
+    if joined {
+        let status_msg = WebSocketMessage::Status(StatusMessage {
+            chat_id,
+            user_id,
+            status: UserStatus::Online,
+            timestamp: Utc::now().naive_utc(),
+        });
+        conn_manager.broadcast_to_chat(chat_id, status_msg);
+    }
+
+    // Agree on the protocol before sending anything else
+    let session = SessionInfo {
         user_id,
-        status: UserStatus::Online,
-        timestamp: Utc::now().naive_utc(),
-    });
-    let _ = conn_manager.broadcast_message(status_msg, chat_id, user_id);
+        username,
+        session_id: Some(session_id),
+        expires_at: DateTime::from_timestamp(claims.exp as i64, 0).map(|expires_at| expires_at.naive_utc()),
+        chat_id,
+        format,
+    };
+    let (welcomed, mut pending_frame) = match handshake(&mut socket, &state, format, session).await {
+        Handshake::Welcomed => (true, None),
+        Handshake::Legacy(frame) => (false, frame),
+        Handshake::Closed => {
+            disconnect(&state, &presence, chat_id, user_id, connection.id, direct_rx).await;
+            return;
+        }
+    };
 
-    // Main loop to handle incoming and outgoing WebSocket messages
+    // Replay what the client missed. The receiver was subscribed before reading the
+    // database, so later messages wait in the channel; those already replayed are skipped.
+    // Past the replay limit, only the latest messages are replayed, after a gap for the others.
+    let plain_text = protocol::sends_plain_text(welcomed, last_seq.is_some(), format.is_binary());
+    let mut delivered_seq = last_seq;
+    if let Some(after_seq) = last_seq {
+        let range = MessageRangeQuery {
+            after_seq: Some(after_seq),
+            latest: Some(state.config.websocket.max_replay_messages.max(1)),
+            ..Default::default()
+        };
+        let missed = match ChatService::get_chat_messages(db_pool.clone(), chat_id, user_id, range).await {
+            Ok(messages) => messages,
+            Err(e) => {
+                eprintln!("Failed to load missed messages: {}", e);
+                Vec::new()
+            }
+        };
+        let mut replayed = true;
+        if let Some(gap) = missed.first().and_then(|first| replay_gap(chat_id, after_seq, first)) {
+            replayed = send_event(&mut socket, format, &WebSocketMessage::Gap(gap)).await.is_ok();
+        }
+        for message in missed {
+            if !replayed {
+                break;
+            }
+            delivered_seq = Some(message.seq);
+            let chat_msg = WebSocketMessage::Chat(ChatMessage::from(message));
+            replayed = send_event(&mut socket, format, &chat_msg).await.is_ok();
+        }
+        if !replayed {
+            disconnect(&state, &presence, chat_id, user_id, connection.id, direct_rx).await;
+            return;
+        }
+    }
+
+    // Only messages up to the end of the replay can arrive twice. Messages relayed from other
+    // instances may arrive slightly out of order, and are delivered all the same.
+    let replayed_seq = delivered_seq;
+
+    // Ping the client regularly; a client that misses too many pongs is gone
+    let ping_interval = Duration::from_secs(state.config.websocket.ping_interval_seconds.max(1));
+    let max_missed_pongs = state.config.websocket.max_missed_pongs;
+    let mut ping_ticker = tokio::time::interval_at(Instant::now() + ping_interval, ping_interval);
+    let mut missed_pongs = 0;
+
+    // Lag tracking: recent lag events, the missed count not reported yet, and the last chat message seen
+    let max_lag_events = state.config.websocket.max_lag_events.max(1);
+    let lag_window = Duration::from_secs(state.config.websocket.lag_window_seconds);
+    let mut lag_events: VecDeque<Instant> = VecDeque::new();
+    let mut pending_gap: Option<u64> = None;
+    let mut last_chat_timestamp: Option<NaiveDateTime> = None;
+
+    // Main loop to handle incoming and outgoing WebSocket messages. Each event is handled to
+    // the end, so a message being stored or sent when shutdown starts still goes through.
     loop {
         tokio::select! {
-            msg = socket.recv() => {
+            notice = going_away(&mut shutdown) => {
+                log::info!("Closing connection of user {}: server shutting down", user_id);
+                let reason = format!("Server shutting down, reconnect in {} ms", notice.reconnect_after_ms);
+                let _ = send_event(&mut socket, format, &WebSocketMessage::GoingAway(notice)).await;
+                let _ = close(&mut socket, close_code::AWAY, &reason).await;
+                break;
+            }
+            // The stale connection sweeper already removed and announced this connection
+            _ = connection.evicted.notified() => {
+                log::info!("Closing connection of user {}: evicted as stale", user_id);
+                let _ = close(&mut socket, close_code::POLICY, "No sign of life from the client").await;
+                break;
+            }
+            // The session was revoked by a password change or reset, or the account was deleted
+            _ = connection.revoked.notified() => {
+                log::info!("Closing connection of user {}: session revoked", user_id);
+                let _ = close(&mut socket, close_code::POLICY, "Session revoked").await;
+                break;
+            }
+            _ = ping_ticker.tick() => {
+                if missed_pongs >= max_missed_pongs {
+                    log::info!("Closing connection of user {} after {} missed pongs", user_id, missed_pongs);
+                    break;
+                }
+                if socket.send(Message::Ping(Vec::new())).await.is_err() {
+                    break;
+                }
+                missed_pongs += 1;
+            }
+            msg = next_frame(&mut socket, &mut pending_frame) => {
+                // Any frame from the client shows the connection is alive
+                if let Some(Ok(_)) = msg {
+                    missed_pongs = 0;
+                    let _ = conn_manager.heartbeat(chat_id, user_id, connection.id);
+                }
                 match msg {
-                    Some(Ok(msg)) => {
-                        if let Ok(text) = msg.to_text() {
-                            let chat_msg = WebSocketMessage::Chat(ChatMessage {
-                                message_id: Uuid::new_v4(),
-                                chat_id,
-                                sender_id: user_id,
-                                content: text.to_string(),
-                                timestamp: Utc::now().naive_utc(),
-                            });
-
-                            if let Err(e) = conn_manager.broadcast_message(chat_msg, chat_id, user_id) {
-                                eprintln!("Failed to broadcast message: {}", e);
+                    Some(Ok(Message::Close(_))) => break,
+                    Some(Ok(frame @ (Message::Text(_) | Message::Binary(_)))) => {
+                        let (client_id, content) = match read_client_frame(format, frame) {
+                            // Typing frames only go through the ConnectionManager, never the database
+                            Some(ClientFrame::Typing) => {
+                                if let Err(e) = conn_manager.start_typing(chat_id, user_id) {
+                                    eprintln!("Failed to broadcast typing: {}", e);
+                                }
+                                continue;
+                            }
+                            Some(ClientFrame::Post { client_id, content }) => (client_id, content),
+                            // A malformed control frame, or a hello once the handshake is over, is
+                            // rejected rather than posted to the chat
+                            Some(frame @ (ClientFrame::Invalid(_) | ClientFrame::Hello { .. })) => {
+                                let message = match frame {
+                                    ClientFrame::Invalid(message) => message,
+                                    _ => "Hello is only accepted as the first frame".to_string(),
+                                };
+                                let error = WebSocketMessage::Error(ErrorMessage {
+                                    code: "invalid_frame".to_string(),
+                                    message,
+                                    client_id: None,
+                                });
+                                if send_event(&mut socket, format, &error).await.is_err() {
+                                    break;
+                                }
+                                continue;
+                            }
+                            // Binary frames carry nothing on JSON connections
+                            None => continue,
+                        };
+
+                        if let Ok(true) = conn_manager.touch(chat_id, user_id) {
+                            presence.became_active(user_id).await;
+                        }
+                        let _ = conn_manager.stop_typing(chat_id, user_id);
+
+                        // The message reaches the chat, this connection included, through the broadcast
+                        let reply = match ChatService::post_message(db_pool.clone(), conn_manager, chat_id, user_id, content, client_id).await {
+                            Ok(message) => client_id.map(|client_id| {
+                                WebSocketMessage::Ack(AckMessage {
+                                    client_id,
+                                    message_id: message.id,
+                                    chat_id,
+                                    seq: message.seq,
+                                    timestamp: message.timestamp,
+                                })
+                            }),
+                            Err(e) => {
+                                eprintln!("Failed to send message: {:?}", e);
+                                Some(WebSocketMessage::Error(ErrorMessage {
+                                    code: e.code().to_string(),
+                                    message: e.to_string(),
+                                    client_id,
+                                }))
+                            }
+                        };
+                        if let Some(reply) = reply {
+                            if send_event(&mut socket, format, &reply).await.is_err() {
                                 break;
                             }
                         }
                     }
+                    // Ping and pong frames carry nothing for the chat
+                    Some(Ok(_)) => {}
                     Some(Err(e)) => {
                         eprintln!("WebSocket error: {}", e);
                         break;
@@ -150,30 +398,321 @@ async fn handle_websocket_connection(
                     None => break,
                 }
             }
-            Ok(msg) = rx.recv() => {
-                if let WebSocketMessage::Chat(chat_msg) = msg {
-                    if let Err(e) = socket.send(Message::Text(chat_msg.content)).await {
+            result = rx.recv() => {
+                let msg = match result {
+                    Ok(msg) => msg,
+                    // The client fell behind and the channel dropped its oldest messages
+                    Err(RecvError::Lagged(missed)) => {
+                        log::warn!("User {} lagged behind in chat {} and missed {} events", user_id, chat_id, missed);
+                        let now = Instant::now();
+                        lag_events.push_back(now);
+                        while lag_events.front().is_some_and(|&at| now.duration_since(at) > lag_window) {
+                            lag_events.pop_front();
+                        }
+                        if lag_events.len() >= max_lag_events {
+                            log::info!("Disconnecting user {} from chat {}: lagging repeatedly", user_id, chat_id);
+                            let _ = close(&mut socket, close_code::POLICY, "Too slow to keep up with the chat").await;
+                            break;
+                        }
+                        pending_gap = Some(pending_gap.unwrap_or(0) + missed);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+
+                // Report the hole before the first message after it, so the client can backfill
+                if let Some(missed) = pending_gap.take() {
+                    let gap = WebSocketMessage::Gap(GapMessage {
+                        chat_id,
+                        missed,
+                        after: last_chat_timestamp,
+                        before: event_timestamp(&msg),
+                        after_seq: delivered_seq,
+                    });
+                    if send_event(&mut socket, format, &gap).await.is_err() {
+                        break;
+                    }
+                }
+                if let WebSocketMessage::Chat(ref chat_msg) = msg {
+                    last_chat_timestamp = Some(chat_msg.timestamp);
+                }
+
+                let outgoing = match msg {
+                    // Already replayed from the database
+                    WebSocketMessage::Chat(ref chat_msg)
+                        if replayed_seq.is_some_and(|seq| chat_msg.seq <= seq) => None,
+                    WebSocketMessage::Chat(mut chat_msg) => {
+                        delivered_seq = delivered_seq.max(Some(chat_msg.seq));
+                        let sender_blocked = is_blocked_sender(&blocked_users, user_id, chat_msg.sender_id);
+                        if !plain_text {
+                            chat_msg.sender_blocked = sender_blocked;
+                            encode_event(format, &WebSocketMessage::Chat(chat_msg))
+                        } else if sender_blocked {
+                            // Plain text cannot carry the flag, so messages from blocked
+                            // senders are sent as JSON for the client to hide
+                            chat_msg.sender_blocked = true;
+                            encode_event(format, &WebSocketMessage::Chat(chat_msg))
+                        } else {
+                            Some(Message::Text(chat_msg.content))
+                        }
+                    }
+                    // Events relayed from other instances were lost; the client backfills from where it stands
+                    WebSocketMessage::Gap(mut gap) => {
+                        gap.after_seq = delivered_seq;
+                        encode_event(format, &WebSocketMessage::Gap(gap))
+                    }
+                    // Profile changes are sent as events so clients can update names live
+                    profile @ WebSocketMessage::ProfileUpdated(_) => encode_event(format, &profile),
+                    // Members never receive their own status, including their typing events
+                    WebSocketMessage::Status(ref status) if status.user_id == user_id => None,
+                    // Other members joining, connecting, typing or leaving the chat
+                    status @ WebSocketMessage::Status(_) => encode_event(format, &status),
+                    _ => None,
+                };
+                if let Some(frame) = outgoing {
+                    if let Err(e) = socket.send(frame).await {
                         eprintln!("Failed to send message: {}", e);
                         break;
                     }
                 }
             }
+            // Messages addressed to the user rather than the chat are always full events
+            result = direct_rx.recv() => {
+                let msg = match result {
+                    Ok(msg) => msg,
+                    Err(RecvError::Lagged(missed)) => {
+                        log::warn!("User {} missed {} direct events", user_id, missed);
+                        // A change of the block list may be among them
+                        match load_blocked_users(&db_pool, user_id).await {
+                            Ok(reloaded) => blocked_users = reloaded,
+                            Err(e) => eprintln!("{}", e),
+                        }
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                apply_block_update(&mut blocked_users, &msg);
+                if let Err(e) = send_event(&mut socket, format, &msg).await {
+                    eprintln!("Failed to send message: {}", e);
+                    break;
+                }
+            }
         }
     }
 
-    let _ = conn_manager.update_user_status(chat_id, user_id, UserStatus::Offline);
-    let _ = conn_manager.remove_user_from_chat(chat_id, user_id);
+    disconnect(&state, &presence, chat_id, user_id, connection.id, direct_rx).await;
 }
 
-// Helper function to check if a user is already present in the in-memory representation of a chat
-pub async fn is_user_in_chat(
-    connection_manager: &ConnectionManager,
+// Removes a closed connection from its chat and from the user directory
+async fn disconnect(
+    state: &AppState,
+    presence: &PresenceService,
     chat_id: Uuid,
     user_id: Uuid,
-) -> Result<bool, String> {
-    let chats = connection_manager.chats.lock().map_err(|_| "Lock error")?;
-    Ok(chats
-        .get(&chat_id)
-        .map(|chat| chat.users.contains_key(&user_id))
-        .unwrap_or(false))
+    connection_id: Uuid,
+    direct_rx: broadcast::Receiver<WebSocketMessage>,
+) {
+    leave_chat(state, chat_id, user_id, connection_id);
+
+    drop(direct_rx);
+    if let Ok(true) = state.connections.unregister_user(user_id) {
+        presence.went_offline(user_id).await;
+    }
+}
+
+// The user leaves the chat with their last connection to it, unless the stale connection
+// sweeper already removed and announced this one
+fn leave_chat(state: &AppState, chat_id: Uuid, user_id: Uuid, connection_id: Uuid) {
+    let conn_manager = &state.connections;
+    if let Ok(true) = conn_manager.remove_user_from_chat(chat_id, user_id, connection_id) {
+        let status_msg = WebSocketMessage::Status(StatusMessage {
+            chat_id,
+            user_id,
+            status: UserStatus::Offline,
+            timestamp: Utc::now().naive_utc(),
+        });
+        conn_manager.broadcast_to_chat(chat_id, status_msg);
+    }
+}
+
+// Closes the connection with a close frame telling the client why
+async fn close(socket: &mut WebSocket, code: u16, reason: &str) -> Result<(), axum::Error> {
+    socket
+        .send(Message::Close(Some(CloseFrame {
+            code,
+            reason: reason.to_string().into(),
+        })))
+        .await
+}
+
+// Outcome of the hello/welcome handshake
+enum Handshake {
+    // The client said hello and was welcomed
+    Welcomed,
+    // A version 1 client, which does not say hello, with the first frame it sent if any
+    Legacy(Option<Message>),
+    // The client left or its version was refused
+    Closed,
+}
+
+// Waits for the client's hello and answers with a welcome. A client that sends any other frame
+// first, or nothing within the hello timeout, speaks version 1 of the protocol.
+async fn handshake(socket: &mut WebSocket, state: &AppState, format: WireFormat, session: SessionInfo) -> Handshake {
+    let config = &state.config.websocket;
+    let frame = match tokio::time::timeout(Duration::from_secs(config.hello_timeout_seconds), socket.recv()).await {
+        Ok(Some(Ok(frame))) => frame,
+        Ok(_) => return Handshake::Closed,
+        Err(_) => return Handshake::Legacy(None),
+    };
+    let (version, features) = match read_client_frame(format, frame.clone()) {
+        Some(ClientFrame::Hello { version, features }) => (version, features),
+        _ => return Handshake::Legacy(Some(frame)),
+    };
+
+    if !protocol::is_supported(version) {
+        log::info!("Refusing connection of user {}: unsupported protocol version {}", session.user_id, version);
+        let reason = format!(
+            "Unsupported protocol version {}; versions {} to {} are supported",
+            version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+        );
+        let _ = close(socket, CLOSE_UNSUPPORTED_VERSION, &reason).await;
+        return Handshake::Closed;
+    }
+
+    let welcome = WebSocketMessage::Welcome(WelcomeMessage {
+        version: PROTOCOL_VERSION,
+        min_version: MIN_PROTOCOL_VERSION,
+        features: protocol::negotiate_features(&features),
+        limits: ProtocolLimits {
+            ping_interval_seconds: config.ping_interval_seconds.max(1),
+            max_missed_pongs: config.max_missed_pongs,
+            channel_capacity: state.connections.channel_capacity,
+            max_lag_events: config.max_lag_events.max(1),
+            lag_window_seconds: config.lag_window_seconds,
+        },
+        server_time: Utc::now().naive_utc(),
+        session,
+    });
+    match send_event(socket, format, &welcome).await {
+        Ok(()) => Handshake::Welcomed,
+        Err(_) => Handshake::Closed,
+    }
+}
+
+// Returns the frame left over from the handshake, then the next frames of the socket
+async fn next_frame(socket: &mut WebSocket, pending: &mut Option<Message>) -> Option<Result<Message, axum::Error>> {
+    match pending.take() {
+        Some(frame) => Some(Ok(frame)),
+        None => socket.recv().await,
+    }
+}
+
+// Sends an event to the client in the connection's format
+async fn send_event(socket: &mut WebSocket, format: WireFormat, event: &WebSocketMessage) -> Result<(), axum::Error> {
+    match encode_event(format, event) {
+        Some(frame) => socket.send(frame).await,
+        None => Ok(()),
+    }
+}
+
+// Encodes an event in the connection's format
+fn encode_event(format: WireFormat, event: &WebSocketMessage) -> Option<Message> {
+    match format.encode(event) {
+        Ok(frame) => Some(frame),
+        Err(e) => {
+            eprintln!("Failed to serialize event: {}", e);
+            None
+        }
+    }
+}
+
+// What a client frame asks for
+enum ClientFrame {
+    Hello { version: u32, features: Vec<String> },
+    Typing,
+    Post { client_id: Option<Uuid>, content: String },
+    Invalid(String),
+}
+
+impl From<ClientMessage> for ClientFrame {
+    fn from(message: ClientMessage) -> Self {
+        match message {
+            ClientMessage::Hello { version, features } => Self::Hello { version, features },
+            ClientMessage::Typing => Self::Typing,
+            ClientMessage::Send { client_id, content } => Self::Post { client_id: Some(client_id), content },
+        }
+    }
+}
+
+// Reads a text frame, or a binary frame on connections using a binary format
+fn read_client_frame(format: WireFormat, frame: Message) -> Option<ClientFrame> {
+    match frame {
+        Message::Text(text) => Some(match serde_json::from_str::<ClientMessage>(&text) {
+            Ok(message) => message.into(),
+            Err(e) if is_control_frame(&text) => ClientFrame::Invalid(e.to_string()),
+            // Any other text is a chat message without acknowledgement
+            Err(_) => ClientFrame::Post { client_id: None, content: text },
+        }),
+        // Binary frames are always control frames
+        Message::Binary(bytes) if format.is_binary() => Some(match format.decode::<ClientMessage>(&bytes) {
+            Ok(message) => message.into(),
+            Err(e) => ClientFrame::Invalid(e.to_string()),
+        }),
+        _ => None,
+    }
+}
+
+// Tells JSON control frames, which carry a "type", apart from chat messages
+fn is_control_frame(text: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(text)
+        .map(|value| value.get("type").is_some())
+        .unwrap_or(false)
+}
+
+// Returns when an event was emitted, for the events that carry a timestamp
+fn event_timestamp(msg: &WebSocketMessage) -> Option<NaiveDateTime> {
+    match msg {
+        WebSocketMessage::Chat(chat_msg) => Some(chat_msg.timestamp),
+        WebSocketMessage::Status(status) => Some(status.timestamp),
+        _ => None,
+    }
+}
+
+// Reports the messages between `after_seq` and the first replayed message, which the replay
+// limit left out. Sequence numbers have no holes, so they give the exact count.
+pub fn replay_gap(chat_id: Uuid, after_seq: i64, first: &StoredMessage) -> Option<GapMessage> {
+    let missed = first.seq - after_seq - 1;
+    (missed > 0).then_some(GapMessage {
+        chat_id,
+        missed: missed as u64,
+        after: None,
+        before: Some(first.timestamp),
+        after_seq: Some(after_seq),
+    })
+}
+
+// Loads the users the connected user has blocked. Connections keep the set up to date with
+// the BlockListUpdated events that BlockService sends them.
+pub async fn load_blocked_users(db_pool: &Pool, user_id: Uuid) -> Result<HashSet<Uuid>, String> {
+    let client = db_pool.get().await.map_err(|e| format!("Error getting DB client: {}", e))?;
+    BlockRepository::get_blocked_ids(&client, user_id)
+        .await
+        .map(|blocked_ids| blocked_ids.into_iter().collect())
+        .map_err(|e| format!("Failed to load block list: {}", e))
+}
+
+// Applies a change of the connected user's block list received as a direct event
+pub fn apply_block_update(blocked_users: &mut HashSet<Uuid>, msg: &WebSocketMessage) {
+    if let WebSocketMessage::BlockListUpdated(update) = msg {
+        if update.blocked {
+            blocked_users.insert(update.user_id);
+        } else {
+            blocked_users.remove(&update.user_id);
+        }
+    }
+}
+
+// Checks whether the connected user has blocked the sender of a message
+pub fn is_blocked_sender(blocked_users: &HashSet<Uuid>, user_id: Uuid, sender_id: Option<Uuid>) -> bool {
+    sender_id.is_some_and(|sender_id| sender_id != user_id && blocked_users.contains(&sender_id))
 }
@@ -1,3 +1,7 @@
+pub mod codec;
 pub mod connection_manager;
+pub mod events;
 pub mod handlers;
+pub mod protocol;
+pub mod pubsub;
 pub mod types;
\ No newline at end of file
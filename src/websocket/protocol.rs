@@ -0,0 +1,88 @@
+// websocket/protocol.rs
+
+/// Version of the WebSocket protocol this server speaks
+///
+/// Version 1 had no handshake; version 2 starts every connection with a hello and a welcome.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Oldest protocol version the server still accepts
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Optional features the server supports, as named in the client's hello
+pub const SUPPORTED_FEATURES: [&str; 3] = ["acks", "resume", "typing"];
+
+/// Close code sent to clients whose hello asks for a protocol version the server does not speak
+pub const CLOSE_UNSUPPORTED_VERSION: u16 = 4000;
+
+/// Whether the server can speak a protocol version
+pub fn is_supported(version: u32) -> bool {
+    (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version)
+}
+
+/// Keeps the features of a hello the server supports too, in the client's order
+pub fn negotiate_features(requested: &[String]) -> Vec<String> {
+    let mut features: Vec<String> = Vec::new();
+    for feature in requested {
+        if SUPPORTED_FEATURES.contains(&feature.as_str()) && !features.contains(feature) {
+            features.push(feature.clone());
+        }
+    }
+    features
+}
+
+/// Whether chat messages reach a connection as their plain text rather than as full events
+///
+/// Only version 1 clients, which skip the handshake, get plain text, and only when they neither
+/// resume nor use a binary format: both need the sequence number, which plain text cannot carry.
+pub fn sends_plain_text(welcomed: bool, resuming: bool, binary: bool) -> bool {
+    !welcomed && !resuming && !binary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(features: &[&str]) -> Vec<String> {
+        features.iter().map(|feature| feature.to_string()).collect()
+    }
+
+    #[test]
+    fn supports_versions_from_the_oldest_to_the_current_one() {
+        for version in MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION {
+            assert!(is_supported(version), "version {}", version);
+        }
+        assert!(!is_supported(0));
+        assert!(!is_supported(PROTOCOL_VERSION + 1));
+        assert!(!is_supported(u32::MAX));
+    }
+
+    #[test]
+    fn keeps_supported_features_in_the_client_order() {
+        assert_eq!(negotiate_features(&names(&["typing", "acks"])), names(&["typing", "acks"]));
+        assert_eq!(negotiate_features(&names(&SUPPORTED_FEATURES)), names(&SUPPORTED_FEATURES));
+    }
+
+    #[test]
+    fn drops_unknown_and_repeated_features() {
+        assert_eq!(
+            negotiate_features(&names(&["reactions", "resume", "ACKS", "resume", "", "acks"])),
+            names(&["resume", "acks"])
+        );
+        assert!(negotiate_features(&[]).is_empty());
+        assert!(negotiate_features(&names(&["reactions"])).is_empty());
+    }
+
+    #[test]
+    fn sends_full_events_to_every_client_that_said_hello() {
+        for (resuming, binary) in [(false, false), (true, false), (false, true), (true, true)] {
+            assert!(!sends_plain_text(true, resuming, binary), "resuming {} binary {}", resuming, binary);
+        }
+    }
+
+    #[test]
+    fn sends_plain_text_to_version_1_clients_only_when_nothing_needs_the_sequence_number() {
+        assert!(sends_plain_text(false, false, false));
+        assert!(!sends_plain_text(false, true, false));
+        assert!(!sends_plain_text(false, false, true));
+    }
+}
@@ -0,0 +1,295 @@
+// websocket/pubsub.rs
+
+use async_trait::async_trait;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use chrono::NaiveDateTime;
+use std::{
+    future::poll_fn,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio_postgres::{AsyncMessage, NoTls};
+use uuid::Uuid;
+
+use crate::config::PubSubConfig;
+
+use super::{connection_manager::ConnectionManager, types::WebSocketMessage};
+
+/// Postgres channel the nodes of a cluster exchange events on
+const CHANNEL: &str = "rustle_chat_events";
+
+/// Largest notification sent inline; Postgres rejects payloads of 8000 bytes or more
+const MAX_INLINE_PAYLOAD: usize = 7000;
+
+/// Delay before the listener reconnects after losing its database connection
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Events waiting to be sent to the other nodes; further events are dropped and reported as a gap
+const OUTBOX_CAPACITY: usize = 10_000;
+
+/// Errors that can occur while configuring or using a pub/sub backend
+#[derive(Error, Debug)]
+pub enum PubSubError {
+    /// Error when the pub/sub configuration is invalid
+    #[error("Invalid pub/sub configuration: {0}")]
+    Config(String),
+
+    /// Error when an event cannot be handed to the backend
+    #[error("Failed to publish event: {0}")]
+    Publish(String),
+}
+
+/// An event every node delivers to the matching local connections
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum ClusterEvent {
+    /// A message for everyone connected to a chat
+    Chat { chat_id: Uuid, message: WebSocketMessage },
+    /// A message for the connections of some users, whatever the chat
+    Direct { user_ids: Vec<Uuid>, message: WebSocketMessage },
+    /// Sessions of a user were revoked; their connections close, except those opened with `keep`
+    SessionsRevoked { user_id: Uuid, keep: Option<Uuid> },
+    /// The publishing node dropped events because its outbox was full
+    Dropped { missed: u64 },
+}
+
+/// Carries events between the nodes serving WebSocket connections
+///
+/// The `ConnectionManager` delivers each event to its own connections and publishes it
+/// so that the other nodes can deliver it to theirs.
+#[async_trait]
+pub trait PubSub: Send + Sync {
+    /// Hands an event to the other nodes without waiting for it to be delivered
+    fn publish(&self, event: &ClusterEvent) -> Result<(), PubSubError>;
+
+    /// Delivers the events published by other nodes to the local connections until the
+    /// process exits
+    async fn run(&self, connections: ConnectionManager);
+}
+
+/// Keeps events within the process, for single-node deployments
+pub struct InProcessPubSub;
+
+#[async_trait]
+impl PubSub for InProcessPubSub {
+    fn publish(&self, _event: &ClusterEvent) -> Result<(), PubSubError> {
+        Ok(())
+    }
+
+    async fn run(&self, _connections: ConnectionManager) {}
+}
+
+/// A notification on the shared channel
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    /// Node that published the event, which has already delivered it
+    origin: Uuid,
+    /// The event itself, when it fits in a notification
+    event: Option<ClusterEvent>,
+    /// Row of `pubsub_payloads` holding the event otherwise
+    payload_id: Option<Uuid>,
+}
+
+/// Exchanges events with the other nodes through Postgres `LISTEN`/`NOTIFY`
+///
+/// Events too large for a notification are stored in `pubsub_payloads` and sent by id.
+pub struct PostgresPubSub {
+    node_id: Uuid,
+    database_url: String,
+    db_pool: Pool,
+    outbox: mpsc::Sender<ClusterEvent>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl PostgresPubSub {
+    /// Creates the backend and starts the task that sends notifications, one at a time so
+    /// that the other nodes receive events in the order they were published
+    pub fn new(db_pool: Pool, database_url: String) -> Self {
+        let node_id = Uuid::new_v4();
+        let (outbox, queue) = mpsc::channel(OUTBOX_CAPACITY);
+        let dropped = Arc::new(AtomicU64::new(0));
+        tokio::spawn(Self::run_publisher(db_pool.clone(), node_id, queue, dropped.clone()));
+
+        Self {
+            node_id,
+            database_url,
+            db_pool,
+            outbox,
+            dropped,
+        }
+    }
+
+    // Sends the queued events as notifications, followed by the number of events dropped
+    // meanwhile so that the other nodes report a gap to their clients
+    async fn run_publisher(
+        db_pool: Pool,
+        node_id: Uuid,
+        mut queue: mpsc::Receiver<ClusterEvent>,
+        dropped: Arc<AtomicU64>,
+    ) {
+        while let Some(event) = queue.recv().await {
+            if let Err(e) = Self::notify(&db_pool, node_id, event).await {
+                log::error!("Failed to publish event to other nodes: {}", e);
+            }
+            let missed = dropped.swap(0, Ordering::SeqCst);
+            if missed > 0 {
+                if let Err(e) = Self::notify(&db_pool, node_id, ClusterEvent::Dropped { missed }).await {
+                    log::error!("Failed to report dropped events to other nodes: {}", e);
+                }
+            }
+        }
+    }
+
+    async fn notify(db_pool: &Pool, node_id: Uuid, event: ClusterEvent) -> Result<(), String> {
+        let client = db_pool.get().await.map_err(|e| format!("Error getting DB client: {}", e))?;
+
+        let mut envelope = Envelope {
+            origin: node_id,
+            event: Some(event),
+            payload_id: None,
+        };
+        let mut payload = serde_json::to_string(&envelope).map_err(|e| e.to_string())?;
+
+        if payload.len() > MAX_INLINE_PAYLOAD {
+            let event = serde_json::to_string(&envelope.event).map_err(|e| e.to_string())?;
+            let query = "
+                INSERT INTO pubsub_payloads (payload) VALUES ($1) RETURNING id
+            ";
+            let row = client
+                .query_one(query, &[&event])
+                .await
+                .map_err(|e| format!("Error storing event payload: {}", e))?;
+
+            // Every listener fetches the payload right away; anything older is abandoned
+            let cleanup_query = "
+                DELETE FROM pubsub_payloads WHERE created_at < NOW() - INTERVAL '5 minutes'
+            ";
+            let _ = client.execute(cleanup_query, &[]).await;
+
+            envelope.event = None;
+            envelope.payload_id = Some(row.get(0));
+            payload = serde_json::to_string(&envelope).map_err(|e| e.to_string())?;
+        }
+
+        client
+            .execute("SELECT pg_notify($1, $2)", &[&CHANNEL, &payload])
+            .await
+            .map_err(|e| format!("Error sending notification: {}", e))?;
+        Ok(())
+    }
+
+    // Listens on a dedicated connection, since pooled connections do not report notifications.
+    // Once listening again after losing the connection at `lost_at`, the local clients are told
+    // that events may have been missed meanwhile.
+    async fn listen(&self, connections: &ConnectionManager, lost_at: &mut Option<NaiveDateTime>) -> Result<(), String> {
+        let (client, mut connection) = tokio_postgres::connect(&self.database_url, NoTls)
+            .await
+            .map_err(|e| format!("Error connecting: {}", e))?;
+
+        // Drive the connection, forwarding notifications until it closes
+        let (notifications_tx, mut notifications) = mpsc::unbounded_channel();
+        let driver = tokio::spawn(async move {
+            while let Some(message) = poll_fn(|cx| connection.poll_message(cx)).await {
+                match message {
+                    Ok(AsyncMessage::Notification(notification)) => {
+                        if notifications_tx.send(notification).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        log::error!("Pub/sub connection error: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
+
+        client
+            .batch_execute(&format!("LISTEN {}", CHANNEL))
+            .await
+            .map_err(|e| format!("Error listening: {}", e))?;
+        log::info!("Node {} listening for events from other nodes", self.node_id);
+        if let Some(after) = lost_at.take() {
+            connections.report_relay_gap(0, Some(after), Some(chrono::Utc::now().naive_utc()));
+        }
+
+        while let Some(notification) = notifications.recv().await {
+            match self.decode(notification.payload()).await {
+                Ok(Some(event)) => connections.deliver_local(&event),
+                Ok(None) => {}
+                Err(e) => log::error!("Failed to read event from another node: {}", e),
+            }
+        }
+
+        driver.abort();
+        Err("Connection closed".to_string())
+    }
+
+    // Turns a notification into an event, or `None` for events this node published
+    async fn decode(&self, payload: &str) -> Result<Option<ClusterEvent>, String> {
+        let envelope: Envelope = serde_json::from_str(payload).map_err(|e| e.to_string())?;
+        if envelope.origin == self.node_id {
+            return Ok(None);
+        }
+        if let Some(event) = envelope.event {
+            return Ok(Some(event));
+        }
+
+        let payload_id = envelope.payload_id.ok_or("Notification without an event")?;
+        let client = self.db_pool.get().await.map_err(|e| format!("Error getting DB client: {}", e))?;
+        let row = client
+            .query_opt("SELECT payload FROM pubsub_payloads WHERE id = $1", &[&payload_id])
+            .await
+            .map_err(|e| format!("Error loading event payload: {}", e))?
+            .ok_or_else(|| format!("Event payload {} has expired", payload_id))?;
+        let payload: String = row.get(0);
+        serde_json::from_str(&payload).map_err(|e| e.to_string())
+    }
+}
+
+#[async_trait]
+impl PubSub for PostgresPubSub {
+    fn publish(&self, event: &ClusterEvent) -> Result<(), PubSubError> {
+        match self.outbox.try_send(event.clone()) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::SeqCst);
+                Err(PubSubError::Publish("Outbox full, event dropped".to_string()))
+            }
+            Err(e) => Err(PubSubError::Publish(e.to_string())),
+        }
+    }
+
+    async fn run(&self, connections: ConnectionManager) {
+        let mut lost_at = None;
+        loop {
+            if let Err(e) = self.listen(&connections, &mut lost_at).await {
+                log::error!("Lost the pub/sub connection: {}", e);
+            }
+            // Kept from the first failure until listening again
+            lost_at.get_or_insert_with(|| chrono::Utc::now().naive_utc());
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+}
+
+/// Creates the pub/sub backend selected by the configuration (`memory` or `postgres`)
+pub fn pubsub_from_config(config: &PubSubConfig, db_pool: Pool) -> Result<Arc<dyn PubSub>, PubSubError> {
+    match config.backend.as_str() {
+        "postgres" => {
+            let database_url = config
+                .database_url
+                .clone()
+                .ok_or_else(|| PubSubError::Config("DATABASE_URL is not set".to_string()))?;
+            Ok(Arc::new(PostgresPubSub::new(db_pool, database_url)))
+        }
+        "memory" => Ok(Arc::new(InProcessPubSub)),
+        other => Err(PubSubError::Config(format!("Unknown PUBSUB_BACKEND: {}", other))),
+    }
+}
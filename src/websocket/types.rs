@@ -5,8 +5,12 @@ use serde::{Deserialize, Serialize};
 use tokio_postgres::Client;
 use uuid::Uuid;
 
-use crate::models::invitation::InvitationNotification;
+use crate::models::{
+    block::BlockListUpdate, friend::FriendRequestNotification, invitation::InvitationNotification,
+    message::Message, profile::UserProfile,
+};
 
+use super::codec::WireFormat;
 use super::connection_manager::ConnectionManager;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -16,6 +20,14 @@ pub enum WebSocketMessage {
     Status(StatusMessage),
     Error(ErrorMessage),
     Invitation(InvitationNotification),
+    ProfileUpdated(UserProfile),
+    FriendRequest(FriendRequestNotification),
+    Presence(PresenceUpdate),
+    Gap(GapMessage),
+    Ack(AckMessage),
+    Welcome(WelcomeMessage),
+    GoingAway(GoingAwayMessage),
+    BlockListUpdated(BlockListUpdate),
 }
 
 
@@ -32,9 +44,108 @@ pub struct AppState {
 pub struct ChatMessage {
     pub message_id: Uuid,
     pub chat_id: Uuid,
-    pub sender_id: Uuid,
+    /// `None` once the author has deleted their account
+    pub sender_id: Option<Uuid>,
     pub content: String,
     pub timestamp: NaiveDateTime,
+    /// Sequence number of the message in its chat, to resume a connection with `last_seq`
+    pub seq: i64,
+    /// Id the sending client gave the message, so its other devices can match it
+    #[serde(default)]
+    pub client_id: Option<Uuid>,
+    /// Set per recipient when they have blocked the sender
+    #[serde(default)]
+    pub sender_blocked: bool,
+}
+
+impl From<Message> for ChatMessage {
+    fn from(message: Message) -> Self {
+        Self {
+            message_id: message.id,
+            chat_id: message.chat_id,
+            sender_id: message.sender_id,
+            content: message.message_text,
+            timestamp: message.timestamp,
+            seq: message.seq,
+            client_id: message.client_id,
+            sender_blocked: message.sender_blocked,
+        }
+    }
+}
+
+/// Control frames sent by clients as JSON, e.g. `{"type": "typing"}`
+///
+/// Any text frame that is not a control frame is a chat message.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// First frame of a connection: the protocol version and optional features the client speaks
+    Hello {
+        version: u32,
+        #[serde(default)]
+        features: Vec<String>,
+    },
+    /// The user is typing; repeat every few seconds while they keep typing
+    Typing,
+    /// A chat message the server acknowledges; resending the same `client_id` is safe
+    Send { client_id: Uuid, content: String },
+}
+
+/// Confirms to the sender that a message sent with a `client_id` was stored
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AckMessage {
+    pub client_id: Uuid,
+    pub message_id: Uuid,
+    pub chat_id: Uuid,
+    pub seq: i64,
+    pub timestamp: NaiveDateTime,
+}
+
+/// The server's answer to a hello
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WelcomeMessage {
+    /// Protocol version the server speaks
+    pub version: u32,
+    /// Oldest protocol version the server still accepts
+    pub min_version: u32,
+    /// Features of the hello the server supports too
+    pub features: Vec<String>,
+    pub limits: ProtocolLimits,
+    pub server_time: NaiveDateTime,
+    pub session: SessionInfo,
+}
+
+/// Limits that apply to the connection
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProtocolLimits {
+    /// Delay between two pings from the server, in seconds
+    pub ping_interval_seconds: u64,
+    /// Unanswered pings after which the server closes the connection
+    pub max_missed_pongs: u32,
+    /// Events buffered for the connection before it starts missing some
+    pub channel_capacity: usize,
+    /// Gaps within `lag_window_seconds` after which the server closes the connection
+    pub max_lag_events: usize,
+    pub lag_window_seconds: u64,
+}
+
+/// Who the connection is authenticated as
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SessionInfo {
+    pub user_id: Uuid,
+    pub username: String,
+    pub session_id: Option<Uuid>,
+    /// When the token the connection was opened with expires
+    pub expires_at: Option<NaiveDateTime>,
+    pub chat_id: Uuid,
+    pub format: WireFormat,
+}
+
+/// Sent to every client when the server shuts down, right before their connection closes
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GoingAwayMessage {
+    /// How long to wait before reconnecting, in milliseconds
+    pub reconnect_after_ms: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -50,7 +161,31 @@ pub struct StatusMessage {
     pub timestamp: NaiveDateTime,
 }
 
+/// Sent to a client that was too slow and missed messages of a chat
+///
+/// The missed chat messages were sent after `after` and before `before`
+/// (either bound is absent when unknown) and can be fetched from
+/// `GET /get_messages/:chat_id?after=...&before=...` or `?after_seq=...`.
 #[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GapMessage {
+    pub chat_id: Uuid,
+    /// Number of events dropped, including non-chat events such as status changes; 0 when unknown
+    pub missed: u64,
+    pub after: Option<NaiveDateTime>,
+    pub before: Option<NaiveDateTime>,
+    /// Sequence number of the last chat message delivered, for `?after_seq=...`
+    pub after_seq: Option<i64>,
+}
+
+/// A change of a user's presence across all of their connections
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PresenceUpdate {
+    pub user_id: Uuid,
+    pub status: UserStatus,
+    pub last_seen_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub enum UserStatus {
     Online,
     Offline,
@@ -63,4 +198,6 @@ pub enum UserStatus {
 pub struct ErrorMessage {
     pub code: String,
     pub message: String,
+    /// Id of the client frame that failed, when it had one
+    pub client_id: Option<Uuid>,
 }
\ No newline at end of file